@@ -25,9 +25,18 @@ pub struct Config {
     #[arg(long = "method", value_enum)]
     pub method: Method,
 
-    #[arg(long = "rpc", required = true)]
+    #[arg(
+        long = "rpc",
+        help = "RPC endpoint URL; replaces the endpoints of --config"
+    )]
     pub rpcs: Vec<String>,
 
+    #[arg(
+        long = "config",
+        help = "TOML config file (endpoints, retry, limits, chunk sizes); see indexer.example.toml"
+    )]
+    pub config: Option<String>,
+
     #[arg(long = "target-address", required_if_eq("method", "trace-filter"))]
     pub target_address: Option<String>,
 
@@ -75,14 +84,51 @@ pub struct Config {
     #[arg(long = "to")]
     pub to: Option<u64>,
 
-    #[arg(long = "chunk-size", default_value = "50")]
-    pub chunk_size: u64,
+    #[arg(
+        long = "chunk-size",
+        help = "Blocks per request [default: from --config, else 50]"
+    )]
+    pub chunk_size: Option<u64>,
+
+    #[arg(
+        long = "resume",
+        help = "Job name: checkpoint progress of trace-filter/get-logs scans and resume from it"
+    )]
+    pub resume: Option<String>,
+
+    #[arg(long = "checkpoint-dir", default_value = ".checkpoints")]
+    pub checkpoint_dir: String,
+
+    #[arg(
+        long = "cache-dir",
+        help = "Cache answers of finalized-block queries on disk in this directory"
+    )]
+    pub cache_dir: Option<String>,
+
+    #[arg(long = "cache-max-mb", default_value = "1024")]
+    pub cache_max_mb: u64,
+
+    #[arg(
+        long = "record",
+        help = "Write every RPC call and its answer to this NDJSON file"
+    )]
+    pub record: Option<String>,
+
+    #[arg(
+        long = "replay",
+        conflicts_with = "record",
+        help = "Answer RPC calls from a --record file instead of the network"
+    )]
+    pub replay: Option<String>,
 
     #[arg(long = "full")]
     pub full: bool,
 
-    #[arg(long = "parallel-requests-per-rpc", default_value = "5")]
-    pub parallel_requests_per_rpc: usize,
+    #[arg(
+        long = "parallel-requests-per-rpc",
+        help = "[default: from --config, else 5]"
+    )]
+    pub parallel_requests_per_rpc: Option<usize>,
 
     #[arg(
         long = "addresses",
@@ -108,3 +154,25 @@ pub struct Config {
     )]
     pub erc20_token_transfers: Option<String>,
 }
+
+impl Method {
+    /// The JSON-RPC method whose configured chunk size applies.
+    pub fn rpc_method(&self) -> &'static str {
+        match self {
+            Method::TraceFilter => "trace_filter",
+            Method::GetBlockByNumber => "eth_getBlockByNumber",
+            Method::GetTransactionByHash => "eth_getTransactionByHash",
+            Method::GetTransactionReceipt => "eth_getTransactionReceipt",
+            Method::GetBalance => "eth_getBalance",
+            Method::GetErc20Balance => "eth_call",
+            Method::GetLogs => "eth_getLogs",
+        }
+    }
+}
+
+impl Config {
+    /// Blocks per request; main fills it in from the config file if not given.
+    pub fn chunk_size(&self) -> u64 {
+        self.chunk_size.unwrap_or(50)
+    }
+}
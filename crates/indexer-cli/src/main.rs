@@ -1,6 +1,9 @@
-use alloy::transports::http::reqwest::Url;
 use clap::Parser;
-use indexer::{EthereumIndexer, ProviderPool, build_rpc_clients_with_retry};
+use indexer::{
+    EthereumIndexer, IndexerConfig, Recorder,
+    config::{CacheSection, EndpointSection},
+};
+use std::sync::Arc;
 use tracing::info;
 
 mod cli;
@@ -10,7 +13,7 @@ mod methods;
 async fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt::init();
 
-    let cfg = cli::Config::parse();
+    let mut cfg = cli::Config::parse();
 
     // Custom validation for method-specific required arguments
     match cfg.method {
@@ -82,27 +85,54 @@ async fn main() -> anyhow::Result<()> {
         }
     }
 
-    let urls: Vec<Url> = cfg
-        .rpcs
-        .iter()
-        .map(|u| u.parse())
-        .collect::<Result<_, _>>()?;
+    let mut file = match &cfg.config {
+        Some(path) => IndexerConfig::load(path)?,
+        None => IndexerConfig::default(),
+    };
+    if !cfg.rpcs.is_empty() {
+        file.endpoints = cfg.rpcs.iter().map(EndpointSection::new).collect();
+    }
+    if file.endpoints.is_empty() && cfg.replay.is_none() {
+        anyhow::bail!("no RPC endpoints: pass --rpc, --config or --replay");
+    }
+    let parallel_per_rpc = cfg
+        .parallel_requests_per_rpc
+        .or(file.parallel_per_rpc)
+        .unwrap_or(5);
+    file.parallel_per_rpc = Some(parallel_per_rpc);
+    cfg.chunk_size = cfg.chunk_size.or(file.chunk_size(cfg.method.rpc_method()));
+    if let Some(dir) = &cfg.cache_dir {
+        file.cache = Some(CacheSection {
+            dir: dir.clone(),
+            max_mb: cfg.cache_max_mb,
+        });
+    }
+    if let Some(cache) = &file.cache {
+        info!(
+            "Caching finalized responses in {} (max {} MB)",
+            cache.dir, cache.max_mb
+        );
+    }
 
-    // Build providers (same concrete type for all URLs)
-    let providers = build_rpc_clients_with_retry(urls.clone(), 10, 1000, 500);
-    let pool = ProviderPool::new(providers, cfg.parallel_requests_per_rpc);
-    let indexer = EthereumIndexer::new(pool, cfg.parallel_requests_per_rpc);
+    let mut builder = file.engine_builder()?;
+    if let Some(path) = &cfg.record {
+        info!("Recording RPC traffic to {}", path);
+        builder = builder.record(Arc::new(Recorder::create(path)?));
+    }
+    let indexer = match &cfg.replay {
+        Some(path) => {
+            info!("Replaying RPC traffic from {}", path);
+            builder.replay(path)?
+        }
+        // Providers are HTTP, WebSocket or IPC, picked per URL scheme
+        None => builder.connect().await?,
+    };
+    let rpc_count = indexer.endpoint_names().len();
 
     info!("Starting {:?} benchmark", cfg.method);
-    info!("RPCs: {}", urls.len());
-    info!(
-        "Parallel requests per RPC: {}",
-        cfg.parallel_requests_per_rpc
-    );
-    info!(
-        "Total parallel requests: {}",
-        urls.len() * cfg.parallel_requests_per_rpc
-    );
+    info!("RPCs: {}", rpc_count);
+    info!("Parallel requests per RPC: {}", parallel_per_rpc);
+    info!("Total parallel requests: {}", rpc_count * parallel_per_rpc);
 
     match cfg.method {
         cli::Method::TraceFilter => {
@@ -111,7 +141,7 @@ async fn main() -> anyhow::Result<()> {
                 "Blocks: {} to {} (chunk size: {})",
                 cfg.from.unwrap(),
                 cfg.to.unwrap(),
-                cfg.chunk_size
+                cfg.chunk_size()
             );
             info!("Target address: {}", target);
         }
@@ -125,7 +155,7 @@ async fn main() -> anyhow::Result<()> {
                     "Blocks: {} to {} (chunk size: {})",
                     cfg.from.unwrap(),
                     cfg.to.unwrap(),
-                    cfg.chunk_size
+                    cfg.chunk_size()
                 );
             }
             if cfg.full {
@@ -166,7 +196,7 @@ async fn main() -> anyhow::Result<()> {
                 "Blocks: {} to {} (chunk size: {})",
                 cfg.from.unwrap(),
                 cfg.to.unwrap(),
-                cfg.chunk_size
+                cfg.chunk_size()
             );
             if !cfg.addresses.is_empty() {
                 info!("Contract addresses: {:?}", cfg.addresses);
@@ -210,23 +240,44 @@ async fn main() -> anyhow::Result<()> {
         }
     }
 
-    print_rpc_stats(&urls, &indexer);
+    print_rpc_stats(&indexer);
 
     Ok(())
 }
 
-fn print_rpc_stats(urls: &[Url], indexer: &EthereumIndexer) {
+fn print_rpc_stats(indexer: &EthereumIndexer) {
     info!("=== RPC STATISTICS ===");
-    for (i, (url, s)) in urls.iter().zip(indexer.stats().iter()).enumerate() {
-        let (req, ok, avg) = s.snapshot();
-        let success = if req > 0 {
-            (ok as f64 / req as f64) * 100.0
-        } else {
-            0.0
-        };
+    let names = indexer.endpoint_names();
+    for (i, (url, s)) in names.iter().zip(indexer.stats().iter()).enumerate() {
+        let d = s.detailed();
         info!(
-            "RPC #{}: {} | {} requests | {:.1}% success | {:.0}ms avg latency",
-            i, url, req, success, avg
+            "RPC #{}: {} | {} requests | {:.1}% success | {:.0}ms avg latency | circuit {:?}",
+            i,
+            url,
+            d.requests,
+            success_rate(d.requests, d.successes),
+            d.latency.mean_ms,
+            d.circuit
         );
+        for m in &d.methods {
+            info!(
+                "  {}: {} requests | {:.1}% success | p50/p95/p99 {:.0}/{:.0}/{:.0}ms | errors: {}",
+                m.method,
+                m.requests,
+                success_rate(m.requests, m.successes),
+                m.latency.p50_ms,
+                m.latency.p95_ms,
+                m.latency.p99_ms,
+                m.errors
+            );
+        }
+    }
+}
+
+fn success_rate(requests: u64, successes: u64) -> f64 {
+    if requests > 0 {
+        (successes as f64 / requests as f64) * 100.0
+    } else {
+        0.0
     }
 }
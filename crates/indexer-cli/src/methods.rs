@@ -1,13 +1,14 @@
 use crate::cli;
 use alloy::primitives::{Address, B256};
 use alloy::rpc::types::eth::BlockNumberOrTag;
-use futures::StreamExt;
+use futures::{StreamExt, future::Either};
 use indexer::{
-    BlockByNumberBuilder, EthereumIndexer, GetLogsPlan, OnMiss, Range, TraceFilterBuilder,
-    TraceFilterPlan, TxByHashPlan, TxReceiptPlan,
+    BlockByNumberBuilder, CheckpointStore, EthereumIndexer, FileCheckpointStore, GetLogsPlan,
+    IndexerError, OnMiss, Range, TraceFilterBuilder, TxByHashPlan, TxReceiptPlan,
     api::eth::get_logs::{Erc20TokenTransfersBuilder, Erc20WalletTransfersBuilder, GetLogsBuilder},
-    balance_at_timestamp, erc20_balance_at_timestamp, order_by_range,
+    balance_at_timestamp, checkpointed, erc20_balance_at_timestamp,
 };
+use std::sync::Arc;
 use tracing::{error, info};
 
 pub async fn run_trace_filter(
@@ -24,47 +25,46 @@ pub async fn run_trace_filter(
         .target(target)
         .start_block(start_block)
         .end_block(end_block)
-        .chunk_size(cfg.chunk_size)
+        .chunk_size(cfg.chunk_size())
         .limits(1_000_000, 10_000)
         .plan()?;
 
-    let total_blocks = end_block - start_block + 1;
-    let work_items = plan.plan()?;
+    let resume = resume_job(&cfg)?;
+    let plan = match &resume {
+        Some((store, job)) => plan.resume(store.as_ref(), job)?,
+        None => plan,
+    };
+    if plan.range.from > plan.range.to {
+        info!(
+            "Nothing left to do: job already reached block {}",
+            plan.range.to
+        );
+        return Ok(());
+    }
 
-    let (completed_blocks, total_txns) = order_by_range(indexer.run(work_items), plan.range.from)
+    let total_blocks = plan.range.to - plan.range.from + 1;
+    let ordered = indexer.run_plan_ordered(&plan, plan.range.from)?;
+
+    let (completed_blocks, total_txns) = with_checkpoints(ordered, resume)
         .fold(
             (0u64, 0usize),
             |(mut completed_blocks, mut total_txns), res| async move {
                 match res {
-                    Ok((range, value)) => match TraceFilterPlan::decode(value) {
-                        Ok(traces) => {
-                            let n = traces
-                                .iter()
-                                .filter(|t| t.trace.trace_address.is_empty())
-                                .inspect(|t| {
-                                    if let Some(h) = &t.transaction_hash {
-                                        println!("{h}");
-                                    }
-                                })
-                                .count();
-
-                            total_txns += n;
-                            completed_blocks += range.to - range.from + 1;
-                            print_progress(
-                                range,
-                                n,
-                                completed_blocks,
-                                total_blocks,
-                                total_txns,
-                                start,
-                            );
-                        }
-                        Err(e) => {
-                            error!("Decode error for range {}-{}: {}", range.from, range.to, e);
-                            error!("Skipping malformed response to prevent data corruption");
-                            completed_blocks += range.to - range.from + 1;
-                        }
-                    },
+                    Ok((range, traces)) => {
+                        let n = traces
+                            .iter()
+                            .filter(|t| t.trace.trace_address.is_empty())
+                            .inspect(|t| {
+                                if let Some(h) = &t.transaction_hash {
+                                    println!("{h}");
+                                }
+                            })
+                            .count();
+
+                        total_txns += n;
+                        completed_blocks += range.to - range.from + 1;
+                        print_progress(range, n, completed_blocks, total_blocks, total_txns, start);
+                    }
                     Err(e) => {
                         error!("RPC error: {}", e);
                     }
@@ -78,6 +78,32 @@ pub async fn run_trace_filter(
     Ok(())
 }
 
+type ResumeJob = (Arc<dyn CheckpointStore>, String);
+
+fn resume_job(cfg: &cli::Config) -> anyhow::Result<Option<ResumeJob>> {
+    let Some(job) = cfg.resume.clone() else {
+        return Ok(None);
+    };
+    let store = FileCheckpointStore::new(&cfg.checkpoint_dir)?;
+    if let Some(done) = store.load(&job)? {
+        info!("Resuming job '{}' after block {}", job, done);
+    }
+    Ok(Some((Arc::new(store), job)))
+}
+
+fn with_checkpoints<S, T>(
+    ordered: S,
+    resume: Option<ResumeJob>,
+) -> impl futures::Stream<Item = Result<(Range, T), IndexerError>>
+where
+    S: futures::Stream<Item = Result<(Range, T), IndexerError>> + Unpin,
+{
+    match resume {
+        Some((store, job)) => Either::Left(checkpointed(ordered, store, job)),
+        None => Either::Right(ordered),
+    }
+}
+
 pub async fn run_get_block_by_number(
     cfg: cli::Config,
     indexer: &EthereumIndexer,
@@ -111,7 +137,6 @@ pub async fn run_get_block_by_number(
     };
 
     let plan = builder.plan()?;
-    let work = plan.plan()?;
 
     // Detect if all items are numeric (ordered) or any tag exists (unordered)
     let all_numeric = plan
@@ -120,50 +145,37 @@ pub async fn run_get_block_by_number(
         .all(|n| matches!(n, BlockNumberOrTag::Number(_)));
 
     let (completed_blocks, total_items) = if all_numeric {
-        let start_key = plan
-            .numbers
-            .iter()
-            .filter_map(|n| {
-                if let BlockNumberOrTag::Number(x) = n {
-                    Some(*x)
-                } else {
-                    None
-                }
-            })
-            .min()
-            .unwrap();
+        let start_key = plan.first_number().unwrap();
 
         // Ordered, parallel
-        order_by_range(indexer.run(work), start_key)
+        indexer
+            .run_plan_ordered(&plan, start_key)?
             .fold(
                 (0u64, 0usize),
                 |(mut completed_blocks, mut total_items), res| async move {
                     match res {
-                        Ok((range, value)) => match indexer::BlockByNumberPlan::decode(value) {
-                            Ok(Some(block)) => {
-                                total_items += 1;
-                                completed_blocks += 1;
-
-                                if completed_blocks % 10 == 0 {
-                                    let elapsed = start.elapsed().as_secs_f64();
-                                    let pct = completed_blocks as f64 / total_blocks as f64 * 100.0;
-                                    info!(
-                                        "Block {} | Range {}-{} | {}/{} ({:.1}%) | {:.0} blk/s",
-                                        block.header.number,
-                                        range.from,
-                                        range.to,
-                                        completed_blocks,
-                                        total_blocks,
-                                        pct,
-                                        completed_blocks as f64 / elapsed
-                                    );
-                                }
+                        Ok((range, Some(block))) => {
+                            total_items += 1;
+                            completed_blocks += 1;
+
+                            if completed_blocks % 10 == 0 {
+                                let elapsed = start.elapsed().as_secs_f64();
+                                let pct = completed_blocks as f64 / total_blocks as f64 * 100.0;
+                                info!(
+                                    "Block {} | Range {}-{} | {}/{} ({:.1}%) | {:.0} blk/s",
+                                    block.header.number,
+                                    range.from,
+                                    range.to,
+                                    completed_blocks,
+                                    total_blocks,
+                                    pct,
+                                    completed_blocks as f64 / elapsed
+                                );
                             }
-                            Ok(None) => {
-                                completed_blocks += 1;
-                            }
-                            Err(e) => error!("decode error: {}", e),
-                        },
+                        }
+                        Ok((_range, None)) => {
+                            completed_blocks += 1;
+                        }
                         Err(e) => error!("{}", e),
                     }
                     (completed_blocks, total_items)
@@ -173,34 +185,31 @@ pub async fn run_get_block_by_number(
     } else {
         // Unordered, parallel - process tags/mixed queries
         indexer
-            .run(work)
+            .run_plan(&plan)?
             .fold(
                 (0u64, 0usize),
                 |(mut completed_blocks, mut total_items), res| async move {
                     match res {
-                        Ok((_key, value)) => match indexer::BlockByNumberPlan::decode(value) {
-                            Ok(Some(block)) => {
-                                total_items += 1;
-                                completed_blocks += 1;
-
-                                if completed_blocks % 10 == 0 {
-                                    let elapsed = start.elapsed().as_secs_f64();
-                                    let pct = completed_blocks as f64 / total_blocks as f64 * 100.0;
-                                    info!(
-                                        "Block {} | {}/{} ({:.1}%) | {:.0} blk/s",
-                                        block.header.number,
-                                        completed_blocks,
-                                        total_blocks,
-                                        pct,
-                                        completed_blocks as f64 / elapsed
-                                    );
-                                }
-                            }
-                            Ok(None) => {
-                                completed_blocks += 1;
+                        Ok((_key, Some(block))) => {
+                            total_items += 1;
+                            completed_blocks += 1;
+
+                            if completed_blocks % 10 == 0 {
+                                let elapsed = start.elapsed().as_secs_f64();
+                                let pct = completed_blocks as f64 / total_blocks as f64 * 100.0;
+                                info!(
+                                    "Block {} | {}/{} ({:.1}%) | {:.0} blk/s",
+                                    block.header.number,
+                                    completed_blocks,
+                                    total_blocks,
+                                    pct,
+                                    completed_blocks as f64 / elapsed
+                                );
                             }
-                            Err(e) => error!("decode error: {}", e),
-                        },
+                        }
+                        Ok((_key, None)) => {
+                            completed_blocks += 1;
+                        }
                         Err(e) => error!("{}", e),
                     }
                     (completed_blocks, total_items)
@@ -222,25 +231,21 @@ pub async fn run_get_transaction_by_hash(
     let hashes = hashes?;
 
     let plan = TxByHashPlan { hashes };
-    let work_items = plan.plan()?;
 
     let (completed_items, found_items) = indexer
-        .run(work_items)
+        .run_plan(&plan)?
         .fold(
             (0usize, 0usize),
             |(mut completed_items, mut found_items), res| async move {
                 match res {
-                    Ok((_key, value)) => match TxByHashPlan::decode(value) {
-                        Ok(Some(tx)) => {
-                            found_items += 1;
-                            completed_items += 1;
-                            println!("{}", tx.inner.hash());
-                        }
-                        Ok(None) => {
-                            completed_items += 1;
-                        }
-                        Err(e) => error!("decode error: {}", e),
-                    },
+                    Ok((_key, Some(tx))) => {
+                        found_items += 1;
+                        completed_items += 1;
+                        println!("{}", tx.inner.hash());
+                    }
+                    Ok((_key, None)) => {
+                        completed_items += 1;
+                    }
                     Err(e) => error!("{}", e),
                 }
                 (completed_items, found_items)
@@ -261,25 +266,21 @@ pub async fn run_get_transaction_receipt(
     let hashes = hashes?;
 
     let plan = TxReceiptPlan { hashes };
-    let work_items = plan.plan()?;
 
     let (completed_items, found_items) = indexer
-        .run(work_items)
+        .run_plan(&plan)?
         .fold(
             (0usize, 0usize),
             |(mut completed_items, mut found_items), res| async move {
                 match res {
-                    Ok((_key, value)) => match TxReceiptPlan::decode(value) {
-                        Ok(Some(receipt)) => {
-                            found_items += 1;
-                            completed_items += 1;
-                            println!("{}", receipt.transaction_hash);
-                        }
-                        Ok(None) => {
-                            completed_items += 1;
-                        }
-                        Err(e) => error!("decode error: {}", e),
-                    },
+                    Ok((_key, Some(receipt))) => {
+                        found_items += 1;
+                        completed_items += 1;
+                        println!("{}", receipt.transaction_hash);
+                    }
+                    Ok((_key, None)) => {
+                        completed_items += 1;
+                    }
                     Err(e) => error!("{}", e),
                 }
                 (completed_items, found_items)
@@ -398,6 +399,11 @@ pub async fn run_get_logs(
     indexer: &EthereumIndexer,
     start: std::time::Instant,
 ) -> anyhow::Result<()> {
+    let erc20_mode = cfg.erc20_transfers_for.is_some() || cfg.erc20_token_transfers.is_some();
+    if erc20_mode && cfg.resume.is_some() {
+        anyhow::bail!("--resume is not supported with ERC-20 transfer modes");
+    }
+
     // Check which mode to use based on CLI parameters
     if let Some(wallet_address) = cfg.erc20_transfers_for.clone() {
         run_erc20_wallet_transfers(cfg, indexer, start, wallet_address).await
@@ -416,7 +422,7 @@ async fn run_general_logs(
     let start_block = cfg.from.unwrap();
     let end_block = cfg.to.unwrap();
 
-    let mut builder = GetLogsBuilder::new(start_block, end_block).chunk_size(cfg.chunk_size);
+    let mut builder = GetLogsBuilder::new(start_block, end_block).chunk_size(cfg.chunk_size());
 
     // Add address filters if provided
     if !cfg.addresses.is_empty() {
@@ -434,43 +440,54 @@ async fn run_general_logs(
     }
 
     let plan = builder.plan()?;
-    let total_blocks = end_block - start_block + 1;
-    let work_items = plan.plan()?;
 
-    let (completed_blocks, total_logs) = order_by_range(indexer.run(work_items), plan.range.from)
+    let resume = resume_job(&cfg)?;
+    let plan = match &resume {
+        Some((store, job)) => plan.resume(store.as_ref(), job)?,
+        None => plan,
+    };
+    if plan.range.from > plan.range.to {
+        info!(
+            "Nothing left to do: job already reached block {}",
+            plan.range.to
+        );
+        return Ok(());
+    }
+
+    let total_blocks = plan.range.to - plan.range.from + 1;
+    let ordered = indexer.run_plan_ordered(&plan, plan.range.from)?;
+
+    let (completed_blocks, total_logs) = with_checkpoints(ordered, resume)
         .fold(
             (0u64, 0usize),
             |(mut completed_blocks, mut total_logs), res| async move {
                 match res {
-                    Ok((range, value)) => match GetLogsPlan::decode(value) {
-                        Ok(logs) => {
-                            let log_count = logs.len();
-                            total_logs += log_count;
-                            completed_blocks += range.to - range.from + 1;
-
-                            // Print some logs for demonstration
-                            for log in logs.iter().take(5) {
-                                if let Some(tx_hash) = &log.transaction_hash {
-                                    println!(
-                                        "Log: tx={}, address={}, topics={}",
-                                        tx_hash,
-                                        log.address(),
-                                        log.topics().len()
-                                    );
-                                }
+                    Ok((range, logs)) => {
+                        let log_count = logs.len();
+                        total_logs += log_count;
+                        completed_blocks += range.to - range.from + 1;
+
+                        // Print some logs for demonstration
+                        for log in logs.iter().take(5) {
+                            if let Some(tx_hash) = &log.transaction_hash {
+                                println!(
+                                    "Log: tx={}, address={}, topics={}",
+                                    tx_hash,
+                                    log.address(),
+                                    log.topics().len()
+                                );
                             }
-
-                            print_progress(
-                                range,
-                                log_count,
-                                completed_blocks,
-                                total_blocks,
-                                total_logs,
-                                start,
-                            );
                         }
-                        Err(e) => error!("decode error: {}", e),
-                    },
+
+                        print_progress(
+                            range,
+                            log_count,
+                            completed_blocks,
+                            total_blocks,
+                            total_logs,
+                            start,
+                        );
+                    }
                     Err(e) => error!("{}", e),
                 }
                 (completed_blocks, total_logs)
@@ -508,7 +525,7 @@ async fn run_erc20_wallet_transfers(
 
     let mut builder =
         Erc20WalletTransfersBuilder::new(wallet, start_block, end_block, transfer_sig)
-            .chunk_size(cfg.chunk_size);
+            .chunk_size(cfg.chunk_size());
 
     // Add token filter if addresses provided
     if !cfg.addresses.is_empty() {
@@ -517,57 +534,55 @@ async fn run_erc20_wallet_transfers(
         builder = builder.tokens(token_addresses?);
     }
 
-    let (from_items, to_items, range) = builder.plan_split()?;
+    let (from_lane, to_lane) = builder.lane_plans()?;
     let total_blocks = end_block - start_block + 1;
 
     // Process each lane separately to avoid duplicate OrderingKey issues
-    let process_lane = |items: Vec<indexer::WorkItem>, lane_name: String| async move {
-        let (lane_blocks, lane_transfers) = order_by_range(indexer.run(items), range.from)
+    let process_lane = |plan: GetLogsPlan, lane_name: String| async move {
+        let (lane_blocks, lane_transfers) = indexer
+            .run_plan_ordered(&plan, plan.range.from)?
             .fold(
                 (0u64, 0usize),
                 |(mut lane_blocks, mut lane_transfers), res| {
                     let lane_name = lane_name.clone();
                     async move {
                         match res {
-                            Ok((range, value)) => match GetLogsPlan::decode(value) {
-                                Ok(logs) => {
-                                    let mut transfer_count = 0;
-                                    for log in logs {
-                                        // Use the contracts module for decoding
-                                        use indexer::contracts::erc20::decode_transfer_from_rpc;
-
-                                        if let Some(decoded) = decode_transfer_from_rpc(&log) {
-                                            transfer_count += 1;
-                                            if let Some(tx_hash) = &log.transaction_hash {
-                                                println!(
-                                                    "[{}] Transfer: {} -> {} ({}), token={}, tx={}",
-                                                    lane_name,
-                                                    decoded.from,
-                                                    decoded.to,
-                                                    decoded.value,
-                                                    log.address(),
-                                                    tx_hash
-                                                );
-                                            }
+                            Ok((range, logs)) => {
+                                let mut transfer_count = 0;
+                                for log in logs {
+                                    // Use the contracts module for decoding
+                                    use indexer::contracts::erc20::decode_transfer_from_rpc;
+
+                                    if let Some(decoded) = decode_transfer_from_rpc(&log) {
+                                        transfer_count += 1;
+                                        if let Some(tx_hash) = &log.transaction_hash {
+                                            println!(
+                                                "[{}] Transfer: {} -> {} ({}), token={}, tx={}",
+                                                lane_name,
+                                                decoded.from,
+                                                decoded.to,
+                                                decoded.value,
+                                                log.address(),
+                                                tx_hash
+                                            );
                                         }
                                     }
-
-                                    lane_transfers += transfer_count;
-                                    lane_blocks += range.to - range.from + 1;
-
-                                    // Show progress for this lane using standard format
-                                    print_progress_with_prefix(
-                                        &lane_name,
-                                        range,
-                                        transfer_count,
-                                        lane_blocks,
-                                        total_blocks,
-                                        lane_transfers,
-                                        start,
-                                    );
                                 }
-                                Err(e) => error!("[{}] decode error: {}", lane_name, e),
-                            },
+
+                                lane_transfers += transfer_count;
+                                lane_blocks += range.to - range.from + 1;
+
+                                // Show progress for this lane using standard format
+                                print_progress_with_prefix(
+                                    &lane_name,
+                                    range,
+                                    transfer_count,
+                                    lane_blocks,
+                                    total_blocks,
+                                    lane_transfers,
+                                    start,
+                                );
+                            }
                             Err(e) => error!("[{}] {}", lane_name, e),
                         }
                         (lane_blocks, lane_transfers)
@@ -581,8 +596,8 @@ async fn run_erc20_wallet_transfers(
 
     // Run both lanes concurrently
     let ((from_blocks, from_transfers), (to_blocks, to_transfers)) = tokio::try_join!(
-        process_lane(from_items, "FROM".to_string()),
-        process_lane(to_items, "TO".to_string())
+        process_lane(from_lane, "FROM".to_string()),
+        process_lane(to_lane, "TO".to_string())
     )?;
 
     let completed_blocks = from_blocks.max(to_blocks); // Both should be the same
@@ -617,53 +632,51 @@ async fn run_erc20_token_transfers(
     };
 
     let builder = Erc20TokenTransfersBuilder::new(token, start_block, end_block, transfer_sig)
-        .chunk_size(cfg.chunk_size);
+        .chunk_size(cfg.chunk_size());
 
-    let (work_items, range) = builder.plan()?;
+    let plan = builder.logs_plan()?;
     let total_blocks = end_block - start_block + 1;
 
     // Process as a single stream since it's all transfers of one token
-    let (completed_blocks, total_transfers) = order_by_range(indexer.run(work_items), range.from)
+    let (completed_blocks, total_transfers) = indexer
+        .run_plan_ordered(&plan, plan.range.from)?
         .fold(
             (0u64, 0usize),
             |(mut completed_blocks, mut total_transfers), res| async move {
                 match res {
-                    Ok((range, value)) => match GetLogsPlan::decode(value) {
-                        Ok(logs) => {
-                            let mut transfer_count = 0;
-                            for log in logs {
-                                // Use the contracts module for decoding
-                                use indexer::contracts::erc20::decode_transfer_from_rpc;
-
-                                if let Some(decoded) = decode_transfer_from_rpc(&log) {
-                                    transfer_count += 1;
-                                    if let Some(tx_hash) = &log.transaction_hash {
-                                        println!(
-                                            "Transfer: {} -> {} ({}), token={}, tx={}",
-                                            decoded.from,
-                                            decoded.to,
-                                            decoded.value,
-                                            log.address(),
-                                            tx_hash
-                                        );
-                                    }
+                    Ok((range, logs)) => {
+                        let mut transfer_count = 0;
+                        for log in logs {
+                            // Use the contracts module for decoding
+                            use indexer::contracts::erc20::decode_transfer_from_rpc;
+
+                            if let Some(decoded) = decode_transfer_from_rpc(&log) {
+                                transfer_count += 1;
+                                if let Some(tx_hash) = &log.transaction_hash {
+                                    println!(
+                                        "Transfer: {} -> {} ({}), token={}, tx={}",
+                                        decoded.from,
+                                        decoded.to,
+                                        decoded.value,
+                                        log.address(),
+                                        tx_hash
+                                    );
                                 }
                             }
-
-                            total_transfers += transfer_count;
-                            completed_blocks += range.to - range.from + 1;
-
-                            print_progress(
-                                range,
-                                transfer_count,
-                                completed_blocks,
-                                total_blocks,
-                                total_transfers,
-                                start,
-                            );
                         }
-                        Err(e) => error!("decode error: {}", e),
-                    },
+
+                        total_transfers += transfer_count;
+                        completed_blocks += range.to - range.from + 1;
+
+                        print_progress(
+                            range,
+                            transfer_count,
+                            completed_blocks,
+                            total_blocks,
+                            total_transfers,
+                            start,
+                        );
+                    }
                     Err(e) => error!("{}", e),
                 }
                 (completed_blocks, total_transfers)
@@ -734,7 +747,7 @@ fn days_since_unix_epoch(year: u32, month: u32, day: u32) -> anyhow::Result<u64>
 }
 
 fn is_leap_year(year: u32) -> bool {
-    (year % 4 == 0) && (year % 100 != 0 || year % 400 == 0)
+    year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400))
 }
 
 async fn determine_block_bounds(
@@ -742,12 +755,8 @@ async fn determine_block_bounds(
     _timestamp: u64,
     indexer: &EthereumIndexer,
 ) -> anyhow::Result<(u64, u64)> {
-    let lo = if let Some(lo) = cfg.block_range_lo {
-        lo
-    } else {
-        // Default to block 0 when no range provided - let binary search handle it
-        0
-    };
+    // Default to block 0 when no range provided - let binary search handle it
+    let lo = cfg.block_range_lo.unwrap_or(0);
 
     let hi = if let Some(hi) = cfg.block_range_hi {
         hi
@@ -755,18 +764,14 @@ async fn determine_block_bounds(
         // Default to latest block
         use indexer::BlockByNumberBuilder;
         let plan = BlockByNumberBuilder::new().latest().plan()?;
-        let work_items = plan.plan()?;
 
         // Get latest block number
         let mut latest_block_number = 0u64;
-        let mut stream = indexer.run(work_items);
+        let mut stream = indexer.run_plan(&plan)?;
         if let Some(result) = stream.next().await {
             match result {
-                Ok((_key, value)) => {
-                    if let Ok(Some(block)) = indexer::BlockByNumberPlan::decode(value) {
-                        latest_block_number = block.header.number;
-                    }
-                }
+                Ok((_key, Some(block))) => latest_block_number = block.header.number,
+                Ok((_key, None)) => {}
                 Err(e) => {
                     anyhow::bail!("Failed to get latest block: {}", e);
                 }
@@ -1,3 +1,4 @@
+use super::status_for;
 use crate::types::{BalanceQuery, BalanceResponse, Erc20BalanceResponse};
 use axum::{
     extract::{Path, Query, State},
@@ -72,7 +73,7 @@ pub async fn get_balance_at_date(
         }
         Err(e) => {
             warn!("Balance query error: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            Err(status_for(&e))
         }
     }
 }
@@ -174,7 +175,7 @@ fn days_since_unix_epoch(year: u32, month: u32, day: u32) -> Result<u64, StatusC
 }
 
 fn is_leap_year(year: u32) -> bool {
-    (year % 4 == 0) && (year % 100 != 0 || year % 400 == 0)
+    year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400))
 }
 
 /// Validate Unix timestamp is reasonable (now unused but kept for reference)
@@ -223,28 +224,24 @@ async fn determine_block_bounds(
         use indexer::BlockByNumberBuilder;
         let plan = BlockByNumberBuilder::new().latest().plan().map_err(|e| {
             warn!("Failed to create latest block plan: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
-        let work_items = plan.plan().map_err(|e| {
-            warn!("Failed to create latest block work items: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
+            status_for(&e)
         })?;
 
         // Get latest block number
         let mut latest_block_number = 0u64;
-        let mut stream = engine.run(work_items);
+        let mut stream = engine.run_plan(&plan).map_err(|e| {
+            warn!("Failed to create latest block work items: {}", e);
+            status_for(&e)
+        })?;
 
         use futures::StreamExt;
         if let Some(result) = stream.next().await {
             match result {
-                Ok((_key, value)) => {
-                    if let Ok(Some(block)) = indexer::BlockByNumberPlan::decode(value) {
-                        latest_block_number = block.header.number;
-                    }
-                }
+                Ok((_key, Some(block))) => latest_block_number = block.header.number,
+                Ok((_key, None)) => {}
                 Err(e) => {
                     warn!("Failed to get latest block: {}", e);
-                    return Err(StatusCode::INTERNAL_SERVER_ERROR);
+                    return Err(status_for(&e));
                 }
             }
         }
@@ -322,7 +319,7 @@ pub async fn get_erc20_balance_at_date(
         }
         Err(e) => {
             warn!("ERC-20 balance query error: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            Err(status_for(&e))
         }
     }
 }
@@ -1,16 +1,19 @@
+use super::status_for;
 use crate::types::BlockByNumberQuery;
 use axum::{
+    Extension,
     extract::{Path, Query, State},
     http::StatusCode,
     response::Json,
 };
 use futures::StreamExt;
-use indexer::{BlockByNumberBuilder, BlockByNumberPlan, EthereumIndexer};
+use indexer::{BlockByNumberBuilder, EthereumIndexer, IndexerConfig};
 use std::sync::Arc;
 use tracing::info;
 
 pub async fn get_block_by_number(
     State(engine): State<Arc<EthereumIndexer>>,
+    Extension(config): Extension<Arc<IndexerConfig>>,
     Path(number): Path<String>,
     Query(params): Query<BlockByNumberQuery>,
 ) -> Result<Json<serde_json::Value>, StatusCode> {
@@ -33,8 +36,9 @@ pub async fn get_block_by_number(
 
         // Safety check for large ranges
         let range_size = to - from + 1;
-        if range_size > 1000 {
-            info!("Range too large: {} blocks (max 1000)", range_size);
+        let max_range = config.server.max_block_range;
+        if range_size > max_range {
+            info!("Range too large: {} blocks (max {})", range_size, max_range);
             return Err(StatusCode::BAD_REQUEST);
         }
 
@@ -61,7 +65,7 @@ pub async fn get_block_by_number(
     }
 
     // Build the plan
-    let plan = match builder.limit(1000).plan() {
+    let plan = match builder.limit(config.server.max_block_range as usize).plan() {
         Ok(p) => p,
         Err(e) => {
             info!("Plan creation failed: {}", e);
@@ -70,37 +74,27 @@ pub async fn get_block_by_number(
     };
 
     // Execute the plan
-    let work_items = match plan.plan() {
-        Ok(items) => items,
+    let mut stream = match engine.run_plan(&plan) {
+        Ok(s) => s,
         Err(e) => {
             info!("Work item creation failed: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            return Err(status_for(&e));
         }
     };
-
-    let mut stream = engine.run(work_items);
     let mut results = Vec::new();
 
     // Collect all results
     while let Some(result) = stream.next().await {
         match result {
-            Ok((_key, data)) => {
-                match BlockByNumberPlan::decode(data) {
-                    Ok(Some(block)) => {
-                        results.push(serde_json::to_value(block).unwrap());
-                    }
-                    Ok(None) => {
-                        // Block not found, continue (some blocks in range might not exist)
-                    }
-                    Err(e) => {
-                        info!("Decode error: {}", e);
-                        return Err(StatusCode::INTERNAL_SERVER_ERROR);
-                    }
-                }
+            Ok((_key, Some(block))) => {
+                results.push(serde_json::to_value(block).unwrap());
+            }
+            Ok((_key, None)) => {
+                // Block not found, continue (some blocks in range might not exist)
             }
             Err(e) => {
                 info!("Stream error: {}", e);
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+                return Err(status_for(&e));
             }
         }
     }
@@ -1,21 +1,23 @@
+use super::status_for;
 use crate::types::{Erc20TokenQuery, Erc20WalletQuery, GetLogsQuery, LogsResponse};
 use alloy::{primitives::Address, sol_types::SolEvent};
 use axum::{
+    Extension,
     extract::{Path, Query, State},
     http::StatusCode,
     response::Json,
 };
 use futures::StreamExt;
 use indexer::{
-    EthereumIndexer, GetLogsPlan,
+    EthereumIndexer, GetLogsPlan, IndexerConfig, IndexerError,
     api::eth::get_logs::{Erc20TokenTransfersBuilder, Erc20WalletTransfersBuilder, GetLogsBuilder},
-    order_by_range,
 };
 use std::sync::Arc;
 use tracing::info;
 
 pub async fn get_logs_general(
     State(engine): State<Arc<EthereumIndexer>>,
+    Extension(config): Extension<Arc<IndexerConfig>>,
     Query(params): Query<GetLogsQuery>,
 ) -> Result<Json<LogsResponse>, StatusCode> {
     let from_block = params.from.ok_or(StatusCode::BAD_REQUEST)?;
@@ -26,10 +28,10 @@ pub async fn get_logs_general(
         from_block, to_block, params.addresses
     );
 
-    validate_block_range(from_block, to_block)?;
+    validate_block_range(from_block, to_block, config.server.max_log_range)?;
+    let chunk_size = chunk_size(&config, params.chunk_size);
 
-    let mut builder =
-        GetLogsBuilder::new(from_block, to_block).chunk_size(params.chunk_size.unwrap_or(1000));
+    let mut builder = GetLogsBuilder::new(from_block, to_block).chunk_size(chunk_size);
 
     // Add address filters if provided
     if !params.addresses.is_empty() {
@@ -48,21 +50,13 @@ pub async fn get_logs_general(
     }
 
     let plan = builder.plan().map_err(|_| StatusCode::BAD_REQUEST)?;
-    let work_items = plan.plan().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    execute_logs_plan(
-        engine,
-        work_items,
-        plan.range.from,
-        from_block,
-        to_block,
-        params.chunk_size.unwrap_or(1000),
-    )
-    .await
+
+    execute_logs_plan(engine, &plan, from_block, to_block, chunk_size).await
 }
 
 pub async fn get_logs_erc20_wallet(
     State(engine): State<Arc<EthereumIndexer>>,
+    Extension(config): Extension<Arc<IndexerConfig>>,
     Path(wallet_address): Path<String>,
     Query(params): Query<Erc20WalletQuery>,
 ) -> Result<Json<LogsResponse>, StatusCode> {
@@ -77,13 +71,14 @@ pub async fn get_logs_erc20_wallet(
         wallet, from_block, to_block
     );
 
-    validate_block_range(from_block, to_block)?;
+    validate_block_range(from_block, to_block, config.server.max_log_range)?;
+    let chunk_size = chunk_size(&config, params.chunk_size);
 
     // Get ERC-20 Transfer signature
     let transfer_sig = get_transfer_signature();
 
     let mut builder = Erc20WalletTransfersBuilder::new(wallet, from_block, to_block, transfer_sig)
-        .chunk_size(params.chunk_size.unwrap_or(1000));
+        .chunk_size(chunk_size);
 
     // Add token filter if provided
     if !params.tokens.is_empty() {
@@ -92,40 +87,43 @@ pub async fn get_logs_erc20_wallet(
         builder = builder.tokens(token_addresses.map_err(|_| StatusCode::BAD_REQUEST)?);
     }
 
-    let (from_items, to_items, range) =
-        builder.plan_split().map_err(|_| StatusCode::BAD_REQUEST)?;
+    let (from_lane, to_lane) = builder.lane_plans().map_err(|_| StatusCode::BAD_REQUEST)?;
 
     // Process both lanes concurrently
     let engine_clone = engine.clone();
     let from_future = async move {
         let mut results = Vec::new();
-        let stream = order_by_range(engine.run(from_items), range.from);
+        let stream = engine
+            .run_plan_ordered(&from_lane, from_lane.range.from)
+            .map_err(|e| status_for(&e))?;
         tokio::pin!(stream);
 
         while let Some(item) = stream.next().await {
             match item {
-                Ok((_range, data)) => {
-                    if let Ok(logs) = GetLogsPlan::decode(data) {
-                        for log in logs {
-                            if let Some(decoded) = decode_transfer_log(&log) {
-                                results.push(serde_json::json!({
-                                    "type": "Transfer",
-                                    "lane": "FROM",
-                                    "from": decoded.from,
-                                    "to": decoded.to,
-                                    "value": decoded.value.to_string(),
-                                    "token": log.address(),
-                                    "transaction_hash": log.transaction_hash,
-                                    "block_number": log.block_number,
-                                    "log_index": log.log_index
-                                }));
-                            }
+                Ok((_range, logs)) => {
+                    for log in logs {
+                        if let Some(decoded) = decode_transfer_log(&log) {
+                            results.push(serde_json::json!({
+                                "type": "Transfer",
+                                "lane": "FROM",
+                                "from": decoded.from,
+                                "to": decoded.to,
+                                "value": decoded.value.to_string(),
+                                "token": log.address(),
+                                "transaction_hash": log.transaction_hash,
+                                "block_number": log.block_number,
+                                "log_index": log.log_index
+                            }));
                         }
                     }
                 }
+                // a malformed answer loses only its range
+                Err(e @ IndexerError::Decode { .. }) => {
+                    info!("FROM: {}, skipping malformed response", e);
+                }
                 Err(e) => {
                     info!("Stream error in FROM: {}", e);
-                    return Err(StatusCode::INTERNAL_SERVER_ERROR);
+                    return Err(status_for(&e));
                 }
             }
         }
@@ -134,33 +132,37 @@ pub async fn get_logs_erc20_wallet(
 
     let to_future = async move {
         let mut results = Vec::new();
-        let stream = order_by_range(engine_clone.run(to_items), range.from);
+        let stream = engine_clone
+            .run_plan_ordered(&to_lane, to_lane.range.from)
+            .map_err(|e| status_for(&e))?;
         tokio::pin!(stream);
 
         while let Some(item) = stream.next().await {
             match item {
-                Ok((_range, data)) => {
-                    if let Ok(logs) = GetLogsPlan::decode(data) {
-                        for log in logs {
-                            if let Some(decoded) = decode_transfer_log(&log) {
-                                results.push(serde_json::json!({
-                                    "type": "Transfer",
-                                    "lane": "TO",
-                                    "from": decoded.from,
-                                    "to": decoded.to,
-                                    "value": decoded.value.to_string(),
-                                    "token": log.address(),
-                                    "transaction_hash": log.transaction_hash,
-                                    "block_number": log.block_number,
-                                    "log_index": log.log_index
-                                }));
-                            }
+                Ok((_range, logs)) => {
+                    for log in logs {
+                        if let Some(decoded) = decode_transfer_log(&log) {
+                            results.push(serde_json::json!({
+                                "type": "Transfer",
+                                "lane": "TO",
+                                "from": decoded.from,
+                                "to": decoded.to,
+                                "value": decoded.value.to_string(),
+                                "token": log.address(),
+                                "transaction_hash": log.transaction_hash,
+                                "block_number": log.block_number,
+                                "log_index": log.log_index
+                            }));
                         }
                     }
                 }
+                // a malformed answer loses only its range
+                Err(e @ IndexerError::Decode { .. }) => {
+                    info!("TO: {}, skipping malformed response", e);
+                }
                 Err(e) => {
                     info!("Stream error in TO: {}", e);
-                    return Err(StatusCode::INTERNAL_SERVER_ERROR);
+                    return Err(status_for(&e));
                 }
             }
         }
@@ -179,7 +181,7 @@ pub async fn get_logs_erc20_wallet(
             "from_block": from_block,
             "to_block": to_block,
             "total_logs": total_logs,
-            "chunk_size": params.chunk_size.unwrap_or(1000),
+            "chunk_size": chunk_size,
             "transfer_type": "wallet"
         }),
     }))
@@ -187,6 +189,7 @@ pub async fn get_logs_erc20_wallet(
 
 pub async fn get_logs_erc20_token(
     State(engine): State<Arc<EthereumIndexer>>,
+    Extension(config): Extension<Arc<IndexerConfig>>,
     Path(token_address): Path<String>,
     Query(params): Query<Erc20TokenQuery>,
 ) -> Result<Json<LogsResponse>, StatusCode> {
@@ -199,43 +202,48 @@ pub async fn get_logs_erc20_token(
         token, from_block, to_block
     );
 
-    validate_block_range(from_block, to_block)?;
+    validate_block_range(from_block, to_block, config.server.max_log_range)?;
+    let chunk_size = chunk_size(&config, params.chunk_size);
 
     // Get ERC-20 Transfer signature
     let transfer_sig = get_transfer_signature();
 
     let builder = Erc20TokenTransfersBuilder::new(token, from_block, to_block, transfer_sig)
-        .chunk_size(params.chunk_size.unwrap_or(1000));
+        .chunk_size(chunk_size);
 
-    let (work_items, range) = builder.plan().map_err(|_| StatusCode::BAD_REQUEST)?;
+    let plan = builder.logs_plan().map_err(|_| StatusCode::BAD_REQUEST)?;
 
-    let stream = order_by_range(engine.run(work_items), range.from);
+    let stream = engine
+        .run_plan_ordered(&plan, plan.range.from)
+        .map_err(|e| status_for(&e))?;
     let mut results = Vec::new();
 
     tokio::pin!(stream);
     while let Some(item) = stream.next().await {
         match item {
-            Ok((_range, data)) => {
-                if let Ok(logs) = GetLogsPlan::decode(data) {
-                    for log in logs {
-                        if let Some(decoded) = decode_transfer_log(&log) {
-                            results.push(serde_json::json!({
-                                "type": "Transfer",
-                                "from": decoded.from,
-                                "to": decoded.to,
-                                "value": decoded.value.to_string(),
-                                "token": log.address(),
-                                "transaction_hash": log.transaction_hash,
-                                "block_number": log.block_number,
-                                "log_index": log.log_index
-                            }));
-                        }
+            Ok((_range, logs)) => {
+                for log in logs {
+                    if let Some(decoded) = decode_transfer_log(&log) {
+                        results.push(serde_json::json!({
+                            "type": "Transfer",
+                            "from": decoded.from,
+                            "to": decoded.to,
+                            "value": decoded.value.to_string(),
+                            "token": log.address(),
+                            "transaction_hash": log.transaction_hash,
+                            "block_number": log.block_number,
+                            "log_index": log.log_index
+                        }));
                     }
                 }
             }
+            // a malformed answer loses only its range
+            Err(e @ IndexerError::Decode { .. }) => {
+                info!("{}, skipping malformed response", e);
+            }
             Err(e) => {
                 info!("Stream error: {}", e);
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+                return Err(status_for(&e));
             }
         }
     }
@@ -247,7 +255,7 @@ pub async fn get_logs_erc20_token(
             "from_block": from_block,
             "to_block": to_block,
             "total_logs": total_logs,
-            "chunk_size": params.chunk_size.unwrap_or(1000),
+            "chunk_size": chunk_size,
             "transfer_type": "token"
         }),
     }))
@@ -257,38 +265,41 @@ pub async fn get_logs_erc20_token(
 
 async fn execute_logs_plan(
     engine: Arc<EthereumIndexer>,
-    work_items: Vec<indexer::WorkItem>,
-    start_key: u64,
+    plan: &GetLogsPlan,
     from_block: u64,
     to_block: u64,
     chunk_size: u64,
 ) -> Result<Json<LogsResponse>, StatusCode> {
-    let stream = order_by_range(engine.run(work_items), start_key);
+    let stream = engine
+        .run_plan_ordered(plan, plan.range.from)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     let mut results = Vec::new();
 
     tokio::pin!(stream);
     while let Some(item) = stream.next().await {
         match item {
-            Ok((_range, data)) => {
-                if let Ok(logs) = GetLogsPlan::decode(data) {
-                    for log in logs {
-                        results.push(serde_json::json!({
-                            "address": log.address(),
-                            "topics": log.topics(),
-                            "data": log.data().data,
-                            "transaction_hash": log.transaction_hash,
-                            "block_number": log.block_number,
-                            "block_hash": log.block_hash,
-                            "log_index": log.log_index,
-                            "transaction_index": log.transaction_index,
-                            "removed": log.removed
-                        }));
-                    }
+            Ok((_range, logs)) => {
+                for log in logs {
+                    results.push(serde_json::json!({
+                        "address": log.address(),
+                        "topics": log.topics(),
+                        "data": log.data().data,
+                        "transaction_hash": log.transaction_hash,
+                        "block_number": log.block_number,
+                        "block_hash": log.block_hash,
+                        "log_index": log.log_index,
+                        "transaction_index": log.transaction_index,
+                        "removed": log.removed
+                    }));
                 }
             }
+            // a malformed answer loses only its range
+            Err(e @ IndexerError::Decode { .. }) => {
+                info!("{}, skipping malformed response", e);
+            }
             Err(e) => {
                 info!("Stream error: {}", e);
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+                return Err(status_for(&e));
             }
         }
     }
@@ -305,15 +316,22 @@ async fn execute_logs_plan(
     }))
 }
 
-fn validate_block_range(from_block: u64, to_block: u64) -> Result<(), StatusCode> {
+/// `?chunk_size=`, else the configured `eth_getLogs` chunk size, else 1000.
+fn chunk_size(config: &IndexerConfig, requested: Option<u64>) -> u64 {
+    requested
+        .or(config.chunk_size("eth_getLogs"))
+        .unwrap_or(1000)
+}
+
+fn validate_block_range(from_block: u64, to_block: u64, max_range: u64) -> Result<(), StatusCode> {
     if to_block < from_block {
         info!("Invalid range: to < from ({} < {})", to_block, from_block);
         return Err(StatusCode::BAD_REQUEST);
     }
 
     let range_size = to_block - from_block + 1;
-    if range_size > 100_000 {
-        info!("Range too large: {} blocks (max 10,000)", range_size);
+    if range_size > max_range {
+        info!("Range too large: {} blocks (max {})", range_size, max_range);
         return Err(StatusCode::BAD_REQUEST);
     }
 
@@ -0,0 +1,18 @@
+use crate::metrics::{HttpMetrics, render};
+use axum::{
+    extract::{Extension, State},
+    http::header,
+    response::IntoResponse,
+};
+use indexer::EthereumIndexer;
+use std::sync::Arc;
+
+pub async fn prometheus_metrics(
+    State(engine): State<Arc<EthereumIndexer>>,
+    Extension(http): Extension<Arc<HttpMetrics>>,
+) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        render(&engine, &http),
+    )
+}
@@ -1,6 +1,7 @@
 pub mod balance;
 pub mod block;
 pub mod logs;
+pub mod metrics;
 pub mod ping;
 pub mod receipt;
 pub mod rpc_info;
@@ -10,8 +11,37 @@ pub mod transaction;
 pub use balance::*;
 pub use block::*;
 pub use logs::*;
+pub use metrics::*;
 pub use ping::*;
 pub use receipt::*;
 pub use rpc_info::*;
 pub use trace_filter::*;
 pub use transaction::*;
+
+use axum::http::StatusCode;
+use indexer::IndexerError;
+
+/// HTTP status for a failed engine call: caller mistakes are 4xx, upstream
+/// node trouble is 5xx.
+pub(crate) fn status_for(e: &IndexerError) -> StatusCode {
+    match e {
+        IndexerError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+        IndexerError::Rpc { code: -32602, .. } => StatusCode::BAD_REQUEST,
+        IndexerError::RangeMiss(_) => StatusCode::NOT_FOUND,
+        IndexerError::NoEndpoint => StatusCode::SERVICE_UNAVAILABLE,
+        IndexerError::Unsupported(_) => StatusCode::NOT_IMPLEMENTED,
+        IndexerError::Checkpoint(_) | IndexerError::Config(_) | IndexerError::Io(_) => {
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+        IndexerError::Timeout { .. } | IndexerError::DeadlineExceeded { .. } => {
+            StatusCode::GATEWAY_TIMEOUT
+        }
+        IndexerError::Rpc { .. }
+        | IndexerError::Transport { .. }
+        | IndexerError::Decode { .. }
+        | IndexerError::Missing { .. }
+        | IndexerError::Divergence { .. }
+        | IndexerError::BlockNotFound(_)
+        | IndexerError::ChainMismatch { .. } => StatusCode::BAD_GATEWAY,
+    }
+}
@@ -1,20 +1,20 @@
 use crate::types::RpcInfoResponse;
-use axum::response::Json;
+use axum::{Extension, extract::State, response::Json};
+use indexer::{CapabilityReport, EthereumIndexer, IndexerConfig};
+use std::sync::Arc;
 
-pub async fn rpc_info() -> Json<RpcInfoResponse> {
-    let rpc_urls: Vec<String> = std::env::var("RPC_URLS")
-        .unwrap_or_else(|_| "https://eth.drpc.org".to_string())
-        .split(',')
-        .map(|url| url.trim().to_string())
-        .collect();
-
-    let parallel_per_rpc = std::env::var("PARALLEL_PER_RPC")
-        .ok()
-        .and_then(|s| s.parse::<usize>().ok())
-        .unwrap_or(8); // Match the default from main.rs
+pub async fn rpc_info(
+    State(engine): State<Arc<EthereumIndexer>>,
+    Extension(capabilities): Extension<Arc<CapabilityReport>>,
+    Extension(config): Extension<Arc<IndexerConfig>>,
+) -> Json<RpcInfoResponse> {
+    // names or redacted URLs: the configured URLs may carry API keys
+    let rpc_urls = engine.endpoint_names().to_vec();
 
     Json(RpcInfoResponse {
         rpc_urls,
-        parallel_per_rpc,
+        parallel_per_rpc: config.parallel_per_rpc.unwrap_or_default(),
+        chain_id: capabilities.chain_id,
+        capabilities: capabilities.endpoints.clone(),
     })
 }
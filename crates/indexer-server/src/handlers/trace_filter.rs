@@ -1,31 +1,36 @@
+use super::status_for;
 use crate::types::TraceFilterQuery;
 use axum::{
+    Extension,
     extract::{Path, Query, State},
     http::StatusCode,
     response::Json,
 };
 use futures::StreamExt;
-use indexer::{EthereumIndexer, TraceFilterBuilder, TraceFilterPlan, order_by_range};
+use indexer::{EthereumIndexer, IndexerConfig, IndexerError, TraceFilterBuilder};
 use std::sync::Arc;
 use tracing::info;
 
 pub async fn trace_filter_no_address(
     State(engine): State<Arc<EthereumIndexer>>,
+    Extension(config): Extension<Arc<IndexerConfig>>,
     Query(params): Query<TraceFilterQuery>,
 ) -> Result<Json<serde_json::Value>, StatusCode> {
-    trace_filter_impl(engine, None, params).await
+    trace_filter_impl(engine, &config, None, params).await
 }
 
 pub async fn trace_filter_with_address(
     State(engine): State<Arc<EthereumIndexer>>,
+    Extension(config): Extension<Arc<IndexerConfig>>,
     Path(address): Path<String>,
     Query(params): Query<TraceFilterQuery>,
 ) -> Result<Json<serde_json::Value>, StatusCode> {
-    trace_filter_impl(engine, Some(address), params).await
+    trace_filter_impl(engine, &config, Some(address), params).await
 }
 
 async fn trace_filter_impl(
     engine: Arc<EthereumIndexer>,
+    config: &IndexerConfig,
     address: Option<String>,
     params: TraceFilterQuery,
 ) -> Result<Json<serde_json::Value>, StatusCode> {
@@ -44,8 +49,8 @@ async fn trace_filter_impl(
     let mut builder = TraceFilterBuilder::new()
         .start_block(start_block)
         .end_block(end_block)
-        .chunk_size(3_000)
-        .limits(100_000, 10_000);
+        .chunk_size(config.chunk_size("trace_filter").unwrap_or(3_000))
+        .limits(config.server.max_trace_span, config.server.max_trace_chunk);
 
     if let Some(addr_str) = address {
         match addr_str.parse() {
@@ -67,66 +72,54 @@ async fn trace_filter_impl(
         }
     };
 
-    let work_items = match plan.plan() {
-        Ok(items) => items,
+    let stream = match engine.run_plan_ordered(&plan, plan.range.from) {
+        Ok(s) => s,
         Err(e) => {
             info!("Work item creation failed: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            return Err(status_for(&e));
         }
     };
 
-    // Configure response size limits
-    let max_results = std::env::var("MAX_TRACE_RESULTS")
-        .ok()
-        .and_then(|s| s.parse::<usize>().ok())
-        .unwrap_or(10_000); // Default limit of 10,000 traces
+    let max_results = config.server.max_trace_results;
 
-    let stream = order_by_range(engine.run(work_items), plan.range.from);
     let mut results = Vec::new();
     let mut total_processed = 0;
 
     tokio::pin!(stream);
     while let Some(item) = stream.next().await {
         match item {
-            Ok((range, data)) => {
-                match TraceFilterPlan::decode(data) {
-                    Ok(traces) => {
-                        // Filter for non-internal transactions only
-                        let filtered_traces: Vec<_> = traces
-                            .into_iter()
-                            .filter(|t| t.trace.trace_address.is_empty())
-                            .collect();
-
-                        // Check if adding these results would exceed our limit
-                        if results.len() + filtered_traces.len() > max_results {
-                            let remaining = max_results - results.len();
-                            if remaining > 0 {
-                                results.extend(filtered_traces.into_iter().take(remaining));
-                            }
-
-                            info!(
-                                "Result limit reached: {} traces (limit: {}). Truncating response.",
-                                results.len(),
-                                max_results
-                            );
-                            break;
-                        }
-
-                        results.extend(filtered_traces);
-                        total_processed += 1;
-                    }
-                    Err(e) => {
-                        info!(
-                            "Decode error for range {}-{}: {}, skipping malformed response",
-                            range.from, range.to, e
-                        );
-                        // Continue processing other ranges instead of silent failure
+            Ok((_range, traces)) => {
+                // Filter for non-internal transactions only
+                let filtered_traces: Vec<_> = traces
+                    .into_iter()
+                    .filter(|t| t.trace.trace_address.is_empty())
+                    .collect();
+
+                // Check if adding these results would exceed our limit
+                if results.len() + filtered_traces.len() > max_results {
+                    let remaining = max_results - results.len();
+                    if remaining > 0 {
+                        results.extend(filtered_traces.into_iter().take(remaining));
                     }
+
+                    info!(
+                        "Result limit reached: {} traces (limit: {}). Truncating response.",
+                        results.len(),
+                        max_results
+                    );
+                    break;
                 }
+
+                results.extend(filtered_traces);
+                total_processed += 1;
+            }
+            // a malformed answer loses only its range
+            Err(e @ IndexerError::Decode { .. }) => {
+                info!("{}, skipping malformed response", e);
             }
             Err(e) => {
                 info!("Stream error: {}", e);
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+                return Err(status_for(&e));
             }
         }
     }
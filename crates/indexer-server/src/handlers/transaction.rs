@@ -1,3 +1,4 @@
+use super::status_for;
 use alloy::primitives::B256;
 use axum::{
     extract::{Path, State},
@@ -5,7 +6,7 @@ use axum::{
     response::Json,
 };
 use futures::StreamExt;
-use indexer::{EthereumIndexer, TxByHashBuilder, TxByHashPlan};
+use indexer::{EthereumIndexer, TxByHashBuilder};
 use std::sync::Arc;
 use tracing::info;
 
@@ -31,35 +32,27 @@ pub async fn get_transaction_by_hash(
         }
     };
 
-    let work_items = match plan.plan() {
-        Ok(items) => items,
+    let mut stream = match engine.run_plan(&plan) {
+        Ok(s) => s,
         Err(e) => {
             info!("Work item creation failed: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            return Err(status_for(&e));
         }
     };
 
-    let mut stream = engine.run(work_items);
-
     if let Some(result) = stream.next().await {
         match result {
-            Ok((_key, data)) => match TxByHashPlan::decode(data) {
-                Ok(Some(transaction)) => {
-                    info!("Transaction found: {}", hash);
-                    Ok(Json(serde_json::to_value(transaction).unwrap()))
-                }
-                Ok(None) => {
-                    info!("Transaction not found: {}", hash);
-                    Err(StatusCode::NOT_FOUND)
-                }
-                Err(e) => {
-                    info!("Decode error: {}", e);
-                    Err(StatusCode::INTERNAL_SERVER_ERROR)
-                }
-            },
+            Ok((_key, Some(transaction))) => {
+                info!("Transaction found: {}", hash);
+                Ok(Json(serde_json::to_value(transaction).unwrap()))
+            }
+            Ok((_key, None)) => {
+                info!("Transaction not found: {}", hash);
+                Err(StatusCode::NOT_FOUND)
+            }
             Err(e) => {
                 info!("Stream error: {}", e);
-                Err(StatusCode::INTERNAL_SERVER_ERROR)
+                Err(status_for(&e))
             }
         }
     } else {
@@ -1,14 +1,18 @@
 mod handlers;
+mod metrics;
 mod types;
 
-use alloy::transports::http::reqwest::Url;
-use axum::{Router, routing::get};
+use axum::{Extension, Router, middleware, routing::get};
 use handlers::{
     get_balance_at_date, get_block_by_number, get_erc20_balance_at_date, get_logs_erc20_token,
     get_logs_erc20_wallet, get_logs_general, get_transaction_by_hash, get_transaction_receipt,
-    ping, rpc_info, trace_filter_no_address, trace_filter_with_address,
+    ping, prometheus_metrics, rpc_info, trace_filter_no_address, trace_filter_with_address,
 };
-use indexer::EngineBuilder;
+use indexer::{
+    IndexerConfig,
+    config::{CacheSection, EndpointSection},
+};
+use metrics::{HttpMetrics, track_http};
 use std::sync::Arc;
 use tokio::net::TcpListener;
 use tower::ServiceBuilder;
@@ -19,32 +23,32 @@ use tracing::info;
 async fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt::init();
 
-    let rpc_urls: Vec<Url> = std::env::var("RPC_URLS")
-        .unwrap_or_else(|_| "https://eth.drpc.org".to_string())
-        .split(',')
-        .map(|url| url.trim().parse())
-        .collect::<Result<Vec<_>, _>>()?;
+    let config = load_config()?;
 
-    info!("Building Engine with {} RPC endpoints", rpc_urls.len(),);
-    for url in 0..rpc_urls.len() {
-        info!("- {}", rpc_urls[url]);
+    info!(
+        "Building Engine with {} RPC endpoints",
+        config.endpoints.len()
+    );
+    info!(
+        "Parallel requests per RPC: {}",
+        config.parallel_per_rpc.unwrap_or_default()
+    );
+    if let Some(cache) = &config.cache {
+        info!(
+            "Caching finalized responses in {} (max {} MB)",
+            cache.dir, cache.max_mb
+        );
     }
 
-    // Configure concurrency based on environment or use safe defaults
-    let parallel_per_rpc = std::env::var("PARALLEL_PER_RPC")
-        .ok()
-        .and_then(|s| s.parse::<usize>().ok())
-        .unwrap_or(8); // Reduced from 20 to 8 for memory stability
-
-    info!("Parallel requests per RPC: {}", parallel_per_rpc);
-
-    let engine = EngineBuilder::new()
-        .rpc_urls(rpc_urls)
-        .parallel_per_rpc(parallel_per_rpc)
-        .retry(10, 1000, 500)
-        .build()?;
+    // Refuses to start if an endpoint is on another chain; endpoints that
+    // are down are reported and kept
+    let (engine, capabilities) = config.engine_builder()?.build_validated().await?;
+    for line in capabilities.to_string().lines() {
+        info!("{}", line);
+    }
 
     let shared_engine = Arc::new(engine);
+    let http_metrics = Arc::new(HttpMetrics::default());
 
     let app = Router::new()
         .route("/ping", get(ping))
@@ -83,6 +87,14 @@ async fn main() -> anyhow::Result<()> {
             "/api/eth/getLogs/erc20/token/{address}",
             get(get_logs_erc20_token),
         )
+        .route_layer(middleware::from_fn_with_state(
+            http_metrics.clone(),
+            track_http,
+        ))
+        .route("/metrics", get(prometheus_metrics))
+        .layer(Extension(http_metrics))
+        .layer(Extension(Arc::new(capabilities)))
+        .layer(Extension(config.clone()))
         .layer(
             ServiceBuilder::new().layer(
                 CorsLayer::new()
@@ -93,11 +105,7 @@ async fn main() -> anyhow::Result<()> {
         )
         .with_state(shared_engine);
 
-    let port = std::env::var("PORT")
-        .unwrap_or_else(|_| "8080".to_string())
-        .parse::<u16>()
-        .unwrap_or(8080);
-
+    let port = config.server.port;
     let addr = format!("0.0.0.0:{}", port);
     let listener = TcpListener::bind(&addr).await?;
 
@@ -106,3 +114,53 @@ async fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// `INDEXER_CONFIG` (a TOML file) if set, then the environment variables on top.
+fn load_config() -> anyhow::Result<Arc<IndexerConfig>> {
+    let mut config = match std::env::var("INDEXER_CONFIG") {
+        Ok(path) => {
+            info!("Loading config from {}", path);
+            IndexerConfig::load(path)?
+        }
+        Err(_) => IndexerConfig::default(),
+    };
+
+    if let Ok(urls) = std::env::var("RPC_URLS") {
+        config.endpoints = urls
+            .split(',')
+            .map(|url| EndpointSection::new(url.trim()))
+            .collect();
+    }
+    if config.endpoints.is_empty() {
+        config.endpoints = vec![EndpointSection::new("https://eth.drpc.org")];
+    }
+    // 8 rather than the engine default, for memory stability
+    config.parallel_per_rpc = env_parse("PARALLEL_PER_RPC")
+        .or(config.parallel_per_rpc)
+        .or(Some(8));
+    if let Some(id) = env_parse("CHAIN_ID") {
+        config.chain_id = Some(id);
+    }
+    if let Ok(dir) = std::env::var("CACHE_DIR") {
+        config.cache = Some(CacheSection {
+            dir,
+            max_mb: env_parse("CACHE_MAX_MB").unwrap_or(1024),
+        });
+    }
+    if let Some(n) = env_parse("MAX_TRACE_RESULTS") {
+        config.server.max_trace_results = n;
+    }
+    // one slow request must not hold a handler for minutes
+    if let Some(secs) = env_parse("RUN_DEADLINE_SECS") {
+        config.timeouts.run_deadline_secs = Some(secs);
+    }
+    config.timeouts.run_deadline_secs.get_or_insert(120.0);
+    if let Some(port) = env_parse("PORT") {
+        config.server.port = port;
+    }
+    Ok(Arc::new(config))
+}
+
+fn env_parse<T: std::str::FromStr>(var: &str) -> Option<T> {
+    std::env::var(var).ok().and_then(|s| s.parse().ok())
+}
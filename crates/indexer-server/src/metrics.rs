@@ -0,0 +1,274 @@
+//! Prometheus metrics: HTTP handler latency per route (recorded by the
+//! `track_http` middleware) and rendering of the engine's `RpcStats`.
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use indexer::{CircuitState, EthereumIndexer, ordering_gauges};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+// seconds
+const HTTP_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Default)]
+struct RouteStats {
+    responses: BTreeMap<u16, u64>, // status -> count
+    buckets: [u64; HTTP_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+#[derive(Default)]
+pub struct HttpMetrics {
+    routes: Mutex<BTreeMap<String, RouteStats>>,
+}
+
+impl HttpMetrics {
+    fn record(&self, route: &str, status: u16, secs: f64) {
+        let mut routes = self.routes.lock().unwrap();
+        let r = routes.entry(route.to_string()).or_default();
+        *r.responses.entry(status).or_default() += 1;
+        for (b, le) in r.buckets.iter_mut().zip(HTTP_BUCKETS) {
+            if secs <= le {
+                *b += 1;
+            }
+        }
+        r.count += 1;
+        r.sum += secs;
+    }
+}
+
+/// Route layer timing every request by its route template.
+pub async fn track_http(
+    State(metrics): State<Arc<HttpMetrics>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let t0 = Instant::now();
+    let res = next.run(req).await;
+    metrics.record(&route, res.status().as_u16(), t0.elapsed().as_secs_f64());
+    res
+}
+
+/// Everything in Prometheus text exposition format.
+pub fn render(engine: &EthereumIndexer, http: &HttpMetrics) -> String {
+    let mut out = String::new();
+    render_rpc(&mut out, engine);
+    render_http(&mut out, http);
+
+    let (streams, buffered) = ordering_gauges();
+    header(
+        &mut out,
+        "indexer_ordered_streams_active",
+        "gauge",
+        "Live range-ordered result streams.",
+    );
+    let _ = writeln!(out, "indexer_ordered_streams_active {streams}");
+    header(
+        &mut out,
+        "indexer_ordered_buffered_items",
+        "gauge",
+        "Results held back waiting for an earlier range.",
+    );
+    let _ = writeln!(out, "indexer_ordered_buffered_items {buffered}");
+    out
+}
+
+fn render_rpc(out: &mut String, engine: &EthereumIndexer) {
+    let stats: Vec<_> = engine.stats().iter().map(|s| s.detailed()).collect();
+
+    header(
+        out,
+        "indexer_rpc_requests_total",
+        "counter",
+        "Requests sent to an RPC endpoint.",
+    );
+    for (i, s) in stats.iter().enumerate() {
+        for m in &s.methods {
+            let _ = writeln!(
+                out,
+                "indexer_rpc_requests_total{{endpoint=\"{i}\",method=\"{}\"}} {}",
+                m.method, m.requests
+            );
+        }
+    }
+
+    header(
+        out,
+        "indexer_rpc_errors_total",
+        "counter",
+        "Failed RPC requests by error class.",
+    );
+    for (i, s) in stats.iter().enumerate() {
+        for m in &s.methods {
+            let e = &m.errors;
+            let labels = format!("endpoint=\"{i}\",method=\"{}\"", m.method);
+            let mut line = |class: &str, code: String, n: u64| {
+                if n > 0 {
+                    let _ = writeln!(
+                        out,
+                        "indexer_rpc_errors_total{{{labels},class=\"{class}\",code=\"{code}\"}} {n}"
+                    );
+                }
+            };
+            line("timeout", String::new(), e.timeout);
+            for (status, n) in &e.http {
+                line("http", status.to_string(), *n);
+            }
+            for (code, n) in &e.rpc {
+                line("rpc", code.to_string(), *n);
+            }
+            line("decode", String::new(), e.decode);
+            line("missing_state", String::new(), e.missing_state);
+            line("other", String::new(), e.other);
+        }
+    }
+
+    header(
+        out,
+        "indexer_rpc_in_flight",
+        "gauge",
+        "RPC requests currently in flight.",
+    );
+    for (i, s) in stats.iter().enumerate() {
+        for m in &s.methods {
+            let _ = writeln!(
+                out,
+                "indexer_rpc_in_flight{{endpoint=\"{i}\",method=\"{}\"}} {}",
+                m.method, m.in_flight
+            );
+        }
+    }
+
+    header(
+        out,
+        "indexer_rpc_request_duration_seconds",
+        "histogram",
+        "RPC request latency.",
+    );
+    for (i, s) in stats.iter().enumerate() {
+        for m in &s.methods {
+            let labels = format!("endpoint=\"{i}\",method=\"{}\"", m.method);
+            let h = &m.histogram;
+            for (le, n) in &h.buckets {
+                let _ = writeln!(
+                    out,
+                    "indexer_rpc_request_duration_seconds_bucket{{{labels},le=\"{le}\"}} {n}"
+                );
+            }
+            let _ = writeln!(
+                out,
+                "indexer_rpc_request_duration_seconds_bucket{{{labels},le=\"+Inf\"}} {}",
+                h.count
+            );
+            let _ = writeln!(
+                out,
+                "indexer_rpc_request_duration_seconds_sum{{{labels}}} {}",
+                h.sum_secs
+            );
+            let _ = writeln!(
+                out,
+                "indexer_rpc_request_duration_seconds_count{{{labels}}} {}",
+                h.count
+            );
+        }
+    }
+
+    header(
+        out,
+        "indexer_rpc_head_block",
+        "gauge",
+        "Latest block number seen on the endpoint.",
+    );
+    for (i, head) in engine.heads().iter().enumerate() {
+        if let Some(head) = head {
+            let _ = writeln!(out, "indexer_rpc_head_block{{endpoint=\"{i}\"}} {head}");
+        }
+    }
+
+    header(
+        out,
+        "indexer_rpc_circuit_state",
+        "gauge",
+        "Circuit breaker state (1 = current).",
+    );
+    for (i, s) in stats.iter().enumerate() {
+        for (name, state) in [
+            ("closed", CircuitState::Closed),
+            ("open", CircuitState::Open),
+            ("half_open", CircuitState::HalfOpen),
+        ] {
+            let _ = writeln!(
+                out,
+                "indexer_rpc_circuit_state{{endpoint=\"{i}\",state=\"{name}\"}} {}",
+                u8::from(s.circuit == state)
+            );
+        }
+    }
+}
+
+fn render_http(out: &mut String, http: &HttpMetrics) {
+    let routes = http.routes.lock().unwrap();
+
+    header(
+        out,
+        "http_requests_total",
+        "counter",
+        "HTTP responses by route and status.",
+    );
+    for (route, r) in routes.iter() {
+        for (status, n) in &r.responses {
+            let _ = writeln!(
+                out,
+                "http_requests_total{{route=\"{route}\",status=\"{status}\"}} {n}"
+            );
+        }
+    }
+
+    header(
+        out,
+        "http_request_duration_seconds",
+        "histogram",
+        "HTTP handler latency.",
+    );
+    for (route, r) in routes.iter() {
+        for (le, n) in HTTP_BUCKETS.iter().zip(r.buckets) {
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds_bucket{{route=\"{route}\",le=\"{le}\"}} {n}"
+            );
+        }
+        let _ = writeln!(
+            out,
+            "http_request_duration_seconds_bucket{{route=\"{route}\",le=\"+Inf\"}} {}",
+            r.count
+        );
+        let _ = writeln!(
+            out,
+            "http_request_duration_seconds_sum{{route=\"{route}\"}} {}",
+            r.sum
+        );
+        let _ = writeln!(
+            out,
+            "http_request_duration_seconds_count{{route=\"{route}\"}} {}",
+            r.count
+        );
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
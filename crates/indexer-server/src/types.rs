@@ -1,3 +1,4 @@
+use indexer::Capabilities;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize)]
@@ -84,4 +85,8 @@ pub struct LogsResponse {
 pub struct RpcInfoResponse {
     pub rpc_urls: Vec<String>,
     pub parallel_per_rpc: usize,
+    /// `null` if no endpoint answered at startup.
+    pub chain_id: Option<u64>,
+    /// Probe results per endpoint, in `rpc_urls` order.
+    pub capabilities: Vec<Capabilities>,
 }
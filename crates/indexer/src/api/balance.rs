@@ -1,6 +1,7 @@
 use crate::EthereumIndexer;
 use crate::api::block_time::{RangeMiss, block_at_or_before_ts_strict};
 use crate::api::erc20::balance::token_balance_at_block;
+use crate::error::{IndexerError, Result};
 use crate::methods::eth::get_balance as get_bal;
 use alloy::{
     primitives::{Address, U256},
@@ -21,10 +22,10 @@ pub async fn balance_at_timestamp(
     mut lo: u64,
     mut hi: u64,
     on_miss: OnMiss,
-) -> anyhow::Result<Option<U256>> {
+) -> Result<Option<U256>> {
     use crate::methods::eth::get_block_by_number as get_block;
 
-    match block_at_or_before_ts_strict(idx, t_sec, lo, hi).await? {
+    match block_at_or_before_ts_strict(idx, t_sec, lo, hi).await {
         Ok(b) => {
             tracing::info!(
                 "Found block {} (timestamp: {}) for target timestamp {}",
@@ -38,9 +39,9 @@ pub async fn balance_at_timestamp(
                     BlockNumberOrTag::Number(b.header.number),
                 )?)
                 .await?;
-            return Ok(Some(get_bal::GetBalancePlan::decode(v)?));
+            Ok(Some(get_bal::GetBalancePlan::decode(v)?))
         }
-        Err(RangeMiss::BeforeRange { t, lo, lo_ts }) => {
+        Err(IndexerError::RangeMiss(RangeMiss::BeforeRange { t, lo, lo_ts })) => {
             tracing::warn!(
                 "Timestamp {} is before range: block {} has timestamp {}",
                 t,
@@ -48,27 +49,27 @@ pub async fn balance_at_timestamp(
                 lo_ts
             );
             match on_miss {
-                OnMiss::Strict => return Ok(None),
+                OnMiss::Strict => Ok(None),
                 OnMiss::ClampToBounds => {
                     tracing::info!("Clamping to block {} (lo bound)", lo);
                     let v = idx
                         .run_once(get_bal::work_one(addr, BlockNumberOrTag::Number(lo))?)
                         .await?;
-                    return Ok(Some(get_bal::GetBalancePlan::decode(v)?));
+                    Ok(Some(get_bal::GetBalancePlan::decode(v)?))
                 }
                 OnMiss::AutoWidenToLatest => {
                     tracing::info!(
                         "Target timestamp is before range - cannot widen downward, returning None"
                     );
-                    return Ok(None);
+                    Ok(None)
                 }
             }
         }
-        Err(RangeMiss::AfterRange {
+        Err(IndexerError::RangeMiss(RangeMiss::AfterRange {
             t,
             hi: hi_bound,
             hi_ts,
-        }) => {
+        })) => {
             tracing::warn!(
                 "Timestamp {} is after range: block {} has timestamp {}",
                 t,
@@ -76,13 +77,13 @@ pub async fn balance_at_timestamp(
                 hi_ts
             );
             match on_miss {
-                OnMiss::Strict => return Ok(None),
+                OnMiss::Strict => Ok(None),
                 OnMiss::ClampToBounds => {
                     tracing::info!("Clamping to block {} (hi bound)", hi_bound);
                     let v = idx
                         .run_once(get_bal::work_one(addr, BlockNumberOrTag::Number(hi_bound))?)
                         .await?;
-                    return Ok(Some(get_bal::GetBalancePlan::decode(v)?));
+                    Ok(Some(get_bal::GetBalancePlan::decode(v)?))
                 }
                 OnMiss::AutoWidenToLatest => {
                     tracing::info!("Auto-widening range to latest block");
@@ -93,7 +94,7 @@ pub async fn balance_at_timestamp(
                     let latest: Option<alloy::rpc::types::eth::Block> =
                         get_block::BlockByNumberPlan::decode(latest_val)?;
                     let latest =
-                        latest.ok_or_else(|| anyhow::anyhow!("latest/finalized block missing"))?;
+                        latest.ok_or_else(|| IndexerError::BlockNotFound("finalized".into()))?;
 
                     lo = hi_bound.saturating_add(1);
                     hi = latest.header.number;
@@ -107,7 +108,9 @@ pub async fn balance_at_timestamp(
                     }
                     // Retry once within widened window
                     tracing::info!("Retrying search in widened range {} to {}", lo, hi);
-                    if let Ok(b) = block_at_or_before_ts_strict(idx, t_sec, lo, hi).await? {
+                    if let Some(b) =
+                        widened_retry(block_at_or_before_ts_strict(idx, t_sec, lo, hi).await)?
+                    {
                         tracing::info!(
                             "Found block {} (timestamp: {}) in widened range",
                             b.header.number,
@@ -133,6 +136,7 @@ pub async fn balance_at_timestamp(
                 }
             }
         }
+        Err(e) => Err(e),
     }
 }
 
@@ -143,7 +147,7 @@ pub async fn balance_at_timestamp_legacy(
     t_sec: u64,
     lo: u64,
     hi: u64,
-) -> anyhow::Result<Option<U256>> {
+) -> Result<Option<U256>> {
     let mb = super::block_time::block_at_or_before_ts(idx, t_sec, lo, hi).await?;
     let Some(b) = mb else {
         return Ok(None);
@@ -167,10 +171,10 @@ pub async fn erc20_balance_at_timestamp(
     mut lo: u64,
     mut hi: u64,
     on_miss: OnMiss,
-) -> anyhow::Result<Option<U256>> {
+) -> Result<Option<U256>> {
     use crate::methods::eth::get_block_by_number as get_block;
 
-    match block_at_or_before_ts_strict(idx, t_sec, lo, hi).await? {
+    match block_at_or_before_ts_strict(idx, t_sec, lo, hi).await {
         Ok(b) => {
             tracing::info!(
                 "Found block {} (timestamp: {}) for target timestamp {}",
@@ -185,9 +189,9 @@ pub async fn erc20_balance_at_timestamp(
                 BlockNumberOrTag::Number(b.header.number),
             )
             .await?;
-            return Ok(Some(balance));
+            Ok(Some(balance))
         }
-        Err(RangeMiss::BeforeRange { t, lo, lo_ts }) => {
+        Err(IndexerError::RangeMiss(RangeMiss::BeforeRange { t, lo, lo_ts })) => {
             tracing::warn!(
                 "Timestamp {} is before range: block {} has timestamp {}",
                 t,
@@ -195,27 +199,27 @@ pub async fn erc20_balance_at_timestamp(
                 lo_ts
             );
             match on_miss {
-                OnMiss::Strict => return Ok(None),
+                OnMiss::Strict => Ok(None),
                 OnMiss::ClampToBounds => {
                     tracing::info!("Clamping to block {} (lo bound)", lo);
                     let balance =
                         token_balance_at_block(idx, token, owner, BlockNumberOrTag::Number(lo))
                             .await?;
-                    return Ok(Some(balance));
+                    Ok(Some(balance))
                 }
                 OnMiss::AutoWidenToLatest => {
                     tracing::info!(
                         "Target timestamp is before range - cannot widen downward, returning None"
                     );
-                    return Ok(None);
+                    Ok(None)
                 }
             }
         }
-        Err(RangeMiss::AfterRange {
+        Err(IndexerError::RangeMiss(RangeMiss::AfterRange {
             t,
             hi: hi_bound,
             hi_ts,
-        }) => {
+        })) => {
             tracing::warn!(
                 "Timestamp {} is after range: block {} has timestamp {}",
                 t,
@@ -223,7 +227,7 @@ pub async fn erc20_balance_at_timestamp(
                 hi_ts
             );
             match on_miss {
-                OnMiss::Strict => return Ok(None),
+                OnMiss::Strict => Ok(None),
                 OnMiss::ClampToBounds => {
                     tracing::info!("Clamping to block {} (hi bound)", hi_bound);
                     let balance = token_balance_at_block(
@@ -233,7 +237,7 @@ pub async fn erc20_balance_at_timestamp(
                         BlockNumberOrTag::Number(hi_bound),
                     )
                     .await?;
-                    return Ok(Some(balance));
+                    Ok(Some(balance))
                 }
                 OnMiss::AutoWidenToLatest => {
                     tracing::info!("Auto-widening range to latest block");
@@ -244,7 +248,7 @@ pub async fn erc20_balance_at_timestamp(
                     let latest: Option<alloy::rpc::types::eth::Block> =
                         get_block::BlockByNumberPlan::decode(latest_val)?;
                     let latest =
-                        latest.ok_or_else(|| anyhow::anyhow!("latest/finalized block missing"))?;
+                        latest.ok_or_else(|| IndexerError::BlockNotFound("finalized".into()))?;
 
                     lo = hi_bound.saturating_add(1);
                     hi = latest.header.number;
@@ -258,7 +262,9 @@ pub async fn erc20_balance_at_timestamp(
                     }
                     // Retry once within widened window
                     tracing::info!("Retrying search in widened range {} to {}", lo, hi);
-                    if let Ok(b) = block_at_or_before_ts_strict(idx, t_sec, lo, hi).await? {
+                    if let Some(b) =
+                        widened_retry(block_at_or_before_ts_strict(idx, t_sec, lo, hi).await)?
+                    {
                         tracing::info!(
                             "Found block {} (timestamp: {}) in widened range",
                             b.header.number,
@@ -285,5 +291,17 @@ pub async fn erc20_balance_at_timestamp(
                 }
             }
         }
+        Err(e) => Err(e),
+    }
+}
+
+/// A miss on the widened retry is not an error: the caller clamps instead.
+fn widened_retry(
+    r: Result<alloy::rpc::types::eth::Block, IndexerError>,
+) -> Result<Option<alloy::rpc::types::eth::Block>, IndexerError> {
+    match r {
+        Ok(b) => Ok(Some(b)),
+        Err(IndexerError::RangeMiss(_)) => Ok(None),
+        Err(e) => Err(e),
     }
 }
@@ -1,9 +1,10 @@
 use crate::EthereumIndexer;
+use crate::error::{IndexerError, Result};
 use crate::methods::eth::get_block_by_number as get_blk;
 use alloy::rpc::types::eth::Block;
 use alloy::rpc::types::eth::BlockNumberOrTag;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum RangeMiss {
     BeforeRange { t: u64, lo: u64, lo_ts: u64 },
     AfterRange { t: u64, hi: u64, hi_ts: u64 },
@@ -29,7 +30,7 @@ pub async fn block_at_or_before_ts_strict(
     t: u64,
     lo: u64,
     hi: u64,
-) -> anyhow::Result<Result<Block, RangeMiss>> {
+) -> Result<Block> {
     // Preflight: timestamps at bounds (2 calls)
     let blo_val = idx
         .run_once(get_blk::work_one(BlockNumberOrTag::Number(lo), false)?)
@@ -43,17 +44,17 @@ pub async fn block_at_or_before_ts_strict(
 
     let (blo, bhi) = match (blo, bhi) {
         (Some(blo), Some(bhi)) => (blo, bhi),
-        _ => anyhow::bail!("boundary block(s) missing"),
+        _ => return Err(IndexerError::decode("boundary block(s) missing")),
     };
 
     let lo_ts = blo.header.timestamp;
     let hi_ts = bhi.header.timestamp;
 
     if t < lo_ts {
-        return Ok(Err(RangeMiss::BeforeRange { t, lo, lo_ts }));
+        return Err(RangeMiss::BeforeRange { t, lo, lo_ts }.into());
     }
     if t > hi_ts {
-        return Ok(Err(RangeMiss::AfterRange { t, hi, hi_ts }));
+        return Err(RangeMiss::AfterRange { t, hi, hi_ts }.into());
     }
 
     // Binary search in [lo, hi] to find the greatest block with ts <= t
@@ -67,7 +68,7 @@ pub async fn block_at_or_before_ts_strict(
         let bm: Option<Block> = get_blk::BlockByNumberPlan::decode(bm_val)?;
         let bm = match bm {
             Some(bm) => bm,
-            None => anyhow::bail!("mid block {mid} missing"),
+            None => return Err(IndexerError::decode(format!("mid block {mid} missing"))),
         };
         if bm.header.timestamp <= t {
             best = bm;
@@ -76,7 +77,7 @@ pub async fn block_at_or_before_ts_strict(
             r = mid.saturating_sub(1);
         }
     }
-    Ok(Ok(best))
+    Ok(best)
 }
 
 pub async fn block_at_or_before_ts(
@@ -84,7 +85,7 @@ pub async fn block_at_or_before_ts(
     t_sec: u64,
     mut lo: u64,
     mut hi: u64,
-) -> anyhow::Result<Option<Block>> {
+) -> Result<Option<Block>> {
     if lo > hi {
         return Ok(None);
     }
@@ -134,3 +135,66 @@ pub async fn block_at_or_before_ts(
         .await?;
     get_blk::BlockByNumberPlan::decode(v)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EngineBuilder, MockRpc};
+
+    fn indexer() -> EthereumIndexer {
+        // blocks 0..=99, ts = 1_000 + 12 * n
+        let mock = MockRpc::new().chain(0, 99, 1_000, 12);
+        EngineBuilder::new().build_with_clients(vec![mock.client()])
+    }
+
+    #[tokio::test]
+    async fn strict_finds_greatest_block_at_or_before() {
+        let idx = indexer();
+        let b = block_at_or_before_ts_strict(&idx, 1_000 + 12 * 37 + 5, 0, 99)
+            .await
+            .unwrap();
+        assert_eq!(b.header.number, 37);
+        let b = block_at_or_before_ts_strict(&idx, 1_000 + 12 * 50, 0, 99)
+            .await
+            .unwrap();
+        assert_eq!(b.header.number, 50);
+    }
+
+    #[tokio::test]
+    async fn strict_reports_range_misses() {
+        let idx = indexer();
+        match block_at_or_before_ts_strict(&idx, 999, 0, 99).await {
+            Err(IndexerError::RangeMiss(RangeMiss::BeforeRange {
+                lo: 0,
+                lo_ts: 1_000,
+                ..
+            })) => {}
+            other => panic!("expected BeforeRange, got {other:?}"),
+        }
+        match block_at_or_before_ts_strict(&idx, 1_000 + 12 * 60, 10, 20).await {
+            Err(IndexerError::RangeMiss(RangeMiss::AfterRange { hi: 20, .. })) => {}
+            other => panic!("expected AfterRange, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn lenient_clamps_to_range() {
+        let idx = indexer();
+        let b = block_at_or_before_ts(&idx, 1_000 + 12 * 37 + 5, 0, 99)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(b.header.number, 37);
+        assert!(
+            block_at_or_before_ts(&idx, 999, 0, 99)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        let b = block_at_or_before_ts(&idx, 1_000 + 12 * 60, 10, 20)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(b.header.number, 20);
+    }
+}
@@ -1,25 +1,96 @@
 use crate::{
+    breaker::BreakerConfig,
+    cache::ResponseCache,
+    capabilities::{Capabilities, CapabilityReport, PROBE_TIMEOUT, check_chain, probe},
+    error::Result,
     exec::EthereumIndexer,
-    pool::ProviderPool,
-    providers::{build_rpc_clients, build_rpc_clients_with_retry},
+    pool::{ProviderPool, Selection},
+    providers::{EndpointConfig, RpcEndpoint, connect_endpoints, http_rpc_client},
+    ratelimit::RateLimit,
+    record::Recorder,
 };
-use alloy::transports::http::reqwest::Url;
+use alloy::{rpc::client::RpcClient, transports::http::reqwest::Url};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
 
 pub struct EngineBuilder {
-    urls: Vec<Url>,
+    endpoints: Vec<EndpointConfig>,
     per_rpc_parallel: usize,
     retry: Option<(u32, u64, u64)>,
+    bisect: bool,
+    selection: Selection,
+    breaker: Option<BreakerConfig>,
+    failover: usize,
+    rate_limit: Option<RateLimit>,
+    rate_limit_for: HashMap<usize, RateLimit>,
+    method_costs: HashMap<String, f64>,
+    batch_size: usize,
+    batch_size_for: HashMap<usize, usize>,
+    cache: Option<Arc<dyn ResponseCache>>,
+    coalesce: bool,
+    quorum: HashMap<String, usize>,
+    chain_id: Option<u64>,
+    // None = not set: every 2s, except in `build_with_clients()`
+    head_tracking: Option<Option<Duration>>,
+    archive_for: HashMap<usize, bool>,
+    weight_for: HashMap<usize, f64>,
+    timeout: Option<Duration>,
+    method_timeouts: HashMap<String, Duration>,
+    deadline: Option<Duration>,
+    recorder: Option<Arc<Recorder>>,
 }
+impl Default for EngineBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl EngineBuilder {
     pub fn new() -> Self {
         Self {
-            urls: vec![],
+            endpoints: vec![],
             per_rpc_parallel: 5,
             retry: None,
+            bisect: true,
+            selection: Selection::RoundRobin,
+            breaker: Some(BreakerConfig::default()),
+            failover: 2,
+            rate_limit: None,
+            rate_limit_for: HashMap::new(),
+            method_costs: HashMap::new(),
+            batch_size: 1,
+            batch_size_for: HashMap::new(),
+            cache: None,
+            coalesce: true,
+            quorum: HashMap::new(),
+            chain_id: None,
+            head_tracking: None,
+            archive_for: HashMap::new(),
+            weight_for: HashMap::new(),
+            timeout: None,
+            method_timeouts: HashMap::new(),
+            deadline: None,
+            recorder: None,
         }
     }
+    /// Endpoints by URL; `ws://` / `wss://` URLs use the WebSocket transport.
     pub fn rpc_urls(mut self, urls: Vec<Url>) -> Self {
-        self.urls = urls;
+        self.endpoints = urls
+            .into_iter()
+            .map(|url| RpcEndpoint::from(url).into())
+            .collect();
+        self
+    }
+    /// Any mix of HTTP, WebSocket and IPC endpoints. WS/IPC need `connect()`.
+    pub fn rpc_endpoints(mut self, endpoints: Vec<RpcEndpoint>) -> Self {
+        self.endpoints = endpoints.into_iter().map(EndpointConfig::from).collect();
+        self
+    }
+    /// Endpoints with auth, extra headers and a display name. Secrets never
+    /// show up in logs or stats: endpoints are shown by name or redacted URL.
+    pub fn endpoint_configs(mut self, endpoints: Vec<EndpointConfig>) -> Self {
+        self.endpoints = endpoints;
         self
     }
     pub fn parallel_per_rpc(mut self, n: usize) -> Self {
@@ -30,13 +101,247 @@ impl EngineBuilder {
         self.retry = Some((max, backoff_ms, jitter_ms));
         self
     }
-    pub fn build(self) -> anyhow::Result<EthereumIndexer> {
-        let clients = if let Some((m, b, j)) = self.retry {
-            build_rpc_clients_with_retry(self.urls, m, b, j)
-        } else {
-            build_rpc_clients(self.urls)
+    /// Endpoint selection strategy (default: round-robin).
+    pub fn selection(mut self, s: Selection) -> Self {
+        self.selection = s;
+        self
+    }
+    /// Per-endpoint circuit breaker (default: 5 failures, 30s cooldown).
+    pub fn circuit_breaker(
+        mut self,
+        failure_threshold: u32,
+        cooldown: std::time::Duration,
+    ) -> Self {
+        self.breaker = Some(BreakerConfig {
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+        });
+        self
+    }
+    pub fn no_circuit_breaker(mut self) -> Self {
+        self.breaker = None;
+        self
+    }
+    /// Re-send a failed request to up to `n` other endpoints (default: 2).
+    pub fn failover(mut self, n: usize) -> Self {
+        self.failover = n;
+        self
+    }
+    /// Relative capacity of the endpoint at `idx` in `rpc_urls` (default 1.0),
+    /// used by the `LeastOutstanding` and `Weighted` selections.
+    pub fn weight_for(mut self, idx: usize, weight: f64) -> Self {
+        self.weight_for.insert(idx, weight);
+        self
+    }
+    /// Token-bucket budget applied to every endpoint.
+    pub fn rate_limit(mut self, limit: RateLimit) -> Self {
+        self.rate_limit = Some(limit);
+        self
+    }
+    /// Token-bucket budget for the endpoint at `idx` in `rpc_urls` (overrides `rate_limit`).
+    pub fn rate_limit_for(mut self, idx: usize, limit: RateLimit) -> Self {
+        self.rate_limit_for.insert(idx, limit);
+        self
+    }
+    /// Budget units a method draws per call (default: 1), e.g. compute units.
+    pub fn method_cost(mut self, method: &str, units: f64) -> Self {
+        self.method_costs.insert(method.to_string(), units.max(0.0));
+        self
+    }
+    /// Pack small work items into JSON-RPC batches of up to `n` calls (default: 1 = off).
+    /// Parallelism counts requests and a batch is one, so up to `n` times as
+    /// many calls can be in flight.
+    pub fn batch_size(mut self, n: usize) -> Self {
+        self.batch_size = n.max(1);
+        self
+    }
+    /// Batch size for the endpoint at `idx` in `rpc_urls` (overrides `batch_size`).
+    pub fn batch_size_for(mut self, idx: usize, n: usize) -> Self {
+        self.batch_size_for.insert(idx, n.max(1));
+        self
+    }
+    /// Cache answers of queries pinned at or below the finalized head (e.g. `DiskCache`).
+    pub fn cache(mut self, cache: Arc<dyn ResponseCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+    /// Write every upstream call to `recorder` as NDJSON, for `replay()`.
+    pub fn record(mut self, recorder: Arc<Recorder>) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+    /// Let identical concurrent requests share one upstream call (default: on).
+    pub fn coalesce_requests(mut self, yes: bool) -> Self {
+        self.coalesce = yes;
+        self
+    }
+    /// Send every `method` call to `k` endpoints and return the answer a
+    /// majority of them agree on; otherwise fail with `IndexerError::Divergence`.
+    pub fn quorum(mut self, method: &str, k: usize) -> Self {
+        self.quorum.insert(method.to_string(), k);
+        self
+    }
+    /// Poll endpoint heads every `every` and only send requests for block N
+    /// (or "latest") to endpoints that have reached it (default: every 2s,
+    /// off for `build_with_clients()`).
+    pub fn head_tracking(mut self, every: Duration) -> Self {
+        self.head_tracking = Some(Some(every));
+        self
+    }
+    pub fn no_head_tracking(mut self) -> Self {
+        self.head_tracking = Some(None);
+        self
+    }
+    /// Tag the endpoint at `idx` in `rpc_urls` as archive (`true`) or pruned
+    /// (`false`), overriding what `build_validated()` probes. Historical state
+    /// queries (`eth_getBalance`, `eth_call`, ... at old blocks) avoid pruned endpoints.
+    pub fn archive_for(mut self, idx: usize, is_archive: bool) -> Self {
+        self.archive_for.insert(idx, is_archive);
+        self
+    }
+    /// Give up on a call that has not been answered within `after` and fail
+    /// over (`IndexerError::Timeout`). Default: no timeout. Also applies to
+    /// the `build_validated()` probes (default 10s) and head polls (default:
+    /// the poll interval).
+    pub fn request_timeout(mut self, after: Duration) -> Self {
+        self.timeout = Some(after);
+        self
+    }
+    /// Timeout for `method` only, e.g. a longer one for `trace_filter`.
+    pub fn method_timeout(mut self, method: &str, after: Duration) -> Self {
+        self.method_timeouts.insert(method.to_string(), after);
+        self
+    }
+    /// Cancel a `run*` / `call_once` still going after `after` and end it
+    /// with `IndexerError::DeadlineExceeded`. Default: no deadline.
+    pub fn run_deadline(mut self, after: Duration) -> Self {
+        self.deadline = Some(after);
+        self
+    }
+    /// Chain every endpoint must be on, checked by `build_validated()`.
+    pub fn expect_chain_id(mut self, id: u64) -> Self {
+        self.chain_id = Some(id);
+        self
+    }
+    /// Split `eth_getLogs` / `trace_filter` chunks that hit provider limits (default: on).
+    pub fn bisect_on_limit(mut self, yes: bool) -> Self {
+        self.bisect = yes;
+        self
+    }
+    /// Build an HTTP-only engine. Use `connect()` when WS/IPC endpoints are present.
+    pub fn build(self) -> Result<EthereumIndexer> {
+        let clients = self
+            .endpoints
+            .iter()
+            .map(|cfg| http_rpc_client(cfg, self.retry))
+            .collect::<Result<_>>()?;
+        Ok(self.assemble(clients, None))
+    }
+
+    /// Build on ready-made clients (e.g. `MockRpc::client()`) instead of the
+    /// configured endpoints; per-index settings apply in `clients` order.
+    /// Retry settings are not applied, and heads are not polled unless
+    /// `head_tracking()` was called.
+    pub fn build_with_clients(mut self, clients: Vec<RpcClient>) -> EthereumIndexer {
+        self.head_tracking.get_or_insert(None);
+        self.assemble(clients, None)
+    }
+
+    /// Build on the recording at `path` (see `record`) instead of the
+    /// configured endpoints: nothing goes over the network, and calls the
+    /// recording does not contain fail.
+    #[cfg(any(test, feature = "mock"))]
+    pub fn replay(mut self, path: impl AsRef<std::path::Path>) -> Result<EthereumIndexer> {
+        let (backend, endpoints) = crate::record::replay(path)?;
+        // there is no live head to follow, nor any configured endpoint
+        self.head_tracking = Some(None);
+        self.endpoints.clear();
+        // as many endpoints as were recorded, so failover plays out the same
+        let clients = (0..endpoints).map(|_| backend.client()).collect();
+        Ok(self.assemble(clients, None))
+    }
+
+    /// Connect every endpoint (HTTP, WebSocket or IPC) and build the engine.
+    pub async fn connect(self) -> Result<EthereumIndexer> {
+        let clients = connect_endpoints(self.endpoints.clone(), self.retry).await?;
+        Ok(self.assemble(clients, None))
+    }
+
+    /// Like `connect()`, but first probe every endpoint: all that answer
+    /// `eth_chainId` / `eth_blockNumber` must be on the same chain (see
+    /// `expect_chain_id`), else the build fails with
+    /// `IndexerError::ChainMismatch`. Endpoints that don't answer are marked
+    /// down in the report and kept. `trace_*` and `debug_*` calls are then
+    /// only sent to endpoints that passed the matching probe.
+    pub async fn build_validated(self) -> Result<(EthereumIndexer, CapabilityReport)> {
+        let names: Vec<String> = self.endpoints.iter().map(|c| c.display_name()).collect();
+        let clients = connect_endpoints(self.endpoints.clone(), self.retry).await?;
+        let timeout_for = |method: &str| {
+            self.method_timeouts
+                .get(method)
+                .copied()
+                .or(self.timeout)
+                .unwrap_or(PROBE_TIMEOUT)
         };
-        let pool = ProviderPool::new(clients, self.per_rpc_parallel);
-        Ok(EthereumIndexer::new(pool, self.per_rpc_parallel))
+        let probes = clients
+            .iter()
+            .zip(&names)
+            .enumerate()
+            .map(|(i, (client, name))| async move {
+                probe(client, i, name.clone(), &timeout_for)
+                    .await
+                    .unwrap_or_else(|e| {
+                        tracing::warn!("{name} is down, keeping it in the pool: {e}");
+                        Capabilities::down(name.clone(), &e)
+                    })
+            });
+        let caps = futures::future::join_all(probes).await;
+        let chain_id = check_chain(&caps, self.chain_id)?;
+        let report = CapabilityReport {
+            chain_id,
+            endpoints: caps.clone(),
+        };
+        Ok((self.assemble(clients, Some(caps)), report))
+    }
+
+    fn assemble(self, clients: Vec<RpcClient>, caps: Option<Vec<Capabilities>>) -> EthereumIndexer {
+        let rate_limits = (0..clients.len())
+            .map(|i| self.rate_limit_for.get(&i).copied().or(self.rate_limit))
+            .collect();
+        let batch_sizes = (0..clients.len())
+            .map(|i| {
+                self.batch_size_for
+                    .get(&i)
+                    .copied()
+                    .unwrap_or(self.batch_size)
+            })
+            .collect();
+        let archive_tags = (0..clients.len())
+            .map(|i| self.archive_for.get(&i).copied())
+            .collect();
+        let names = self.endpoints.iter().map(|c| c.display_name()).collect();
+        let weights = (0..clients.len())
+            .map(|i| self.weight_for.get(&i).copied().unwrap_or(1.0))
+            .collect();
+        let pool = ProviderPool::new(clients, self.per_rpc_parallel)
+            .with_names(names)
+            .with_weights(weights)
+            .with_selection(self.selection)
+            .with_breaker(self.breaker)
+            .with_failover(self.failover)
+            .with_rate_limits(rate_limits)
+            .with_method_costs(self.method_costs)
+            .with_batch_sizes(batch_sizes)
+            .with_cache(self.cache)
+            .with_coalescing(self.coalesce)
+            .with_quorum(self.quorum)
+            .with_capabilities(caps)
+            .with_archive_tags(archive_tags)
+            .with_timeouts(self.timeout, self.method_timeouts)
+            .with_recorder(self.recorder)
+            .with_head_tracking(self.head_tracking.unwrap_or(Some(Duration::from_secs(2))));
+        EthereumIndexer::new(pool, self.per_rpc_parallel)
+            .bisect_on_limit(self.bisect)
+            .with_deadline(self.deadline)
     }
 }
@@ -1,4 +1,9 @@
-use crate::{EthereumIndexer, api::eth::call, contracts::erc20::IERC20};
+use crate::{
+    EthereumIndexer,
+    api::eth::call,
+    contracts::erc20::IERC20,
+    error::{IndexerError, Result},
+};
 use alloy::{
     primitives::{Address, U256},
     rpc::types::eth::{BlockNumberOrTag, TransactionRequest},
@@ -10,7 +15,7 @@ pub async fn token_balance_at_block(
     token: Address,
     owner: Address,
     at: BlockNumberOrTag,
-) -> anyhow::Result<U256> {
+) -> Result<U256> {
     // Create the balanceOf call data
     let call_data = IERC20::balanceOfCall { owner }.abi_encode();
 
@@ -20,6 +25,69 @@ pub async fn token_balance_at_block(
 
     let v = idx.run_once(call::work_one(tx_request, at)?).await?;
     let bytes = call::decode_bytes(v)?;
-    let bal = IERC20::balanceOfCall::abi_decode_returns(&bytes)?;
+    let bal = IERC20::balanceOfCall::abi_decode_returns(&bytes).map_err(IndexerError::decode)?;
     Ok(bal)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::balance::{OnMiss, erc20_balance_at_timestamp};
+    use crate::{EngineBuilder, MockRpc};
+    use alloy::primitives::{Bytes, address};
+    use serde_json::json;
+
+    const TOKEN: Address = address!("00000000000000000000000000000000000000aa");
+    const OWNER: Address = address!("00000000000000000000000000000000000000bb");
+
+    // balanceOf answers 100 * block number, so the queried block is visible in the result
+    fn mock() -> MockRpc {
+        MockRpc::new()
+            .chain(0, 99, 1_000, 12)
+            .respond_with("eth_call", |params| {
+                let block = params[1].as_str().unwrap_or("0x0");
+                let n = u64::from_str_radix(block.trim_start_matches("0x"), 16).unwrap_or(0);
+                let ret = IERC20::balanceOfCall::abi_encode_returns(&U256::from(100 * n));
+                Ok(json!(Bytes::from(ret)))
+            })
+    }
+
+    #[tokio::test]
+    async fn decodes_balance_of() {
+        let mock = mock();
+        let idx = EngineBuilder::new().build_with_clients(vec![mock.client()]);
+        let bal = token_balance_at_block(&idx, TOKEN, OWNER, BlockNumberOrTag::Number(42))
+            .await
+            .unwrap();
+        assert_eq!(bal, U256::from(4_200));
+
+        let (_, params) = mock
+            .calls()
+            .into_iter()
+            .find(|(m, _)| m == "eth_call")
+            .unwrap();
+        let input: Bytes = serde_json::from_value(params[0]["input"].clone()).unwrap();
+        assert_eq!(
+            input,
+            Bytes::from(IERC20::balanceOfCall { owner: OWNER }.abi_encode())
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_balance_at_block_for_timestamp() {
+        let mock = mock();
+        let idx = EngineBuilder::new().build_with_clients(vec![mock.client()]);
+        let bal = erc20_balance_at_timestamp(
+            &idx,
+            TOKEN,
+            OWNER,
+            1_000 + 12 * 37 + 5,
+            0,
+            99,
+            OnMiss::Strict,
+        )
+        .await
+        .unwrap();
+        assert_eq!(bal, Some(U256::from(3_700)));
+    }
+}
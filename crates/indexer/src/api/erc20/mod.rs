@@ -1 +1,78 @@
 pub mod balance;
+
+#[cfg(test)]
+mod tests {
+    use crate::api::Erc20WalletTransfersBuilder;
+    use crate::contracts::erc20::{TRANSFER_SIG, indexed_address_topic};
+    use crate::{EngineBuilder, GetLogsPlan, MockRpc};
+    use alloy::primitives::{Address, B256, Bytes, address};
+    use futures::StreamExt;
+
+    const WALLET: Address = address!("00000000000000000000000000000000000000bb");
+    const OTHER: Address = address!("00000000000000000000000000000000000000cc");
+    const TOKEN: Address = address!("00000000000000000000000000000000000000aa");
+
+    fn transfer(mock: MockRpc, block: u64, from: Address, to: Address) -> MockRpc {
+        let topics = vec![
+            TRANSFER_SIG,
+            indexed_address_topic(from),
+            indexed_address_topic(to),
+        ];
+        mock.log(block, TOKEN, topics, Bytes::new())
+    }
+
+    #[tokio::test]
+    async fn wallet_lanes_split_by_direction() {
+        let mock = MockRpc::new().chain(0, 49, 1_000, 12);
+        let mock = transfer(mock, 3, WALLET, OTHER);
+        let mock = transfer(mock, 7, OTHER, WALLET);
+        let mock = transfer(mock, 9, OTHER, WALLET);
+        let mock = transfer(mock, 11, OTHER, OTHER);
+        let idx = EngineBuilder::new().build_with_clients(vec![mock.client()]);
+
+        let (from_lane, to_lane) = Erc20WalletTransfersBuilder::new(WALLET, 0, 49, B256::ZERO)
+            .chunk_size(20)
+            .lane_plans()
+            .unwrap();
+        let count = |plan: GetLogsPlan| {
+            let idx = &idx;
+            async move {
+                let mut n = 0;
+                let mut s = idx.run_plan(&plan).unwrap();
+                while let Some(r) = s.next().await {
+                    n += r.unwrap().1.len();
+                }
+                n
+            }
+        };
+        assert_eq!(count(from_lane).await, 1);
+        assert_eq!(count(to_lane).await, 2);
+    }
+
+    #[tokio::test]
+    async fn wallet_lanes_filter_by_token() {
+        let mock = transfer(MockRpc::new().chain(0, 9, 1_000, 12), 3, WALLET, OTHER);
+        let idx = EngineBuilder::new().build_with_clients(vec![mock.client()]);
+        let (from_lane, _) = Erc20WalletTransfersBuilder::new(WALLET, 0, 9, B256::ZERO)
+            .tokens(vec![OTHER])
+            .lane_plans()
+            .unwrap();
+        let out: Vec<_> = idx.run_plan(&from_lane).unwrap().collect().await;
+        assert!(out.into_iter().all(|r| r.unwrap().1.is_empty()));
+    }
+
+    #[test]
+    fn wallet_lanes_reject_bad_ranges() {
+        assert!(
+            Erc20WalletTransfersBuilder::new(WALLET, 10, 5, B256::ZERO)
+                .lane_plans()
+                .is_err()
+        );
+        assert!(
+            Erc20WalletTransfersBuilder::new(WALLET, 0, 100, B256::ZERO)
+                .limits(50, 10)
+                .lane_plans()
+                .is_err()
+        );
+    }
+}
@@ -1,8 +1,9 @@
+use crate::error::Result;
 use crate::exec::{OrderingKey, WorkItem};
 use alloy::primitives::Bytes;
 use alloy::rpc::types::eth::{BlockNumberOrTag, TransactionRequest};
 
-pub fn work_one(call: TransactionRequest, at: BlockNumberOrTag) -> anyhow::Result<WorkItem> {
+pub fn work_one(call: TransactionRequest, at: BlockNumberOrTag) -> Result<WorkItem> {
     Ok(WorkItem {
         method: "eth_call",
         params: vec![serde_json::to_value(call)?, serde_json::to_value(at)?],
@@ -10,6 +11,6 @@ pub fn work_one(call: TransactionRequest, at: BlockNumberOrTag) -> anyhow::Resul
     })
 }
 
-pub fn decode_bytes(v: serde_json::Value) -> anyhow::Result<Bytes> {
+pub fn decode_bytes(v: serde_json::Value) -> Result<Bytes> {
     Ok(serde_json::from_value(v)?)
 }
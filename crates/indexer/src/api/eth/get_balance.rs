@@ -4,10 +4,10 @@
 
 use alloy::primitives::{Address, U256};
 use alloy::rpc::types::eth::BlockNumberOrTag;
-use anyhow::{anyhow, bail};
 
 use crate::EthereumIndexer;
 use crate::api::block_time::block_at_or_before_ts;
+use crate::error::{IndexerError, Result};
 use crate::methods::eth::get_balance as m_balance;
 
 /// Builder for fetching one address balance, either at a block/tag, or at a timestamp.
@@ -62,21 +62,21 @@ impl GetBalanceBuilder {
 
     /// Pure planner (no IO) — only valid for the "block/tag" case.
     /// Returns a plan you can feed into the engine’s `run(...)` for batching.
-    pub fn plan(self) -> anyhow::Result<m_balance::GetBalancePlan> {
+    pub fn plan(self) -> Result<m_balance::GetBalancePlan> {
         match self.at {
             At::Block(n) => Ok(m_balance::GetBalancePlan {
                 queries: vec![(self.addr, n)],
             }),
-            At::Timestamp { .. } => {
-                bail!("plan() is not supported for timestamp; call `execute(&indexer).await`")
-            }
+            At::Timestamp { .. } => Err(IndexerError::invalid(
+                "plan() is not supported for timestamp; call `execute(&indexer).await`",
+            )),
         }
     }
 
     /// Execute the request immediately via the engine.
     /// - For block/tag: one RPC call.
     /// - For timestamp: binary-search the block, then call eth_getBalance at that block.
-    pub async fn execute(self, idx: &EthereumIndexer) -> anyhow::Result<U256> {
+    pub async fn execute(self, idx: &EthereumIndexer) -> Result<U256> {
         match self.at {
             At::Block(n) => {
                 let item = m_balance::work_one(self.addr, n)?;
@@ -85,11 +85,17 @@ impl GetBalanceBuilder {
             }
             At::Timestamp { ts_sec, lo, hi } => {
                 if lo == 0 && hi == 0 {
-                    bail!("timestamp query requires bounds: call `.bounds(lo, hi)`");
+                    return Err(IndexerError::invalid(
+                        "timestamp query requires bounds: call `.bounds(lo, hi)`",
+                    ));
                 }
                 let blk = block_at_or_before_ts(idx, ts_sec, lo, hi)
                     .await?
-                    .ok_or_else(|| anyhow!("no block at or before {ts_sec} within [{lo}, {hi}]"))?;
+                    .ok_or_else(|| {
+                        IndexerError::invalid(format!(
+                            "no block at or before {ts_sec} within [{lo}, {hi}]"
+                        ))
+                    })?;
                 let n = BlockNumberOrTag::Number(blk.header.number);
                 let item = m_balance::work_one(self.addr, n)?;
                 let v = idx.run_once(item).await?;
@@ -104,7 +110,7 @@ pub async fn get_balance_at_block(
     idx: &EthereumIndexer,
     addr: Address,
     n: BlockNumberOrTag,
-) -> anyhow::Result<U256> {
+) -> Result<U256> {
     GetBalanceBuilder::new(addr).at_block(n).execute(idx).await
 }
 
@@ -114,7 +120,7 @@ pub async fn get_balance_at_timestamp(
     ts_sec: u64,
     lo: u64,
     hi: u64,
-) -> anyhow::Result<U256> {
+) -> Result<U256> {
     GetBalanceBuilder::new(addr)
         .at_timestamp(ts_sec)
         .bounds(lo, hi)
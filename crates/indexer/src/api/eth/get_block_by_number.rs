@@ -1,3 +1,5 @@
+use crate::Range;
+use crate::error::{IndexerError, Result};
 use crate::methods::eth::get_block_by_number::BlockByNumberPlan;
 use alloy::rpc::types::eth::BlockNumberOrTag;
 
@@ -10,6 +12,12 @@ pub struct BlockByNumberBuilder {
     max_count: usize,
 }
 
+impl Default for BlockByNumberBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl BlockByNumberBuilder {
     pub fn new() -> Self {
         Self {
@@ -63,27 +71,31 @@ impl BlockByNumberBuilder {
     pub fn hashes_only(self) -> Self {
         self.full(false)
     }
+    /// Cap on explicitly listed numbers/tags; a `range` is generated lazily
+    /// and not counted.
     pub fn limit(mut self, max: usize) -> Self {
         self.max_count = max.max(1);
         self
     }
 
-    pub fn plan(mut self) -> anyhow::Result<BlockByNumberPlan> {
-        if let (Some(s), Some(e)) = (self.from, self.to) {
-            if e < s {
-                anyhow::bail!("invalid range: end < start");
+    pub fn plan(self) -> Result<BlockByNumberPlan> {
+        let range = match (self.from, self.to) {
+            (Some(s), Some(e)) if e < s => {
+                return Err(IndexerError::invalid("invalid range: end < start"));
             }
-            self.numbers.extend((s..=e).map(BlockNumberOrTag::Number));
-        }
+            (Some(from), Some(to)) => Some(Range { from, to }),
+            _ => None,
+        };
         if self.numbers.len() > self.max_count {
-            anyhow::bail!(
+            return Err(IndexerError::invalid(format!(
                 "too many block queries ({} > {})",
                 self.numbers.len(),
                 self.max_count
-            );
+            )));
         }
         Ok(BlockByNumberPlan {
             numbers: self.numbers,
+            range,
             full: self.full,
         })
     }
@@ -1,4 +1,5 @@
 use crate::{
+    error::{IndexerError, Result},
     exec::Range,
     methods::eth::get_logs::{GetLogsPlan, Topic},
 };
@@ -79,16 +80,19 @@ impl GetLogsBuilder {
         self
     }
 
-    pub fn plan(self) -> anyhow::Result<GetLogsPlan> {
+    pub fn plan(self) -> Result<GetLogsPlan> {
         if self.to < self.from {
-            anyhow::bail!("invalid range: to < from");
+            return Err(IndexerError::invalid("invalid range: to < from"));
         }
         let blocks = self.to - self.from + 1;
         if blocks > self.max_blocks {
-            anyhow::bail!("range too large: {blocks} > {}", self.max_blocks);
+            return Err(IndexerError::invalid(format!(
+                "range too large: {blocks} > {}",
+                self.max_blocks
+            )));
         }
         if self.addresses.len() > self.max_addresses {
-            anyhow::bail!("too many contract addresses");
+            return Err(IndexerError::invalid("too many contract addresses"));
         }
 
         Ok(GetLogsPlan {
@@ -142,16 +146,18 @@ impl Erc20WalletTransfersBuilder {
         self
     }
 
-    pub fn work_items(self) -> anyhow::Result<Vec<crate::exec::WorkItem>> {
+    /// One `GetLogsPlan` per lane: transfers out of (FROM) and into (TO)
+    /// the watched wallet. Run them separately, their ranges overlap.
+    pub fn lane_plans(self) -> Result<(GetLogsPlan, GetLogsPlan)> {
         if self.to < self.from {
-            anyhow::bail!("invalid range: to < from");
+            return Err(IndexerError::invalid("invalid range: to < from"));
         }
         let blocks = self.to - self.from + 1;
         if blocks > self.max_blocks {
-            anyhow::bail!("range too large");
+            return Err(IndexerError::invalid("range too large"));
         }
         if self.tokens.len() > self.max_tokens {
-            anyhow::bail!("too many token addresses");
+            return Err(IndexerError::invalid("too many token addresses"));
         }
 
         use crate::contracts::erc20::{TRANSFER_SIG, indexed_address_topic};
@@ -182,94 +188,39 @@ impl Erc20WalletTransfersBuilder {
                 to: self.to,
             },
             chunk_size: self.chunk_size,
-            addresses: self.tokens.clone(), // empty => any token
+            addresses: self.tokens, // empty => any token
             topics: vec![],
         };
 
-        let mut out = Vec::new();
-        out.extend(
+        Ok((
             GetLogsPlan {
-                topics: from_topics.clone(),
+                topics: from_topics,
                 ..base.clone()
-            }
-            .plan()?,
-        );
-        out.extend(
+            },
             GetLogsPlan {
-                topics: to_topics.clone(),
+                topics: to_topics,
                 ..base
-            }
-            .plan()?,
-        );
+            },
+        ))
+    }
+
+    pub fn work_items(self) -> Result<Vec<crate::exec::WorkItem>> {
+        let (from_lane, to_lane) = self.lane_plans()?;
+        let mut out = from_lane.plan()?;
+        out.extend(to_lane.plan()?);
         Ok(out)
     }
 
     /// Ergonomic API: returns separate work items for FROM and TO lanes
     pub fn plan_split(
         self,
-    ) -> anyhow::Result<(
+    ) -> Result<(
         Vec<crate::exec::WorkItem>,
         Vec<crate::exec::WorkItem>,
         Range,
     )> {
-        if self.to < self.from {
-            anyhow::bail!("invalid range: to < from");
-        }
-        let blocks = self.to - self.from + 1;
-        if blocks > self.max_blocks {
-            anyhow::bail!("range too large");
-        }
-        if self.tokens.len() > self.max_tokens {
-            anyhow::bail!("too many token addresses");
-        }
-
-        use crate::contracts::erc20::{TRANSFER_SIG, indexed_address_topic};
-        let sig = if self.transfer_sig == B256::ZERO {
-            TRANSFER_SIG
-        } else {
-            self.transfer_sig
-        };
-
-        let range = Range {
-            from: self.from,
-            to: self.to,
-        };
-
-        // FROM lane
-        let from_topics = vec![
-            Topic::One(sig),
-            Topic::One(indexed_address_topic(self.watched)),
-            Topic::Any,
-            Topic::Any,
-        ];
-        // TO lane
-        let to_topics = vec![
-            Topic::One(sig),
-            Topic::Any,
-            Topic::One(indexed_address_topic(self.watched)),
-            Topic::Any,
-        ];
-
-        let base = GetLogsPlan {
-            range: range.clone(),
-            chunk_size: self.chunk_size,
-            addresses: self.tokens.clone(), // empty => any token
-            topics: vec![],
-        };
-
-        let from_items = GetLogsPlan {
-            topics: from_topics,
-            ..base.clone()
-        }
-        .plan()?;
-
-        let to_items = GetLogsPlan {
-            topics: to_topics,
-            ..base
-        }
-        .plan()?;
-
-        Ok((from_items, to_items, range))
+        let (from_lane, to_lane) = self.lane_plans()?;
+        Ok((from_lane.plan()?, to_lane.plan()?, from_lane.range))
     }
 }
 
@@ -303,14 +254,14 @@ impl Erc20TokenTransfersBuilder {
         self
     }
 
-    /// Ergonomic API: returns work items and range directly
-    pub fn plan(self) -> anyhow::Result<(Vec<crate::exec::WorkItem>, Range)> {
+    /// The `GetLogsPlan` for all transfers of the token.
+    pub fn logs_plan(self) -> Result<GetLogsPlan> {
         if self.to < self.from {
-            anyhow::bail!("invalid range: to < from");
+            return Err(IndexerError::invalid("invalid range: to < from"));
         }
         let blocks = self.to - self.from + 1;
         if blocks > self.max_blocks {
-            anyhow::bail!("range too large");
+            return Err(IndexerError::invalid("range too large"));
         }
 
         use crate::contracts::erc20::TRANSFER_SIG;
@@ -320,19 +271,20 @@ impl Erc20TokenTransfersBuilder {
             self.transfer_sig
         };
 
-        let range = Range {
-            from: self.from,
-            to: self.to,
-        };
-
-        let plan = GetLogsPlan {
-            range: range.clone(),
+        Ok(GetLogsPlan {
+            range: Range {
+                from: self.from,
+                to: self.to,
+            },
             chunk_size: self.chunk_size,
             addresses: vec![self.token], // filter by contract
             topics: vec![Topic::One(sig), Topic::Any, Topic::Any, Topic::Any],
-        };
+        })
+    }
 
-        let work_items = plan.plan()?;
-        Ok((work_items, range))
+    /// Ergonomic API: returns work items and range directly
+    pub fn plan(self) -> Result<(Vec<crate::exec::WorkItem>, Range)> {
+        let plan = self.logs_plan()?;
+        Ok((plan.plan()?, plan.range))
     }
 }
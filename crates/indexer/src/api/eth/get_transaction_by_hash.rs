@@ -1,3 +1,4 @@
+use crate::error::{IndexerError, Result};
 use crate::methods::eth::get_transaction_by_hash::TxByHashPlan;
 use alloy::primitives::B256;
 use std::collections::HashSet;
@@ -11,6 +12,12 @@ pub struct TxByHashBuilder {
     max_hashes: usize,
 }
 
+impl Default for TxByHashBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TxByHashBuilder {
     /// Defaults: empty set, limit = 10_000
     pub fn new() -> Self {
@@ -39,13 +46,13 @@ impl TxByHashBuilder {
     }
 
     /// Validate + dedupe (stable) → `TxByHashPlan`.
-    pub fn plan(self) -> anyhow::Result<TxByHashPlan> {
+    pub fn plan(self) -> Result<TxByHashPlan> {
         if self.hashes.len() > self.max_hashes {
-            anyhow::bail!(
+            return Err(IndexerError::invalid(format!(
                 "too many hashes ({} > {})",
                 self.hashes.len(),
                 self.max_hashes
-            );
+            )));
         }
 
         // Stable dedupe: retain first occurrence order.
@@ -1,3 +1,4 @@
+use crate::error::{IndexerError, Result};
 use crate::methods::eth::get_transaction_receipt::TxReceiptPlan;
 use alloy::primitives::B256;
 
@@ -7,6 +8,12 @@ pub struct TxReceiptBuilder {
     max_hashes: usize, // server safety guard
 }
 
+impl Default for TxReceiptBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TxReceiptBuilder {
     pub fn new() -> Self {
         Self {
@@ -31,13 +38,13 @@ impl TxReceiptBuilder {
         self
     }
 
-    pub fn plan(self) -> anyhow::Result<TxReceiptPlan> {
+    pub fn plan(self) -> Result<TxReceiptPlan> {
         if self.hashes.len() > self.max_hashes {
-            anyhow::bail!(
+            return Err(IndexerError::invalid(format!(
                 "too many hashes ({} > {})",
                 self.hashes.len(),
                 self.max_hashes
-            );
+            )));
         }
         // (Optional) dedupe while preserving order
         // use index_of first occurrence
@@ -1,4 +1,8 @@
-use crate::{exec::Range, methods::trace::filter::TraceFilterPlan};
+use crate::{
+    error::{IndexerError, Result},
+    exec::Range,
+    methods::trace::filter::TraceFilterPlan,
+};
 use alloy::{primitives::Address, rpc::types::trace::filter::TraceFilterMode};
 
 pub struct TraceFilterBuilder {
@@ -14,6 +18,12 @@ pub struct TraceFilterBuilder {
     max_span: u64,
     max_chunk: u64,
 }
+impl Default for TraceFilterBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TraceFilterBuilder {
     pub fn new() -> Self {
         Self {
@@ -69,10 +79,13 @@ impl TraceFilterBuilder {
         self
     }
 
-    pub fn plan(self) -> anyhow::Result<TraceFilterPlan> {
+    pub fn plan(self) -> Result<TraceFilterPlan> {
         let span = self.end.saturating_sub(self.start) + 1;
         if span > self.max_span {
-            anyhow::bail!("range too large ({span} > {})", self.max_span);
+            return Err(IndexerError::invalid(format!(
+                "range too large ({span} > {})",
+                self.max_span
+            )));
         }
         let chunk = self.chunk.min(self.max_chunk).max(1);
         Ok(TraceFilterPlan {
@@ -0,0 +1,184 @@
+//! Archive vs pruned endpoints.
+//! Full nodes only keep recent state; `eth_getBalance` / `eth_call` at an
+//! older block fail there with "missing trie node" and similar errors. Such
+//! queries are kept on endpoints that are tagged (or found to be) archives.
+
+use crate::error::IndexerError;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Substrings clients use when the state of a block has been pruned. Kept
+/// narrow: a match tags the endpoint as pruned for good.
+const MISSING_STATE_MARKERS: &[&str] = &[
+    "missing trie node",
+    "state not available",
+    "state is not available",
+];
+
+/// How many blocks behind the head a full node still has state for
+/// (geth's default); older blocks count as historical.
+pub(crate) const RECENT_STATE_BLOCKS: u64 = 128;
+
+/// True if the error says the endpoint no longer has the requested state.
+pub fn is_missing_state_error(e: &IndexerError) -> bool {
+    let msg = match e {
+        IndexerError::Rpc { message, .. } => message.to_lowercase(),
+        IndexerError::Transport { source, .. } => source.to_string().to_lowercase(),
+        _ => return false,
+    };
+    MISSING_STATE_MARKERS.iter().any(|m| msg.contains(m))
+}
+
+/// Methods that read account state at a block.
+pub(crate) fn is_state_query(method: &str) -> bool {
+    matches!(
+        method,
+        "eth_getBalance"
+            | "eth_getCode"
+            | "eth_getTransactionCount"
+            | "eth_getStorageAt"
+            | "eth_getProof"
+            | "eth_call"
+    )
+}
+
+const UNKNOWN: u8 = 0;
+const ARCHIVE: u8 = 1;
+const PRUNED: u8 = 2;
+
+/// Archive tag per endpoint: set by hand, by probing, or learned when an
+/// endpoint of unknown kind answers a state query with a missing-state error.
+pub(crate) struct ArchiveTags(Vec<AtomicU8>);
+
+impl ArchiveTags {
+    pub(crate) fn new(n: usize) -> Self {
+        Self((0..n).map(|_| AtomicU8::new(UNKNOWN)).collect())
+    }
+
+    pub(crate) fn get(&self, idx: usize) -> Option<bool> {
+        match self.0[idx].load(Ordering::Relaxed) {
+            ARCHIVE => Some(true),
+            PRUNED => Some(false),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn set(&self, idx: usize, archive: bool) {
+        let tag = if archive { ARCHIVE } else { PRUNED };
+        self.0[idx].store(tag, Ordering::Relaxed);
+    }
+
+    /// Mark an endpoint of unknown kind as pruned. Returns true if it was unknown.
+    pub(crate) fn learn_pruned(&self, idx: usize) -> bool {
+        self.0[idx]
+            .compare_exchange(UNKNOWN, PRUNED, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    /// Endpoints (outside `skip`) known to be pruned.
+    pub(crate) fn pruned(&self, skip: &[usize]) -> Vec<usize> {
+        (0..self.0.len())
+            .filter(|i| !skip.contains(i) && self.get(*i) == Some(false))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::methods::eth::{get_balance, get_logs::GetLogsPlan};
+    use crate::{EngineBuilder, MockError, MockRpc, Range};
+    use alloy::primitives::{Address, U256};
+    use alloy::rpc::types::eth::BlockNumberOrTag;
+    use futures::StreamExt;
+
+    const WHO: Address = Address::repeat_byte(0x11);
+
+    fn rpc(message: &str) -> IndexerError {
+        IndexerError::Rpc {
+            code: -32000,
+            message: message.to_string(),
+            endpoint: Some(0),
+            range: None,
+        }
+    }
+
+    #[test]
+    fn matches_only_pruning_messages() {
+        assert!(is_missing_state_error(&rpc(
+            "missing trie node 1a2b (path )"
+        )));
+        assert!(is_missing_state_error(&rpc(
+            "historical state is not available"
+        )));
+        assert!(!is_missing_state_error(&rpc(
+            "historical state root mismatch"
+        )));
+        assert!(!is_missing_state_error(&rpc("world state update failed")));
+        assert!(!is_missing_state_error(&rpc("header not found")));
+    }
+
+    fn nodes() -> (MockRpc, MockRpc) {
+        let pruned = MockRpc::new().chain(0, 9, 1_000, 12);
+        pruned.fail(
+            "eth_getBalance",
+            MockError::rpc(-32000, "missing trie node 1a2b"),
+        );
+        let archive = MockRpc::new()
+            .chain(0, 9, 1_000, 12)
+            .balance(WHO, 0, U256::from(7));
+        (pruned, archive)
+    }
+
+    async fn balances(idx: &crate::EthereumIndexer, n: usize) {
+        for _ in 0..n {
+            let item = get_balance::work_one(WHO, BlockNumberOrTag::Number(1)).unwrap();
+            let v = idx.run_once(item).await.unwrap();
+            assert_eq!(
+                get_balance::GetBalancePlan::decode(v).unwrap(),
+                U256::from(7)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn learns_pruned_endpoint_and_reroutes() {
+        let (pruned, archive) = nodes();
+        let idx = EngineBuilder::new().build_with_clients(vec![pruned.client(), archive.client()]);
+        balances(&idx, 6).await;
+        // the first miss tags it; round robin would otherwise send it half the queries
+        assert_eq!(pruned.call_count("eth_getBalance"), 1);
+        assert_eq!(archive.call_count("eth_getBalance"), 6);
+    }
+
+    #[tokio::test]
+    async fn tagged_endpoint_is_never_asked() {
+        let (pruned, archive) = nodes();
+        let idx = EngineBuilder::new()
+            .archive_for(0, false)
+            .build_with_clients(vec![pruned.client(), archive.client()]);
+        balances(&idx, 4).await;
+        assert_eq!(pruned.call_count("eth_getBalance"), 0);
+    }
+
+    #[tokio::test]
+    async fn other_methods_do_not_tag() {
+        let (pruned, archive) = nodes();
+        pruned.fail_next(
+            "eth_getLogs",
+            1,
+            MockError::rpc(-32000, "missing trie node 1a2b"),
+        );
+        let idx = EngineBuilder::new().build_with_clients(vec![pruned.client(), archive.client()]);
+        let plan = GetLogsPlan {
+            range: Range { from: 0, to: 0 },
+            chunk_size: 1,
+            addresses: vec![],
+            topics: vec![],
+        };
+        let out: Vec<_> = idx.run_plan(&plan).unwrap().collect().await;
+        assert!(out.iter().all(|r| r.is_ok()));
+        // still untagged: it gets asked (and learned from) once
+        balances(&idx, 4).await;
+        assert_eq!(pruned.call_count("eth_getBalance"), 1);
+    }
+}
@@ -0,0 +1,155 @@
+//! Range bisection for provider result limits.
+//! When an `eth_getLogs` / `trace_filter` chunk is rejected because it is too
+//! large, split the range in halves (down to a single block) and stitch the
+//! sub-results back together so the caller still sees one value per chunk.
+
+use crate::{
+    error::{IndexerError, Result},
+    exec::Range,
+    pool::ProviderPool,
+};
+use alloy::rpc::types::eth::BlockNumberOrTag;
+use futures::{FutureExt, future::BoxFuture};
+use serde_json::Value;
+
+/// Substrings providers use when a range query exceeds their limits.
+const LIMIT_MARKERS: &[&str] = &[
+    "query returned more than",
+    "block range too large",
+    "block range is too large",
+    "exceed maximum block range",
+    "exceeds max block range",
+    "range is too wide",
+    "response size exceeded",
+    "response is too big",
+    "log response size exceeded",
+    "too many results",
+];
+
+/// True if the error is a JSON-RPC result/range limit rejection.
+pub fn is_range_limit_error(e: &IndexerError) -> bool {
+    let msg = match e {
+        IndexerError::Rpc { message, .. } => message.to_lowercase(),
+        IndexerError::Transport { source, .. } => source.to_string().to_lowercase(),
+        _ => return false,
+    };
+    LIMIT_MARKERS.iter().any(|m| msg.contains(m))
+}
+
+/// Only range filters without pagination can be split safely.
+pub(crate) fn is_splittable(method: &str, params: &[Value]) -> bool {
+    if method != "eth_getLogs" && method != "trace_filter" {
+        return false;
+    }
+    let Some(filter) = params.first().and_then(Value::as_object) else {
+        return false;
+    };
+    let paginated = ["after", "count"]
+        .iter()
+        .any(|k| filter.get(*k).is_some_and(|v| !v.is_null()));
+    !paginated && filter.contains_key("fromBlock") && filter.contains_key("toBlock")
+}
+
+/// Copy of `params` with the filter narrowed to `r`.
+fn with_range(params: &[Value], r: Range) -> Result<Vec<Value>> {
+    let mut out = params.to_vec();
+    let filter = out
+        .first_mut()
+        .and_then(Value::as_object_mut)
+        .ok_or_else(|| IndexerError::invalid("range filter must be an object"))?;
+    filter.insert(
+        "fromBlock".into(),
+        serde_json::to_value(BlockNumberOrTag::Number(r.from))?,
+    );
+    filter.insert(
+        "toBlock".into(),
+        serde_json::to_value(BlockNumberOrTag::Number(r.to))?,
+    );
+    Ok(out)
+}
+
+/// Concatenate two array results (`null` counts as empty).
+fn merge(a: Value, b: Value) -> Result<Value> {
+    let into_vec = |v: Value| match v {
+        Value::Array(v) => Ok(v),
+        Value::Null => Ok(vec![]),
+        other => Err(IndexerError::decode(format!(
+            "cannot merge non-array range result: {other}"
+        ))),
+    };
+    let mut out = into_vec(a)?;
+    out.extend(into_vec(b)?);
+    Ok(Value::Array(out))
+}
+
+/// `rr_request`, but on a limit error split `range` in halves and retry each
+/// half recursively. A single-block range that still fails returns the error.
+pub(crate) fn request_bisecting<'a>(
+    pool: &'a ProviderPool,
+    method: &'static str,
+    params: Vec<Value>,
+    range: Range,
+) -> BoxFuture<'a, Result<Value>> {
+    async move {
+        match pool.rr_request(method, params.clone()).await {
+            Ok(v) => Ok(v),
+            Err(e) if range.from < range.to && is_range_limit_error(&e) => {
+                let mid = range.from + (range.to - range.from) / 2;
+                let lo = Range {
+                    from: range.from,
+                    to: mid,
+                };
+                let hi = Range {
+                    from: mid + 1,
+                    to: range.to,
+                };
+                tracing::debug!(
+                    "{method} {}-{} hit provider limit, splitting at {mid}",
+                    range.from,
+                    range.to
+                );
+                let (a, b) = futures::try_join!(
+                    request_bisecting(pool, method, with_range(&params, lo)?, lo),
+                    request_bisecting(pool, method, with_range(&params, hi)?, hi),
+                )?;
+                merge(a, b)
+            }
+            Err(e) => Err(e),
+        }
+    }
+    .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{EngineBuilder, GetLogsPlan, MockError, MockRpc, OrderingKey, Range};
+    use alloy::primitives::{Address, Bytes};
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn splits_range_on_limit_error() {
+        let mock = (0..8).fold(MockRpc::new().chain(0, 7, 1_000, 12), |m, n| {
+            m.log(n, Address::ZERO, vec![], Bytes::new())
+        });
+        mock.fail_next(
+            "eth_getLogs",
+            1,
+            MockError::rpc(-32005, "query returned more than 10000 results"),
+        );
+        let idx = EngineBuilder::new().build_with_clients(vec![mock.client()]);
+        let plan = GetLogsPlan {
+            range: Range { from: 0, to: 7 },
+            chunk_size: 100,
+            addresses: vec![],
+            topics: vec![],
+        };
+
+        let out: Vec<_> = idx.run_plan(&plan).unwrap().collect().await;
+        assert_eq!(out.len(), 1);
+        let (key, logs) = out.into_iter().next().unwrap().unwrap();
+        assert_eq!(key, OrderingKey::Range(Range { from: 0, to: 7 }));
+        assert_eq!(logs.len(), 8);
+        // the rejected 0..=7 plus its two halves
+        assert_eq!(mock.call_count("eth_getLogs"), 3);
+    }
+}
@@ -0,0 +1,181 @@
+//! Per-endpoint circuit breaker.
+//! Closed -> (N consecutive transport failures) -> Open -> (cooldown) ->
+//! HalfOpen, where a single request is let through as a probe: success closes
+//! the circuit, failure re-opens it for another cooldown.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize)]
+pub enum CircuitState {
+    #[default]
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BreakerConfig {
+    /// Consecutive failures before the circuit opens.
+    pub failure_threshold: u32,
+    /// How long an open circuit rejects traffic before admitting a probe.
+    pub cooldown: Duration,
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    state: CircuitState,
+    failures: u32,
+    opened_at: Option<Instant>,
+    probe_started: Option<Instant>,
+}
+
+impl Inner {
+    // a probe whose future was dropped never reports back; let it lapse
+    fn probing(&self, cfg: &BreakerConfig) -> bool {
+        self.probe_started
+            .is_some_and(|t| t.elapsed() < cfg.cooldown)
+    }
+}
+
+#[derive(Default)]
+pub struct CircuitBreaker {
+    inner: Mutex<Inner>,
+}
+
+impl CircuitBreaker {
+    pub fn state(&self) -> CircuitState {
+        self.inner.lock().unwrap().state
+    }
+
+    /// Could a request be admitted right now? (no state change)
+    pub(crate) fn is_available(&self, cfg: &BreakerConfig) -> bool {
+        let g = self.inner.lock().unwrap();
+        match g.state {
+            CircuitState::Closed => true,
+            CircuitState::HalfOpen => !g.probing(cfg),
+            CircuitState::Open => g.opened_at.is_none_or(|t| t.elapsed() >= cfg.cooldown),
+        }
+    }
+
+    /// Admit a request; an expired open circuit turns half-open and this
+    /// request becomes its probe. Returns false if another probe is running.
+    pub(crate) fn try_admit(&self, cfg: &BreakerConfig) -> bool {
+        let mut g = self.inner.lock().unwrap();
+        match g.state {
+            CircuitState::Closed => true,
+            CircuitState::HalfOpen if !g.probing(cfg) => {
+                g.probe_started = Some(Instant::now());
+                true
+            }
+            CircuitState::HalfOpen => false,
+            CircuitState::Open => {
+                if g.opened_at.is_some_and(|t| t.elapsed() < cfg.cooldown) {
+                    return false;
+                }
+                g.state = CircuitState::HalfOpen;
+                g.probe_started = Some(Instant::now());
+                true
+            }
+        }
+    }
+
+    pub(crate) fn on_success(&self) {
+        let mut g = self.inner.lock().unwrap();
+        *g = Inner::default();
+    }
+
+    pub(crate) fn on_failure(&self, cfg: &BreakerConfig) {
+        let mut g = self.inner.lock().unwrap();
+        g.failures = g.failures.saturating_add(1);
+        g.probe_started = None;
+        let trip = match g.state {
+            CircuitState::HalfOpen => true,
+            CircuitState::Closed => g.failures >= cfg.failure_threshold,
+            CircuitState::Open => false,
+        };
+        if trip {
+            g.state = CircuitState::Open;
+            g.opened_at = Some(Instant::now());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CFG: BreakerConfig = BreakerConfig {
+        failure_threshold: 3,
+        cooldown: Duration::from_millis(30),
+    };
+
+    fn tripped() -> CircuitBreaker {
+        let b = CircuitBreaker::default();
+        for _ in 0..CFG.failure_threshold {
+            assert!(b.try_admit(&CFG));
+            b.on_failure(&CFG);
+        }
+        assert_eq!(b.state(), CircuitState::Open);
+        b
+    }
+
+    #[test]
+    fn opens_after_threshold_and_closes_after_probe() {
+        let b = CircuitBreaker::default();
+        b.on_failure(&CFG);
+        b.on_failure(&CFG);
+        assert_eq!(b.state(), CircuitState::Closed);
+        b.on_success();
+        b.on_failure(&CFG);
+        b.on_failure(&CFG);
+        // the success reset the count
+        assert_eq!(b.state(), CircuitState::Closed);
+
+        let b = tripped();
+        assert!(!b.is_available(&CFG));
+        assert!(!b.try_admit(&CFG));
+
+        std::thread::sleep(CFG.cooldown);
+        assert!(b.is_available(&CFG));
+        assert!(b.try_admit(&CFG));
+        assert_eq!(b.state(), CircuitState::HalfOpen);
+        // one probe at a time
+        assert!(!b.is_available(&CFG));
+        assert!(!b.try_admit(&CFG));
+
+        b.on_success();
+        assert_eq!(b.state(), CircuitState::Closed);
+        assert!(b.try_admit(&CFG));
+    }
+
+    #[test]
+    fn failed_probe_reopens() {
+        let b = tripped();
+        std::thread::sleep(CFG.cooldown);
+        assert!(b.try_admit(&CFG));
+        b.on_failure(&CFG);
+        assert_eq!(b.state(), CircuitState::Open);
+        assert!(!b.try_admit(&CFG));
+    }
+
+    #[test]
+    fn abandoned_probe_lapses() {
+        let b = tripped();
+        std::thread::sleep(CFG.cooldown);
+        assert!(b.try_admit(&CFG));
+        // the probe's future is dropped without reporting back
+        std::thread::sleep(CFG.cooldown);
+        assert!(b.try_admit(&CFG));
+        assert_eq!(b.state(), CircuitState::HalfOpen);
+    }
+}
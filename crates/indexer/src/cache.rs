@@ -0,0 +1,233 @@
+//! Response cache for queries pinned to blocks at or below the finalized
+//! head. Their answers never change, so repeated runs skip the RPC call.
+
+use crate::error::{IndexerError, Result};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+pub trait ResponseCache: Send + Sync {
+    fn get(&self, key: &str) -> Option<Value>;
+    fn put(&self, key: &str, value: &Value);
+}
+
+/// Cache key: method plus params with object keys sorted.
+pub fn cache_key(method: &str, params: &[Value]) -> String {
+    let mut s = String::from(method);
+    for p in params {
+        s.push(':');
+        canonical(p, &mut s);
+    }
+    s
+}
+
+/// `v` as JSON text with object keys sorted, for comparing answers.
+pub(crate) fn canonical_json(v: &Value) -> String {
+    let mut s = String::new();
+    canonical(v, &mut s);
+    s
+}
+
+fn canonical(v: &Value, out: &mut String) {
+    match v {
+        Value::Object(map) => {
+            let sorted: BTreeMap<_, _> = map.iter().collect();
+            out.push('{');
+            for (i, (k, v)) in sorted.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(k.clone()).to_string());
+                out.push(':');
+                canonical(v, out);
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, v) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                canonical(v, out);
+            }
+            out.push(']');
+        }
+        v => out.push_str(&v.to_string()),
+    }
+}
+
+/// Highest block the answer of `method(params)` depends on, if the query is
+/// pinned to block numbers (tags like "latest" are never cacheable).
+pub fn pinned_block(method: &str, params: &[Value]) -> Option<u64> {
+    match method {
+        "eth_getBlockByNumber" => block_number(params.first()?),
+        "eth_getBalance"
+        | "eth_getCode"
+        | "eth_getTransactionCount"
+        | "eth_getStorageAt"
+        | "eth_call" => block_number(params.last()?),
+        "eth_getLogs" | "trace_filter" => {
+            let filter = params.first()?;
+            block_number(filter.get("fromBlock")?)?;
+            block_number(filter.get("toBlock")?)
+        }
+        _ => None,
+    }
+}
+
+fn block_number(v: &Value) -> Option<u64> {
+    u64::from_str_radix(v.as_str()?.strip_prefix("0x")?, 16).ok()
+}
+
+/// One JSON file per entry under `dir`, capped at `max_bytes` in total.
+/// The least recently used entries are evicted first.
+pub struct DiskCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    index: Mutex<Index>,
+}
+
+#[derive(Default)]
+struct Index {
+    total: u64,
+    tick: u64,
+    entries: HashMap<String, (u64, u64)>, // file name -> (size, last use)
+    lru: BTreeMap<u64, String>,           // last use -> file name
+}
+
+impl Index {
+    fn touch(&mut self, name: &str) {
+        self.tick += 1;
+        if let Some((_, used)) = self.entries.get_mut(name) {
+            self.lru.remove(used);
+            *used = self.tick;
+            self.lru.insert(self.tick, name.to_string());
+        }
+    }
+
+    fn insert(&mut self, name: String, size: u64) {
+        self.remove(&name);
+        self.tick += 1;
+        self.total += size;
+        self.lru.insert(self.tick, name.clone());
+        self.entries.insert(name, (size, self.tick));
+    }
+
+    fn remove(&mut self, name: &str) {
+        if let Some((size, used)) = self.entries.remove(name) {
+            self.total -= size;
+            self.lru.remove(&used);
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Entry {
+    key: String,
+    value: Value,
+}
+
+impl DiskCache {
+    pub fn open(dir: impl Into<PathBuf>, max_bytes: u64) -> Result<Self> {
+        let dir = dir.into();
+        Self::scan(&dir, max_bytes)
+            .map_err(|e| IndexerError::Io(format!("opening cache {}: {e}", dir.display())))
+    }
+
+    fn scan(dir: &Path, max_bytes: u64) -> std::io::Result<Self> {
+        let dir = dir.to_path_buf();
+        std::fs::create_dir_all(&dir)?;
+        // oldest files first, so they are evicted first
+        let mut files = Vec::new();
+        for e in std::fs::read_dir(&dir)? {
+            let e = e?;
+            let name = e.file_name().to_string_lossy().into_owned();
+            if !name.ends_with(".json") {
+                continue;
+            }
+            let meta = e.metadata()?;
+            files.push((meta.modified()?, name, meta.len()));
+        }
+        files.sort();
+        let mut index = Index::default();
+        for (_, name, size) in files {
+            index.insert(name, size);
+        }
+        let cache = Self {
+            dir,
+            max_bytes,
+            index: Mutex::new(index),
+        };
+        cache.evict(&mut cache.index.lock().unwrap());
+        Ok(cache)
+    }
+
+    fn file_name(key: &str) -> String {
+        format!("{:x}.json", alloy::primitives::keccak256(key))
+    }
+
+    fn evict(&self, index: &mut Index) {
+        while index.total > self.max_bytes {
+            let Some((_, name)) = index.lru.pop_first() else {
+                break;
+            };
+            if let Some((size, _)) = index.entries.remove(&name) {
+                index.total -= size;
+            }
+            let _ = std::fs::remove_file(self.dir.join(&name));
+        }
+    }
+
+    fn read(&self, name: &str, key: &str) -> Result<Option<Value>> {
+        let entry: Entry = match std::fs::read(self.dir.join(name)) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(IndexerError::Io(e.to_string())),
+        };
+        Ok((entry.key == key).then_some(entry.value))
+    }
+
+    fn write(&self, name: &str, key: &str, value: &Value) -> Result<u64> {
+        let bytes = serde_json::to_vec(&Entry {
+            key: key.to_string(),
+            value: value.clone(),
+        })?;
+        let path = self.dir.join(name);
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, &bytes)
+            .and_then(|()| std::fs::rename(tmp, path))
+            .map_err(|e| IndexerError::Io(e.to_string()))?;
+        Ok(bytes.len() as u64)
+    }
+}
+
+impl ResponseCache for DiskCache {
+    fn get(&self, key: &str) -> Option<Value> {
+        let name = Self::file_name(key);
+        match self.read(&name, key) {
+            Ok(Some(v)) => {
+                self.index.lock().unwrap().touch(&name);
+                Some(v)
+            }
+            Ok(None) => None,
+            Err(e) => {
+                tracing::warn!("cache read {name}: {e}");
+                None
+            }
+        }
+    }
+
+    fn put(&self, key: &str, value: &Value) {
+        let name = Self::file_name(key);
+        match self.write(&name, key, value) {
+            Ok(size) => {
+                let mut index = self.index.lock().unwrap();
+                index.insert(name, size);
+                self.evict(&mut index);
+            }
+            Err(e) => tracing::warn!("cache write {name}: {e}"),
+        }
+    }
+}
@@ -0,0 +1,309 @@
+//! Endpoint probing: chain id, head and which optional method families
+//! (`trace_*`, `debug_*`, historical state) an endpoint actually serves.
+
+use crate::error::{IndexerError, Result};
+use alloy::rpc::client::RpcClient;
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::fmt;
+use std::time::Duration;
+
+/// How long a probe waits for an answer unless a timeout is configured.
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What one endpoint answered to the probes.
+#[derive(Clone, Debug, Serialize)]
+pub struct Capabilities {
+    pub endpoint: String,
+    /// Why the endpoint failed the probes, if it did. It stays in the pool,
+    /// but nothing is known about it and the fields below are unset / false.
+    pub down: Option<String>,
+    pub chain_id: Option<u64>,
+    /// `eth_blockNumber` at probe time.
+    pub head: Option<u64>,
+    /// `trace_filter` works.
+    pub trace: bool,
+    /// `debug_traceCall` works.
+    pub debug: bool,
+    /// State at block 1 is still available (not pruned).
+    pub archive: bool,
+}
+
+impl Capabilities {
+    /// An endpoint that did not answer the probes.
+    pub fn down(endpoint: String, e: &IndexerError) -> Self {
+        Self {
+            endpoint,
+            down: Some(e.to_string()),
+            chain_id: None,
+            head: None,
+            trace: false,
+            debug: false,
+            archive: false,
+        }
+    }
+
+    /// Can this endpoint serve `method`? Unknown method families, and
+    /// endpoints that were down when probed, are assumed to work.
+    pub fn supports(&self, method: &str) -> bool {
+        if self.down.is_some() {
+            true
+        } else if method.starts_with("trace_") {
+            self.trace
+        } else if method.starts_with("debug_") {
+            self.debug
+        } else {
+            true
+        }
+    }
+}
+
+/// Probe results for every endpoint of a pool, in pool order.
+#[derive(Clone, Debug, Serialize)]
+pub struct CapabilityReport {
+    /// `None` if no chain was expected and no endpoint answered.
+    pub chain_id: Option<u64>,
+    pub endpoints: Vec<Capabilities>,
+}
+
+impl fmt::Display for CapabilityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.chain_id {
+            Some(id) => writeln!(f, "chain id {id}")?,
+            None => writeln!(f, "chain id unknown")?,
+        }
+        for (i, c) in self.endpoints.iter().enumerate() {
+            if let Some(e) = &c.down {
+                writeln!(f, "RPC #{i}: {} | down: {e}", c.endpoint)?;
+                continue;
+            }
+            let yes_no = |b: bool| if b { "yes" } else { "no" };
+            writeln!(
+                f,
+                "RPC #{i}: {} | head {} | trace {} | debug {} | archive {}",
+                c.endpoint,
+                c.head.unwrap_or_default(),
+                yes_no(c.trace),
+                yes_no(c.debug),
+                yes_no(c.archive)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Ask `client` (endpoint `idx` of the pool) for its chain id and head, then
+/// try one cheap call of each optional method family. A family counts as
+/// supported if the call succeeds. Each call gives up after `timeout(method)`.
+pub async fn probe(
+    client: &RpcClient,
+    idx: usize,
+    endpoint: String,
+    timeout: impl Fn(&str) -> Duration,
+) -> Result<Capabilities> {
+    let ask = |method: &'static str, params: Value| {
+        let after = timeout(method);
+        async move {
+            tokio::time::timeout(after, client.request::<_, Value>(method, params))
+                .await
+                .map_err(|_| IndexerError::Timeout {
+                    method: method.to_string(),
+                    after,
+                    endpoint: Some(idx),
+                    range: None,
+                })?
+                .map_err(|e| IndexerError::from_transport(e, idx))
+        }
+    };
+    let chain_id = hex_u64(ask("eth_chainId", json!([])).await?, "eth_chainId")?;
+    let head = hex_u64(ask("eth_blockNumber", json!([])).await?, "eth_blockNumber")?;
+    let head_tag = format!("0x{head:x}");
+
+    let trace = ask(
+        "trace_filter",
+        json!([{"fromBlock": head_tag, "toBlock": head_tag, "count": 1}]),
+    );
+    let debug = ask(
+        "debug_traceCall",
+        json!([{"to": ZERO_ADDRESS}, "latest", {"tracer": "callTracer"}]),
+    );
+    let archive = ask("eth_getBalance", json!([ZERO_ADDRESS, "0x1"]));
+    let (trace, debug, archive) = futures::join!(trace, debug, archive);
+    for (family, res) in [("trace", &trace), ("debug", &debug), ("archive", &archive)] {
+        if let Err(e) = res {
+            tracing::debug!("{endpoint}: {family} probe failed: {e}");
+        }
+    }
+
+    Ok(Capabilities {
+        endpoint,
+        down: None,
+        chain_id: Some(chain_id),
+        head: Some(head),
+        trace: trace.is_ok(),
+        debug: debug.is_ok(),
+        archive: archive.is_ok(),
+    })
+}
+
+/// Check that all endpoints that answered serve the same chain: `expected`
+/// if given, otherwise the one most of them are on. `None` if there is
+/// neither. Endpoints that were down are not checked.
+pub fn check_chain(endpoints: &[Capabilities], expected: Option<u64>) -> Result<Option<u64>> {
+    let ids: Vec<(usize, u64)> = endpoints
+        .iter()
+        .enumerate()
+        .filter_map(|(i, c)| Some((i, c.chain_id?)))
+        .collect();
+    let majority = ids
+        .iter()
+        .map(|&(_, id)| id)
+        .max_by_key(|id| ids.iter().filter(|(_, other)| other == id).count());
+    let Some(chain_id) = expected.or(majority) else {
+        return Ok(None);
+    };
+    let wrong: Vec<(usize, u64)> = ids.into_iter().filter(|&(_, id)| id != chain_id).collect();
+    if !wrong.is_empty() {
+        return Err(IndexerError::ChainMismatch {
+            expected: chain_id,
+            endpoints: wrong,
+        });
+    }
+    Ok(Some(chain_id))
+}
+
+const ZERO_ADDRESS: &str = "0x0000000000000000000000000000000000000000";
+
+fn hex_u64(v: Value, method: &str) -> Result<u64> {
+    v.as_str()
+        .and_then(|s| u64::from_str_radix(s.trim_start_matches("0x"), 16).ok())
+        .ok_or_else(|| IndexerError::decode(format!("{method}: unexpected answer {v}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EthereumIndexer, MockError, MockRpc, ProviderPool, TraceFilterPlan};
+    use futures::StreamExt;
+
+    fn probe_timeout(_: &str) -> Duration {
+        Duration::from_millis(100)
+    }
+
+    fn full_node() -> MockRpc {
+        MockRpc::new()
+            .chain_id(1)
+            .chain(0, 9, 1_000, 12)
+            .respond("debug_traceCall", json!({}))
+    }
+
+    fn pruned_node() -> MockRpc {
+        let mock = MockRpc::new().chain_id(1).chain(0, 9, 1_000, 12);
+        mock.fail(
+            "eth_getBalance",
+            MockError::rpc(-32000, "missing trie node"),
+        );
+        mock
+    }
+
+    #[tokio::test]
+    async fn probes_method_families() {
+        let full = probe(&full_node().client(), 0, "full".into(), probe_timeout)
+            .await
+            .unwrap();
+        assert_eq!((full.chain_id, full.head), (Some(1), Some(9)));
+        assert!(full.trace && full.debug && full.archive);
+
+        let mock = pruned_node();
+        mock.fail("trace_filter", MockError::rpc(-32601, "method not found"));
+        let pruned = probe(&mock.client(), 1, "pruned".into(), probe_timeout)
+            .await
+            .unwrap();
+        assert!(!pruned.trace && !pruned.debug && !pruned.archive);
+        assert!(!pruned.supports("trace_filter"));
+        assert!(pruned.supports("eth_getLogs"));
+    }
+
+    #[tokio::test]
+    async fn unresponsive_endpoint_times_out() {
+        let slow = full_node().method_latency("eth_chainId", Duration::from_secs(5));
+        match probe(&slow.client(), 2, "slow".into(), probe_timeout).await {
+            Err(IndexerError::Timeout { endpoint, .. }) => assert_eq!(endpoint, Some(2)),
+            other => panic!("expected Timeout, got {other:?}"),
+        }
+        let down = Capabilities::down("slow".into(), &IndexerError::NoEndpoint);
+        assert!(down.supports("trace_filter"));
+    }
+
+    #[tokio::test]
+    async fn routes_trace_calls_to_capable_endpoints() {
+        let no_trace = full_node();
+        no_trace.fail("trace_filter", MockError::rpc(-32601, "method not found"));
+        let tracer = full_node();
+        let mut caps = Vec::new();
+        for (i, mock) in [&no_trace, &tracer].into_iter().enumerate() {
+            caps.push(
+                probe(&mock.client(), i, i.to_string(), probe_timeout)
+                    .await
+                    .unwrap(),
+            );
+        }
+        let calls_before = no_trace.call_count("trace_filter");
+        let pool = ProviderPool::new(vec![no_trace.client(), tracer.client()], 4)
+            .with_capabilities(Some(caps));
+        let idx = EthereumIndexer::new(pool, 4);
+        let plan = TraceFilterPlan {
+            range: crate::Range { from: 0, to: 9 },
+            chunk_size: 2,
+            from: vec![],
+            to: vec![],
+            mode: None,
+            after: None,
+            count: None,
+        };
+        let out: Vec<_> = idx.run_plan(&plan).unwrap().collect().await;
+        assert_eq!(out.len(), 5);
+        assert!(out.iter().all(|r| r.is_ok()), "{out:?}");
+        assert_eq!(no_trace.call_count("trace_filter"), calls_before);
+    }
+
+    fn caps(chain_id: Option<u64>) -> Capabilities {
+        Capabilities {
+            endpoint: String::new(),
+            down: None,
+            chain_id,
+            head: Some(1),
+            trace: true,
+            debug: true,
+            archive: true,
+        }
+    }
+
+    #[test]
+    fn check_chain_uses_expected_or_majority() {
+        let all = [caps(Some(1)), caps(Some(1)), caps(None)];
+        assert_eq!(check_chain(&all, None).unwrap(), Some(1));
+        assert_eq!(check_chain(&all, Some(1)).unwrap(), Some(1));
+        assert_eq!(check_chain(&[caps(None)], None).unwrap(), None);
+
+        let mixed = [caps(Some(1)), caps(Some(5)), caps(Some(1))];
+        match check_chain(&mixed, None) {
+            Err(IndexerError::ChainMismatch {
+                expected,
+                endpoints,
+            }) => {
+                assert_eq!((expected, endpoints), (1, vec![(1, 5)]));
+            }
+            other => panic!("expected ChainMismatch, got {other:?}"),
+        }
+        match check_chain(&mixed, Some(5)) {
+            Err(IndexerError::ChainMismatch {
+                expected,
+                endpoints,
+            }) => {
+                assert_eq!((expected, endpoints), (5, vec![(0, 1), (2, 1)]));
+            }
+            other => panic!("expected ChainMismatch, got {other:?}"),
+        }
+    }
+}
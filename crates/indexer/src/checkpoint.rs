@@ -0,0 +1,224 @@
+//! Checkpoints for resumable range scans.
+//! A checkpoint is the last block of the highest contiguous range a named job
+//! has finished; a resumed scan starts right after it.
+
+use crate::error::{IndexerError, Result};
+use crate::exec::Range;
+use futures::{Stream, StreamExt, ready};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+pub trait CheckpointStore: Send + Sync {
+    /// Last fully processed block for `job`, if any.
+    fn load(&self, job: &str) -> Result<Option<u64>>;
+    fn save(&self, job: &str, block: u64) -> Result<()>;
+}
+
+/// One small file per job (`<dir>/<job>.checkpoint`) holding the block number.
+pub struct FileCheckpointStore {
+    dir: PathBuf,
+}
+
+impl FileCheckpointStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir).map_err(|e| failed(&dir, e))?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self, job: &str) -> PathBuf {
+        let name: String = job
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        self.dir.join(format!("{name}.checkpoint"))
+    }
+}
+
+impl CheckpointStore for FileCheckpointStore {
+    fn load(&self, job: &str) -> Result<Option<u64>> {
+        let path = self.path(job);
+        match std::fs::read_to_string(&path) {
+            Ok(s) => s.trim().parse().map(Some).map_err(|e| failed(&path, e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(failed(&path, e)),
+        }
+    }
+
+    fn save(&self, job: &str, block: u64) -> Result<()> {
+        // write-then-rename so a crash never leaves a torn file
+        let path = self.path(job);
+        let tmp = path.with_extension("checkpoint.tmp");
+        std::fs::write(&tmp, block.to_string())
+            .and_then(|()| std::fs::rename(&tmp, &path))
+            .map_err(|e| failed(&path, e))
+    }
+}
+
+fn failed(path: &std::path::Path, e: impl std::fmt::Display) -> IndexerError {
+    IndexerError::Checkpoint(format!("{}: {e}", path.display()))
+}
+
+/// The part of `range` still to do for `job`.
+/// Empty (`from > to`) if the job already got past `range.to`.
+pub fn resume_range(range: Range, store: &dyn CheckpointStore, job: &str) -> Result<Range> {
+    Ok(match store.load(job)? {
+        Some(done) if done >= range.from => Range {
+            from: done.saturating_add(1),
+            to: range.to,
+        },
+        _ => range,
+    })
+}
+
+/// Record progress of an ordered stream (see `order_by_range`) under `job`.
+/// A range is checkpointed once the consumer asks for the next item, i.e.
+/// after it has been handled. The checkpoint stops advancing at the first
+/// failed range so a resumed run picks it up again.
+pub fn checkpointed<S, T>(
+    stream: S,
+    store: Arc<dyn CheckpointStore>,
+    job: impl Into<String>,
+) -> impl Stream<Item = Result<(Range, T), IndexerError>>
+where
+    S: Stream<Item = Result<(Range, T), IndexerError>> + Unpin,
+{
+    Checkpointed {
+        inner: stream,
+        store,
+        job: job.into(),
+        pending: None,
+        failed: false,
+    }
+}
+
+struct Checkpointed<S> {
+    inner: S,
+    store: Arc<dyn CheckpointStore>,
+    job: String,
+    pending: Option<u64>,
+    failed: bool,
+}
+
+impl<S> Checkpointed<S> {
+    fn flush(&mut self) {
+        if let Some(block) = self.pending.take()
+            && let Err(e) = self.store.save(&self.job, block)
+        {
+            tracing::warn!("job {}: {e}", self.job);
+        }
+    }
+}
+
+impl<S, T> Stream for Checkpointed<S>
+where
+    S: Stream<Item = Result<(Range, T), IndexerError>> + Unpin,
+{
+    type Item = Result<(Range, T), IndexerError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        this.flush();
+        let item = ready!(this.inner.poll_next_unpin(cx));
+        match &item {
+            Some(Ok((r, _))) if !this.failed => this.pending = Some(r.to),
+            Some(Err(_)) => this.failed = true,
+            _ => {}
+        }
+        Poll::Ready(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EngineBuilder, GetLogsPlan, MockRpc};
+
+    fn store(name: &str) -> (PathBuf, Arc<FileCheckpointStore>) {
+        let dir = std::env::temp_dir().join(format!(
+            "indexer-checkpoint-test-{name}-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = Arc::new(FileCheckpointStore::new(&dir).unwrap());
+        (dir, store)
+    }
+
+    #[test]
+    fn file_store_round_trip() {
+        let (dir, store) = store("file");
+        assert_eq!(store.load("logs/usdc").unwrap(), None);
+        store.save("logs/usdc", 41).unwrap();
+        store.save("logs/usdc", 42).unwrap();
+        let reopened = FileCheckpointStore::new(&dir).unwrap();
+        assert_eq!(reopened.load("logs/usdc").unwrap(), Some(42));
+        assert_eq!(reopened.load("other").unwrap(), None);
+
+        let range = Range { from: 10, to: 99 };
+        assert_eq!(
+            resume_range(range, &reopened, "logs/usdc").unwrap().from,
+            43
+        );
+        assert_eq!(resume_range(range, &reopened, "other").unwrap().from, 10);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn interrupted_scan_resumes_after_checkpoint() {
+        let (dir, store) = store("resume");
+        let mock = MockRpc::new().chain(0, 99, 1_000, 12);
+        let idx = EngineBuilder::new().build_with_clients(vec![mock.client()]);
+        let plan = GetLogsPlan {
+            range: Range { from: 0, to: 49 },
+            chunk_size: 10,
+            addresses: vec![],
+            topics: vec![],
+        };
+
+        let ordered = idx.run_plan_ordered(&plan, 0).unwrap();
+        let mut scan = checkpointed(Box::pin(ordered), store.clone(), "scan");
+        for _ in 0..3 {
+            scan.next().await.unwrap().unwrap();
+        }
+        // the third range was handed out but not acknowledged
+        drop(scan);
+        assert_eq!(store.load("scan").unwrap(), Some(19));
+
+        let resumed = plan.resume(store.as_ref(), "scan").unwrap();
+        assert_eq!(resumed.range, Range { from: 20, to: 49 });
+        let ordered = idx.run_plan_ordered(&resumed, 20).unwrap();
+        let ranges: Vec<_> = checkpointed(Box::pin(ordered), store.clone(), "scan")
+            .map(|r| r.unwrap().0.from)
+            .collect()
+            .await;
+        assert_eq!(ranges, vec![20, 30, 40]);
+        assert_eq!(store.load("scan").unwrap(), Some(49));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn stops_at_first_failed_range() {
+        let (dir, store) = store("failed");
+        let items: Vec<Result<(Range, ())>> = vec![
+            Ok((Range { from: 0, to: 9 }, ())),
+            Err(IndexerError::Missing {
+                range: Range { from: 10, to: 19 },
+            }),
+            Ok((Range { from: 20, to: 29 }, ())),
+        ];
+        let out: Vec<_> = checkpointed(futures::stream::iter(items), store.clone(), "gap")
+            .collect()
+            .await;
+        assert_eq!(out.len(), 3);
+        assert_eq!(store.load("gap").unwrap(), Some(9));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
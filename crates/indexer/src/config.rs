@@ -0,0 +1,385 @@
+//! TOML configuration shared by `indexer-cli` and `indexer-server`:
+//! endpoints (weights, limits, auth), engine policies, per-method chunk
+//! sizes and the server's safety limits. See `indexer.example.toml`.
+//!
+//! String values of the form `${VAR}` in auth and header fields are read
+//! from the environment, so secrets can stay out of the file.
+
+use crate::{
+    api::EngineBuilder,
+    cache::DiskCache,
+    error::{IndexerError, Result},
+    pool::Selection,
+    providers::{EndpointConfig, RpcEndpoint},
+    ratelimit::RateLimit,
+};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IndexerConfig {
+    pub endpoints: Vec<EndpointSection>,
+    /// Concurrent requests per endpoint (binaries pick their own default).
+    pub parallel_per_rpc: Option<usize>,
+    /// Refuse endpoints on another chain (checked by `build_validated()`).
+    pub chain_id: Option<u64>,
+    pub selection: Option<Selection>,
+    pub failover: Option<usize>,
+    /// JSON-RPC batch size for endpoints without their own.
+    pub batch_size: Option<usize>,
+    pub coalesce: Option<bool>,
+    /// Head polling interval; 0 turns head tracking off.
+    pub head_tracking_secs: Option<u64>,
+    pub retry: RetrySection,
+    pub timeouts: TimeoutSection,
+    /// Budget for endpoints without their own `rate_limit`.
+    pub rate_limit: Option<RateLimitSection>,
+    /// Budget units per call, e.g. `trace_filter = 20`.
+    pub method_costs: HashMap<String, f64>,
+    /// Endpoints that must agree per call, e.g. `eth_getLogs = 2`.
+    pub quorum: HashMap<String, usize>,
+    /// Default chunk size (blocks) per method, e.g. `trace_filter = 3000`.
+    pub chunk_sizes: HashMap<String, u64>,
+    pub cache: Option<CacheSection>,
+    pub server: ServerLimits,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EndpointSection {
+    pub url: String,
+    /// Shown in logs and `/api/rpc-info` instead of the URL.
+    pub name: Option<String>,
+    /// Relative share of traffic under `least-outstanding` / `weighted` selection.
+    pub weight: Option<f64>,
+    pub rate_limit: Option<RateLimitSection>,
+    pub batch_size: Option<usize>,
+    /// Archive (`true`) or pruned (`false`) node; probed/learned if unset.
+    pub archive: Option<bool>,
+    pub bearer: Option<String>,
+    pub basic: Option<BasicAuthSection>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+impl EndpointSection {
+    /// An endpoint with nothing but its URL, e.g. from a command-line flag.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            name: None,
+            weight: None,
+            rate_limit: None,
+            batch_size: None,
+            archive: None,
+            bearer: None,
+            basic: None,
+            headers: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BasicAuthSection {
+    pub user: String,
+    pub password: String,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitSection {
+    pub per_second: f64,
+    /// Defaults to one second worth of budget.
+    pub burst: Option<f64>,
+}
+
+impl From<RateLimitSection> for RateLimit {
+    fn from(r: RateLimitSection) -> Self {
+        RateLimit {
+            units_per_sec: r.per_second,
+            burst: r.burst.unwrap_or(r.per_second),
+        }
+    }
+}
+
+/// Retry/backoff of the transport; `max = 0` turns it off.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetrySection {
+    pub max: u32,
+    pub backoff_ms: u64,
+    pub jitter_ms: u64,
+}
+
+impl Default for RetrySection {
+    fn default() -> Self {
+        Self {
+            max: 10,
+            backoff_ms: 1000,
+            jitter_ms: 500,
+        }
+    }
+}
+
+/// Request timeouts and the deadline of a whole run, in seconds; unset = none.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeoutSection {
+    pub request_secs: Option<f64>,
+    /// Per-method overrides, e.g. `trace_filter = 120`.
+    pub methods: HashMap<String, f64>,
+    pub run_deadline_secs: Option<f64>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CacheSection {
+    pub dir: String,
+    #[serde(default = "default_cache_max_mb")]
+    pub max_mb: u64,
+}
+
+fn default_cache_max_mb() -> u64 {
+    1024
+}
+
+/// Caps `indexer-server` puts on a single HTTP request.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerLimits {
+    pub port: u16,
+    /// Blocks per `getBlockByNumber` range request.
+    pub max_block_range: u64,
+    /// Blocks per `getLogs` request.
+    pub max_log_range: u64,
+    /// Blocks per `trace/filter` request, and per trace chunk.
+    pub max_trace_span: u64,
+    pub max_trace_chunk: u64,
+    /// Traces returned before the response is truncated.
+    pub max_trace_results: usize,
+}
+
+impl Default for ServerLimits {
+    fn default() -> Self {
+        Self {
+            port: 8080,
+            max_block_range: 1000,
+            max_log_range: 100_000,
+            max_trace_span: 100_000,
+            max_trace_chunk: 10_000,
+            max_trace_results: 10_000,
+        }
+    }
+}
+
+impl IndexerConfig {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| IndexerError::Io(format!("reading {}: {e}", path.display())))?;
+        toml::from_str(&text).map_err(|e| IndexerError::Config(format!("{}: {e}", path.display())))
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self> {
+        toml::from_str(text).map_err(|e| IndexerError::Config(e.to_string()))
+    }
+
+    /// Configured chunk size for `method`, if any.
+    pub fn chunk_size(&self, method: &str) -> Option<u64> {
+        self.chunk_sizes.get(method).copied()
+    }
+
+    /// Endpoints with their auth and headers resolved (`${VAR}` from the environment).
+    pub fn endpoint_configs(&self) -> Result<Vec<EndpointConfig>> {
+        self.endpoints
+            .iter()
+            .map(|e| {
+                let endpoint: RpcEndpoint = e.url.parse()?;
+                let mut cfg = EndpointConfig::new(endpoint);
+                cfg.name = e.name.clone();
+                if let Some(token) = &e.bearer {
+                    cfg = cfg.bearer(secret(token)?);
+                }
+                if let Some(b) = &e.basic {
+                    cfg = cfg.basic_auth(secret(&b.user)?, secret(&b.password)?);
+                }
+                for (name, value) in &e.headers {
+                    cfg = cfg.header(name, secret(value)?);
+                }
+                Ok(cfg)
+            })
+            .collect()
+    }
+
+    /// An `EngineBuilder` set up from this config; call `build()`,
+    /// `connect()` or `build_validated()` on it.
+    pub fn engine_builder(&self) -> Result<EngineBuilder> {
+        let mut b = EngineBuilder::new().endpoint_configs(self.endpoint_configs()?);
+        if let Some(n) = self.parallel_per_rpc {
+            b = b.parallel_per_rpc(n);
+        }
+        if let Some(id) = self.chain_id {
+            b = b.expect_chain_id(id);
+        }
+        if let Some(s) = self.selection {
+            b = b.selection(s);
+        }
+        if let Some(n) = self.failover {
+            b = b.failover(n);
+        }
+        if let Some(n) = self.batch_size {
+            b = b.batch_size(n);
+        }
+        if let Some(yes) = self.coalesce {
+            b = b.coalesce_requests(yes);
+        }
+        match self.head_tracking_secs {
+            Some(0) => b = b.no_head_tracking(),
+            Some(secs) => b = b.head_tracking(Duration::from_secs(secs)),
+            None => {}
+        }
+        let r = self.retry;
+        if r.max > 0 {
+            b = b.retry(r.max, r.backoff_ms, r.jitter_ms);
+        }
+        let t = &self.timeouts;
+        if let Some(secs) = t.request_secs {
+            b = b.request_timeout(seconds("timeouts.request_secs", secs)?);
+        }
+        for (method, secs) in &t.methods {
+            b = b.method_timeout(method, seconds(method, *secs)?);
+        }
+        if let Some(secs) = t.run_deadline_secs {
+            b = b.run_deadline(seconds("timeouts.run_deadline_secs", secs)?);
+        }
+        if let Some(limit) = self.rate_limit {
+            b = b.rate_limit(limit.into());
+        }
+        for (method, units) in &self.method_costs {
+            b = b.method_cost(method, *units);
+        }
+        for (method, k) in &self.quorum {
+            b = b.quorum(method, *k);
+        }
+        for (i, e) in self.endpoints.iter().enumerate() {
+            if let Some(w) = e.weight {
+                b = b.weight_for(i, w);
+            }
+            if let Some(limit) = e.rate_limit {
+                b = b.rate_limit_for(i, limit.into());
+            }
+            if let Some(n) = e.batch_size {
+                b = b.batch_size_for(i, n);
+            }
+            if let Some(archive) = e.archive {
+                b = b.archive_for(i, archive);
+            }
+        }
+        if let Some(c) = &self.cache {
+            b = b.cache(Arc::new(DiskCache::open(&c.dir, c.max_mb * 1024 * 1024)?));
+        }
+        Ok(b)
+    }
+}
+
+/// `${VAR}` -> value of the environment variable, anything else as is.
+fn secret(value: &str) -> Result<String> {
+    match value.strip_prefix("${").and_then(|v| v.strip_suffix('}')) {
+        Some(var) => std::env::var(var)
+            .map_err(|_| IndexerError::Config(format!("environment variable {var} is not set"))),
+        None => Ok(value.to_string()),
+    }
+}
+
+fn seconds(what: &str, secs: f64) -> Result<Duration> {
+    Duration::try_from_secs_f64(secs)
+        .map_err(|e| IndexerError::Config(format!("{what} = {secs}: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::RpcAuth;
+
+    #[test]
+    fn example_config_parses() {
+        let cfg = IndexerConfig::from_toml(include_str!("../../../indexer.example.toml")).unwrap();
+        assert_eq!(cfg.endpoints.len(), 2);
+        assert_eq!(cfg.selection, Some(Selection::LeastOutstanding));
+        assert_eq!(cfg.chunk_size("trace_filter"), Some(3000));
+        assert_eq!(cfg.chunk_size("eth_call"), None);
+        assert_eq!(cfg.timeouts.methods.get("trace_filter"), Some(&90.0));
+        assert_eq!(
+            cfg.endpoints[1].rate_limit.map(|r| r.per_second),
+            Some(200.0)
+        );
+        assert_eq!(cfg.server.max_block_range, 1000);
+        // defaults fill what the file leaves out
+        assert_eq!(IndexerConfig::from_toml("").unwrap().server.port, 8080);
+    }
+
+    #[test]
+    fn expands_environment_variables() {
+        // SAFETY: no other test reads or writes these variables
+        unsafe {
+            std::env::set_var("INDEXER_CONFIG_TEST_TOKEN", "s3cret");
+            std::env::set_var("INDEXER_CONFIG_TEST_PASSWORD", "hunter2");
+        }
+        let cfg = IndexerConfig::from_toml(
+            r#"
+            [[endpoints]]
+            url = "https://a.example.com"
+            bearer = "${INDEXER_CONFIG_TEST_TOKEN}"
+            headers = { "x-api-key" = "${INDEXER_CONFIG_TEST_TOKEN}", "x-team" = "indexer" }
+
+            [[endpoints]]
+            url = "https://b.example.com"
+            basic = { user = "alice", password = "${INDEXER_CONFIG_TEST_PASSWORD}" }
+            "#,
+        )
+        .unwrap();
+        let eps = cfg.endpoint_configs().unwrap();
+        assert!(matches!(&eps[0].auth, Some(RpcAuth::Bearer(t)) if t == "s3cret"));
+        assert_eq!(
+            eps[0].headers,
+            vec![
+                ("x-api-key".to_string(), "s3cret".to_string()),
+                ("x-team".to_string(), "indexer".to_string()),
+            ]
+        );
+        assert!(matches!(
+            &eps[1].auth,
+            Some(RpcAuth::Basic { user, password }) if user == "alice" && password == "hunter2"
+        ));
+    }
+
+    #[test]
+    fn rejects_bad_configs() {
+        let unset = IndexerConfig::from_toml(
+            r#"
+            [[endpoints]]
+            url = "https://a.example.com"
+            bearer = "${INDEXER_CONFIG_TEST_UNSET}"
+            "#,
+        )
+        .unwrap();
+        match unset.endpoint_configs() {
+            Err(IndexerError::Config(msg)) => assert!(msg.contains("INDEXER_CONFIG_TEST_UNSET")),
+            other => panic!("expected a Config error, got {:?}", other.map(|v| v.len())),
+        }
+        assert!(matches!(
+            IndexerConfig::from_toml("paralel_per_rpc = 4"),
+            Err(IndexerError::Config(_))
+        ));
+        let negative = IndexerConfig::from_toml("[timeouts]\nrequest_secs = -1").unwrap();
+        assert!(matches!(
+            negative.engine_builder(),
+            Err(IndexerError::Config(_))
+        ));
+    }
+}
@@ -0,0 +1,230 @@
+//! Error type of the pool, executor, planners, checkpoints and block-time search.
+//! Keeps what callers need to react differently: the JSON-RPC code, the
+//! endpoint that answered and the block range involved.
+
+use crate::api::block_time::RangeMiss;
+use crate::exec::Range;
+use crate::providers::redact_urls;
+use alloy::transports::TransportError;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub type Result<T, E = IndexerError> = std::result::Result<T, E>;
+
+#[derive(Clone, Debug)]
+pub enum IndexerError {
+    /// The node answered with a JSON-RPC error object.
+    Rpc {
+        code: i64,
+        message: String,
+        endpoint: Option<usize>,
+        range: Option<Range>,
+    },
+    /// The request never got a JSON-RPC answer: timeout, connection, HTTP status.
+    /// Shared so that coalesced callers can all get the error.
+    Transport {
+        source: Arc<TransportError>,
+        endpoint: Option<usize>,
+        range: Option<Range>,
+    },
+    /// The endpoint did not answer within the method's timeout.
+    Timeout {
+        method: String,
+        after: Duration,
+        endpoint: Option<usize>,
+        range: Option<Range>,
+    },
+    /// The run's deadline passed; work items still outstanding were cancelled.
+    DeadlineExceeded { after: Duration },
+    /// A response (or request parameter) did not match the expected shape.
+    Decode {
+        message: String,
+        range: Option<Range>,
+    },
+    /// Invalid query or planner input; retrying will not help.
+    InvalidRequest(String),
+    /// Timestamp outside the searched block range.
+    RangeMiss(RangeMiss),
+    /// A range never produced a result (see `order_by_range`).
+    Missing { range: Range },
+    /// No endpoint could take the request.
+    NoEndpoint,
+    /// No endpoint in the pool serves this method (see `CapabilityReport`).
+    Unsupported(String),
+    /// A checkpoint could not be read or written (see `CheckpointStore`).
+    Checkpoint(String),
+    /// Bad engine or endpoint configuration: URL, header, config file, env var.
+    Config(String),
+    /// A local file (response cache, recording) could not be read or written.
+    Io(String),
+    /// The node returned no block for a tag it should know, e.g. "finalized".
+    BlockNotFound(String),
+    /// Endpoints (index, chain id) on another chain than `expected`.
+    ChainMismatch {
+        expected: u64,
+        endpoints: Vec<(usize, u64)>,
+    },
+    /// Endpoints asked under a quorum policy answered differently and no
+    /// answer had a majority. Each group lists endpoints that agreed.
+    Divergence {
+        method: String,
+        groups: Vec<Vec<usize>>,
+        range: Option<Range>,
+    },
+}
+
+impl IndexerError {
+    pub fn invalid(msg: impl Into<String>) -> Self {
+        IndexerError::InvalidRequest(msg.into())
+    }
+
+    pub fn decode(msg: impl fmt::Display) -> Self {
+        IndexerError::Decode {
+            message: msg.to_string(),
+            range: None,
+        }
+    }
+
+    /// Split an alloy error into a JSON-RPC error response or a transport failure.
+    pub fn from_transport(e: TransportError, endpoint: usize) -> Self {
+        match e.as_error_resp() {
+            Some(payload) => IndexerError::Rpc {
+                code: payload.code,
+                message: payload.message.to_string(),
+                endpoint: Some(endpoint),
+                range: None,
+            },
+            None => IndexerError::Transport {
+                source: Arc::new(e),
+                endpoint: Some(endpoint),
+                range: None,
+            },
+        }
+    }
+
+    /// JSON-RPC error code, if the node answered with one.
+    pub fn code(&self) -> Option<i64> {
+        match self {
+            IndexerError::Rpc { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+
+    /// A request timeout or an exceeded run deadline.
+    pub fn is_timeout(&self) -> bool {
+        matches!(
+            self,
+            IndexerError::Timeout { .. } | IndexerError::DeadlineExceeded { .. }
+        )
+    }
+
+    /// Index (in the pool) of the endpoint that produced the error.
+    pub fn endpoint(&self) -> Option<usize> {
+        match self {
+            IndexerError::Rpc { endpoint, .. }
+            | IndexerError::Transport { endpoint, .. }
+            | IndexerError::Timeout { endpoint, .. } => *endpoint,
+            _ => None,
+        }
+    }
+
+    /// Block range of the work item that failed.
+    pub fn range(&self) -> Option<Range> {
+        match self {
+            IndexerError::Rpc { range, .. }
+            | IndexerError::Transport { range, .. }
+            | IndexerError::Timeout { range, .. }
+            | IndexerError::Decode { range, .. }
+            | IndexerError::Divergence { range, .. } => *range,
+            IndexerError::Missing { range } => Some(*range),
+            _ => None,
+        }
+    }
+
+    /// Attach the range of the failed work item (no-op for variants without one).
+    pub fn with_range(mut self, r: Range) -> Self {
+        match &mut self {
+            IndexerError::Rpc { range, .. }
+            | IndexerError::Transport { range, .. }
+            | IndexerError::Timeout { range, .. }
+            | IndexerError::Decode { range, .. }
+            | IndexerError::Divergence { range, .. } => *range = Some(r),
+            _ => {}
+        }
+        self
+    }
+}
+
+impl fmt::Display for IndexerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(r) = self.range() {
+            write!(f, "blocks {}..={}: ", r.from, r.to)?;
+        }
+        if let Some(i) = self.endpoint() {
+            write!(f, "RPC #{i}: ")?;
+        }
+        match self {
+            IndexerError::Rpc { code, message, .. } => write!(f, "error {code}: {message}"),
+            // HTTP client errors quote the request URL, which may hold an API key
+            IndexerError::Transport { source, .. } => {
+                write!(f, "{}", redact_urls(&source.to_string()))
+            }
+            IndexerError::Timeout { method, after, .. } => {
+                write!(f, "{method} timed out after {after:?}")
+            }
+            IndexerError::DeadlineExceeded { after } => {
+                write!(
+                    f,
+                    "deadline of {after:?} exceeded, outstanding requests cancelled"
+                )
+            }
+            IndexerError::Decode { message, .. } => write!(f, "decode error: {message}"),
+            IndexerError::InvalidRequest(msg) => write!(f, "invalid request: {msg}"),
+            IndexerError::RangeMiss(m) => write!(f, "{m}"),
+            IndexerError::Missing { .. } => write!(f, "no result received"),
+            IndexerError::NoEndpoint => write!(f, "no RPC endpoints available"),
+            IndexerError::Unsupported(method) => write!(f, "no RPC endpoint supports {method}"),
+            IndexerError::Checkpoint(msg) => write!(f, "checkpoint: {msg}"),
+            IndexerError::Config(msg) => write!(f, "configuration: {msg}"),
+            IndexerError::Io(msg) => write!(f, "{msg}"),
+            IndexerError::BlockNotFound(tag) => write!(f, "block {tag} not found"),
+            IndexerError::ChainMismatch {
+                expected,
+                endpoints,
+            } => {
+                let wrong: Vec<String> = endpoints
+                    .iter()
+                    .map(|(i, id)| format!("RPC #{i} is on chain {id}"))
+                    .collect();
+                write!(
+                    f,
+                    "endpoints disagree on the chain (expected {expected}): {}",
+                    wrong.join(", ")
+                )
+            }
+            IndexerError::Divergence { method, groups, .. } => {
+                let groups: Vec<String> = groups.iter().map(|g| format!("{g:?}")).collect();
+                write!(
+                    f,
+                    "{method}: endpoints disagree, no majority: {}",
+                    groups.join(" vs ")
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for IndexerError {}
+
+impl From<serde_json::Error> for IndexerError {
+    fn from(e: serde_json::Error) -> Self {
+        IndexerError::decode(e)
+    }
+}
+
+impl From<RangeMiss> for IndexerError {
+    fn from(m: RangeMiss) -> Self {
+        IndexerError::RangeMiss(m)
+    }
+}
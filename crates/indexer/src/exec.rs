@@ -1,6 +1,18 @@
-use crate::pool::{ProviderPool, RpcStats};
-use futures::StreamExt;
-use std::sync::Arc;
+use crate::{
+    bisect,
+    error::{IndexerError, Result},
+    methods::Plan,
+    order::{DEFAULT_MAX_BUFFERED, Intake, order_by_range_with},
+    pool::ProviderPool,
+    stats::RpcStats,
+};
+use futures::{StreamExt, future::Either};
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Range {
@@ -23,36 +35,132 @@ pub struct WorkItem {
 pub struct EthereumIndexer {
     pool: Arc<ProviderPool>,
     global_parallel: usize,
+    bisect: bool,
+    deadline: Option<Duration>,
 }
 
 impl EthereumIndexer {
     pub fn new(pool: ProviderPool, per_rpc_parallel: usize) -> Self {
         let n = pool.len();
+        let pool = Arc::new(pool);
+        pool.start_head_tracking();
         Self {
-            pool: Arc::new(pool),
+            pool,
             global_parallel: n * per_rpc_parallel,
+            bisect: true,
+            deadline: None,
         }
     }
+    /// Split range items (`eth_getLogs`, `trace_filter`) in halves when the
+    /// provider rejects them for result/range limits. Enabled by default.
+    pub fn bisect_on_limit(mut self, yes: bool) -> Self {
+        self.bisect = yes;
+        self
+    }
+    /// Limit every `run*` / `call_once` to `after`, counted from the call.
+    /// When it passes, outstanding work items are cancelled and the stream
+    /// ends with `IndexerError::DeadlineExceeded`. Off by default.
+    pub fn with_deadline(mut self, after: Option<Duration>) -> Self {
+        self.deadline = after;
+        self
+    }
     pub fn run(
         &self,
         items: Vec<WorkItem>,
-    ) -> impl futures::Stream<Item = anyhow::Result<(OrderingKey, serde_json::Value)>> {
+    ) -> impl futures::Stream<Item = Result<(OrderingKey, serde_json::Value)>> {
+        self.run_stream(futures::stream::iter(items))
+    }
+    /// Like `run`, but pulls items lazily from an iterator (e.g. `plan_iter()`).
+    pub fn run_iter<I>(
+        &self,
+        items: I,
+    ) -> impl futures::Stream<Item = Result<(OrderingKey, serde_json::Value)>>
+    where
+        I: IntoIterator<Item = WorkItem>,
+    {
+        self.run_stream(futures::stream::iter(items))
+    }
+    /// Execute items as they are pulled from `items`. A new item is only
+    /// requested when a concurrency slot frees up, so memory stays bounded by
+    /// the parallelism rather than the size of the scan. A batch (see
+    /// `EngineBuilder::batch_size`) takes one slot for all its calls.
+    pub fn run_stream<S>(
+        &self,
+        items: S,
+    ) -> impl futures::Stream<Item = Result<(OrderingKey, serde_json::Value)>>
+    where
+        S: futures::Stream<Item = WorkItem>,
+    {
         let pool = self.pool.clone();
-        futures::stream::iter(items.into_iter().map(move |w| {
-            let pool = pool.clone();
-            async move {
-                let v = pool.rr_request(w.method, w.params).await?;
-                Ok::<_, anyhow::Error>((w.key, v))
-            }
-        }))
-        .buffer_unordered(self.global_parallel)
+        let bisect = self.bisect;
+        let results = group_for_batching(items, pool.max_batch_size())
+            .map(move |g| {
+                let pool = pool.clone();
+                async move {
+                    if g.len() == 1 {
+                        let w = g.into_iter().next().expect("non-empty group");
+                        let key = w.key;
+                        return vec![run_item(&pool, bisect, w).await.map_err(|e| tag(key, e))];
+                    }
+                    let keys: Vec<OrderingKey> = g.iter().map(|w| w.key).collect();
+                    let calls = g.into_iter().map(|w| (w.method, w.params)).collect();
+                    let results = pool.batch_request(calls).await;
+                    keys.into_iter()
+                        .zip(results)
+                        .map(|(k, r)| r.map(|v| (k, v)).map_err(|e| tag(k, e)))
+                        .collect::<Vec<_>>()
+                }
+            })
+            .buffer_unordered(self.global_parallel)
+            .flat_map(futures::stream::iter);
+        match self.deadline {
+            Some(after) => Either::Left(until_deadline(results, after)),
+            None => Either::Right(results),
+        }
+    }
+    /// Run a plan and decode every result into `P::Output`. Decode failures
+    /// are reported like request failures (tagged with their range).
+    pub fn run_plan<P: Plan>(
+        &self,
+        plan: &P,
+    ) -> Result<impl futures::Stream<Item = Result<(OrderingKey, P::Output)>>> {
+        Ok(decode::<P, _>(self.run_iter(plan.work_iter()?)))
+    }
+    /// Like `run_iter`, with results re-sequenced by range from `start` (see
+    /// `order_by_range`). New items are held back while
+    /// `DEFAULT_MAX_BUFFERED` results wait behind a slow range, so memory
+    /// stays bounded without giving up on it.
+    pub fn run_ordered<I>(
+        &self,
+        items: I,
+        start: u64,
+    ) -> impl futures::Stream<Item = Result<(Range, serde_json::Value)>>
+    where
+        I: IntoIterator<Item = WorkItem>,
+    {
+        let intake = Intake::new(start, DEFAULT_MAX_BUFFERED);
+        let results = self.run_stream(intake.gate(futures::stream::iter(items)));
+        order_by_range_with(results, &intake)
+    }
+    /// `run_plan` with results in range order from `start`, bounded like `run_ordered`.
+    pub fn run_plan_ordered<P: Plan>(
+        &self,
+        plan: &P,
+        start: u64,
+    ) -> Result<impl futures::Stream<Item = Result<(Range, P::Output)>>> {
+        let intake = Intake::new(start, DEFAULT_MAX_BUFFERED);
+        let items = intake.gate(futures::stream::iter(plan.work_iter()?));
+        Ok(order_by_range_with(
+            decode::<P, _>(self.run_stream(items)),
+            &intake,
+        ))
     }
-    pub async fn run_once(&self, item: WorkItem) -> anyhow::Result<serde_json::Value> {
+    pub async fn run_once(&self, item: WorkItem) -> Result<serde_json::Value> {
         let mut s = self.run(vec![item]);
         match s.next().await {
             Some(Ok((_k, v))) => Ok(v),
             Some(Err(e)) => Err(e),
-            None => anyhow::bail!("empty stream for single call"),
+            None => Err(IndexerError::NoEndpoint),
         }
     }
     // syntax sugar to for a one time call
@@ -60,7 +168,7 @@ impl EthereumIndexer {
         &self,
         method: &'static str,
         params: Vec<serde_json::Value>,
-    ) -> anyhow::Result<serde_json::Value> {
+    ) -> Result<serde_json::Value> {
         self.run_once(WorkItem {
             method,
             params,
@@ -71,4 +179,290 @@ impl EthereumIndexer {
     pub fn stats(&self) -> Arc<[RpcStats]> {
         self.pool.stats()
     }
+    /// Display name per endpoint (configured name or redacted URL).
+    pub fn endpoint_names(&self) -> &[String] {
+        self.pool.names()
+    }
+    /// Last seen head per endpoint (see `EngineBuilder::head_tracking`).
+    pub fn heads(&self) -> Vec<Option<u64>> {
+        self.pool.heads()
+    }
+}
+
+async fn run_item(
+    pool: &ProviderPool,
+    bisect: bool,
+    w: WorkItem,
+) -> Result<(OrderingKey, serde_json::Value)> {
+    let v = match w.key {
+        OrderingKey::Range(r) if bisect && bisect::is_splittable(w.method, &w.params) => {
+            bisect::request_bisecting(pool, w.method, w.params, r).await?
+        }
+        _ => pool.rr_request(w.method, w.params).await?,
+    };
+    Ok((w.key, v))
+}
+
+/// Pass `results` through until `after` has passed, then drop the rest
+/// (cancelling what is in flight) and end with a `DeadlineExceeded` error.
+fn until_deadline<S>(results: S, after: Duration) -> impl futures::Stream<Item = S::Item> + Unpin
+where
+    S: futures::Stream<Item = Result<(OrderingKey, serde_json::Value)>>,
+{
+    let expires = tokio::time::Instant::now() + after;
+    // the timer is created on first poll, inside the runtime
+    let state = Some((Box::pin(results), None));
+    // boxed so the stream stays `Unpin`, like it is without a deadline
+    Box::pin(futures::stream::unfold(state, move |state| async move {
+        let (mut results, expired) = state?;
+        let mut expired: Pin<Box<tokio::time::Sleep>> =
+            expired.unwrap_or_else(|| Box::pin(tokio::time::sleep_until(expires)));
+        tokio::select! {
+            biased;
+            item = results.next() => item.map(|item| (item, Some((results, Some(expired))))),
+            () = &mut expired => Some((Err(IndexerError::DeadlineExceeded { after }), None)),
+        }
+    }))
+}
+
+fn decode<P: Plan, S>(results: S) -> impl futures::Stream<Item = Result<(OrderingKey, P::Output)>>
+where
+    S: futures::Stream<Item = Result<(OrderingKey, serde_json::Value)>>,
+{
+    results.map(|r| r.and_then(|(k, v)| P::decode(v).map(|o| (k, o)).map_err(|e| tag(k, e))))
+}
+
+fn tag(key: OrderingKey, e: IndexerError) -> IndexerError {
+    match key {
+        OrderingKey::Range(r) => e.with_range(r),
+        OrderingKey::None => e,
+    }
+}
+
+/// Range scans are heavy; only small point lookups are packed into batches.
+fn is_batchable(method: &str) -> bool {
+    !matches!(method, "eth_getLogs" | "trace_filter")
+}
+
+/// Pack consecutive batchable items into groups of `batch_size`; everything
+/// else runs alone. Groups are formed lazily as items are pulled.
+fn group_for_batching<S>(items: S, batch_size: usize) -> Grouped<S>
+where
+    S: futures::Stream<Item = WorkItem>,
+{
+    Grouped {
+        items: Box::pin(items),
+        batch_size,
+        open: Vec::new(),
+        held: None,
+        done: false,
+    }
+}
+
+struct Grouped<S> {
+    items: Pin<Box<S>>,
+    batch_size: usize,
+    open: Vec<WorkItem>,
+    // non-batchable item held back until the open batch is flushed
+    held: Option<WorkItem>,
+    done: bool,
+}
+
+impl<S: futures::Stream<Item = WorkItem>> futures::Stream for Grouped<S> {
+    type Item = Vec<WorkItem>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        if let Some(w) = this.held.take() {
+            return Poll::Ready(Some(vec![w]));
+        }
+        while !this.done {
+            let w = match this.items.as_mut().poll_next(cx) {
+                Poll::Ready(Some(w)) => w,
+                Poll::Ready(None) => {
+                    this.done = true;
+                    break;
+                }
+                // don't sit on a partial batch while the source is idle
+                Poll::Pending if this.open.is_empty() => return Poll::Pending,
+                Poll::Pending => return Poll::Ready(Some(std::mem::take(&mut this.open))),
+            };
+            if this.batch_size > 1 && is_batchable(w.method) {
+                this.open.push(w);
+                if this.open.len() == this.batch_size {
+                    return Poll::Ready(Some(std::mem::take(&mut this.open)));
+                }
+            } else if this.open.is_empty() {
+                return Poll::Ready(Some(vec![w]));
+            } else {
+                this.held = Some(w);
+                return Poll::Ready(Some(std::mem::take(&mut this.open)));
+            }
+        }
+        if this.open.is_empty() {
+            Poll::Ready(None)
+        } else {
+            Poll::Ready(Some(std::mem::take(&mut this.open)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EngineBuilder, MockRpc};
+    use futures::FutureExt;
+
+    fn item(method: &'static str, n: u64) -> WorkItem {
+        WorkItem {
+            method,
+            params: vec![],
+            key: OrderingKey::Range(Range { from: n, to: n }),
+        }
+    }
+
+    async fn groups(items: Vec<WorkItem>, batch_size: usize) -> Vec<Vec<(&'static str, u64)>> {
+        group_for_batching(futures::stream::iter(items), batch_size)
+            .map(|g| {
+                g.into_iter()
+                    .map(|w| match w.key {
+                        OrderingKey::Range(r) => (w.method, r.from),
+                        OrderingKey::None => (w.method, u64::MAX),
+                    })
+                    .collect()
+            })
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn range_scan_flushes_open_batch() {
+        let items = vec![
+            item("eth_getBlockByNumber", 0),
+            item("eth_getBlockByNumber", 1),
+            item("eth_getLogs", 2),
+            item("eth_getBlockByNumber", 3),
+            item("eth_getBlockByNumber", 4),
+            item("eth_getBlockByNumber", 5),
+            item("eth_getBlockByNumber", 6),
+        ];
+        let got = groups(items, 3).await;
+        assert_eq!(
+            got,
+            vec![
+                vec![("eth_getBlockByNumber", 0), ("eth_getBlockByNumber", 1)],
+                vec![("eth_getLogs", 2)],
+                vec![
+                    ("eth_getBlockByNumber", 3),
+                    ("eth_getBlockByNumber", 4),
+                    ("eth_getBlockByNumber", 5),
+                ],
+                vec![("eth_getBlockByNumber", 6)],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn pending_source_flushes_open_batch() {
+        let items = vec![
+            item("eth_getBlockByNumber", 0),
+            item("eth_getBlockByNumber", 1),
+        ];
+        let source = futures::stream::iter(items).chain(futures::stream::pending());
+        let mut grouped = group_for_batching(source, 3);
+        let first = grouped.next().await.unwrap();
+        assert_eq!(first.len(), 2);
+        assert!(grouped.next().now_or_never().is_none());
+    }
+
+    #[tokio::test]
+    async fn no_batches_without_batch_size() {
+        let items = vec![
+            item("eth_getBlockByNumber", 0),
+            item("eth_getBlockByNumber", 1),
+        ];
+        assert_eq!(groups(items, 1).await.len(), 2);
+    }
+
+    fn block(n: u64) -> WorkItem {
+        crate::methods::eth::get_block_by_number::work_one(
+            alloy::rpc::types::eth::BlockNumberOrTag::Number(n),
+            false,
+        )
+        .unwrap()
+    }
+
+    fn slow_chain(latency: Duration) -> MockRpc {
+        MockRpc::new()
+            .chain(0, 9, 1_000, 12)
+            .method_latency("eth_getBlockByNumber", latency)
+    }
+
+    #[tokio::test]
+    async fn request_timeout_per_method() {
+        let slow = slow_chain(Duration::from_millis(200));
+        let idx = EngineBuilder::new()
+            .request_timeout(Duration::from_millis(30))
+            .build_with_clients(vec![slow.client()]);
+        match idx.run_once(block(1)).await {
+            Err(IndexerError::Timeout {
+                method, endpoint, ..
+            }) => assert_eq!(
+                (method.as_str(), endpoint),
+                ("eth_getBlockByNumber", Some(0))
+            ),
+            other => panic!("expected Timeout, got {other:?}"),
+        }
+
+        let idx = EngineBuilder::new()
+            .request_timeout(Duration::from_millis(30))
+            .method_timeout("eth_getBlockByNumber", Duration::from_secs(2))
+            .build_with_clients(vec![slow.client()]);
+        assert!(idx.run_once(block(1)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn timed_out_calls_fail_over() {
+        let slow = slow_chain(Duration::from_secs(5));
+        let fast = MockRpc::new().chain(0, 9, 1_000, 12);
+        let idx = EngineBuilder::new()
+            .request_timeout(Duration::from_millis(30))
+            .build_with_clients(vec![slow.client(), fast.client()]);
+        let started = std::time::Instant::now();
+        for n in 0..4 {
+            assert!(idx.run_once(block(n)).await.is_ok());
+        }
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(fast.call_count("eth_getBlockByNumber"), 4);
+    }
+
+    #[tokio::test]
+    async fn run_deadline_ends_the_stream() {
+        let slow = slow_chain(Duration::from_millis(50));
+        let idx = EngineBuilder::new()
+            .parallel_per_rpc(1)
+            .run_deadline(Duration::from_millis(175))
+            .build_with_clients(vec![slow.client()]);
+        let out: Vec<_> = idx
+            .run((0..10).map(block).collect::<Vec<_>>())
+            .collect()
+            .await;
+        let (last, done) = out.split_last().unwrap();
+        assert!(
+            matches!(last, Err(IndexerError::DeadlineExceeded { .. })),
+            "{last:?}"
+        );
+        assert!(!done.is_empty() && done.len() < 10);
+        assert!(done.iter().all(|r| r.is_ok()));
+
+        // the deadline is per run
+        assert!(idx.run_once(block(1)).await.is_ok());
+        let idx = EngineBuilder::new()
+            .run_deadline(Duration::from_millis(10))
+            .build_with_clients(vec![slow.client()]);
+        assert!(matches!(
+            idx.run_once(block(1)).await,
+            Err(IndexerError::DeadlineExceeded { .. })
+        ));
+    }
 }
@@ -0,0 +1,116 @@
+//! Per-endpoint chain head, polled in the background, so requests for a
+//! block are not sent to endpoints that haven't seen it yet.
+
+use crate::pool::ProviderPool;
+use serde_json::Value;
+use std::sync::{
+    Weak,
+    atomic::{AtomicU64, Ordering},
+};
+use std::time::Duration;
+
+/// Block a request needs the endpoint to have.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Need {
+    Block(u64),
+    /// "latest" / "pending": the newest head any endpoint has.
+    Latest,
+}
+
+/// What `method(params)` needs, if it reads a specific or the latest block.
+/// Tags like "finalized" or "earliest" are served by any synced endpoint.
+pub(crate) fn needed_block(method: &str, params: &[Value]) -> Option<Need> {
+    let block = match method {
+        "eth_blockNumber" => return Some(Need::Latest),
+        "eth_getBlockByNumber" | "eth_getBlockReceipts" | "trace_block" => params.first()?,
+        "eth_getBalance"
+        | "eth_getCode"
+        | "eth_getTransactionCount"
+        | "eth_getStorageAt"
+        | "eth_call" => params.last()?,
+        "eth_getLogs" | "trace_filter" => params.first()?.get("toBlock")?,
+        _ => return None,
+    };
+    match block.as_str()? {
+        "latest" | "pending" => Some(Need::Latest),
+        _ => hex_u64(block).map(Need::Block),
+    }
+}
+
+fn hex_u64(v: &Value) -> Option<u64> {
+    u64::from_str_radix(v.as_str()?.strip_prefix("0x")?, 16).ok()
+}
+
+/// Last seen head per endpoint; 0 = not known yet.
+pub(crate) struct HeadTracker {
+    heads: Vec<AtomicU64>,
+}
+
+impl HeadTracker {
+    pub(crate) fn new(n: usize) -> Self {
+        Self {
+            heads: (0..n).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    pub(crate) fn get(&self, idx: usize) -> Option<u64> {
+        Some(self.heads[idx].load(Ordering::Relaxed)).filter(|&h| h > 0)
+    }
+
+    /// Highest head seen on any endpoint.
+    pub(crate) fn best(&self) -> Option<u64> {
+        (0..self.heads.len()).filter_map(|i| self.get(i)).max()
+    }
+
+    /// Heads only move forward; a reorg shows up as the same or a higher number.
+    pub(crate) fn update(&self, idx: usize, head: u64) {
+        self.heads[idx].fetch_max(head, Ordering::Relaxed);
+    }
+
+    /// Back to "not known", e.g. after a poll went unanswered.
+    pub(crate) fn forget(&self, idx: usize) {
+        self.heads[idx].store(0, Ordering::Relaxed);
+    }
+
+    /// Endpoints (outside `skip`) whose head is behind what `need` asks for.
+    /// Only compared against the best head among them, so at least that
+    /// endpoint always stays eligible; endpoints with unknown heads are too.
+    pub(crate) fn lagging(&self, need: Need, skip: &[usize]) -> Vec<usize> {
+        let known: Vec<(usize, u64)> = (0..self.heads.len())
+            .filter(|i| !skip.contains(i))
+            .filter_map(|i| Some((i, self.get(i)?)))
+            .collect();
+        let Some(best) = known.iter().map(|&(_, h)| h).max() else {
+            return Vec::new();
+        };
+        let want = match need {
+            Need::Block(n) => n.min(best),
+            Need::Latest => best,
+        };
+        known
+            .into_iter()
+            .filter(|&(_, h)| h < want)
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+/// Poll `eth_blockNumber` on every endpoint of `pool` each `every` until
+/// the pool is dropped. An endpoint that has not answered within `timeout`
+/// gets an unknown head until a later poll succeeds.
+pub(crate) async fn poll_heads(pool: Weak<ProviderPool>, every: Duration, timeout: Duration) {
+    let mut tick = tokio::time::interval(every);
+    tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        tick.tick().await;
+        let Some(pool) = pool.upgrade() else {
+            return;
+        };
+        pool.refresh_heads(timeout).await;
+    }
+}
+
+/// Head from an `eth_blockNumber` answer.
+pub(crate) fn parse_head(v: &Value) -> Option<u64> {
+    hex_u64(v)
+}
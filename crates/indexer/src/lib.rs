@@ -1,10 +1,24 @@
 pub mod api;
+pub mod archive;
+pub mod bisect;
+pub mod breaker;
+pub mod cache;
+pub mod capabilities;
+pub mod checkpoint;
+pub mod config;
 pub mod contracts;
+pub mod error;
 pub mod exec;
+mod head;
 pub mod methods;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
 pub mod order;
 pub mod pool;
 pub mod providers;
+pub mod ratelimit;
+pub mod record;
+pub mod stats;
 
 // API (builders)
 pub use api::{
@@ -19,12 +33,29 @@ pub use api::{
 };
 
 // Core types
+pub use breaker::{BreakerConfig, CircuitState};
+pub use cache::{DiskCache, ResponseCache};
+pub use capabilities::{Capabilities, CapabilityReport};
+pub use checkpoint::{CheckpointStore, FileCheckpointStore, checkpointed};
+pub use config::IndexerConfig;
+pub use error::IndexerError;
 pub use exec::{EthereumIndexer, OrderingKey, Range, WorkItem};
-pub use pool::{ProviderPool, RpcStats};
+pub use methods::Plan;
+#[cfg(any(test, feature = "mock"))]
+pub use mock::{MockError, MockRpc};
+pub use pool::{ProviderPool, Selection};
+pub use ratelimit::RateLimit;
+pub use record::Recorder;
+pub use stats::{
+    EndpointStats, ErrorClass, ErrorStats, LatencyHistogram, LatencyStats, MethodStats, RpcStats,
+};
 
 // Utilities
-pub use order::{chunk_range, order_by_range};
-pub use providers::{build_rpc_clients, build_rpc_clients_with_retry};
+pub use order::{Intake, chunk_range, order_by_range, order_by_range_with, ordering_gauges};
+pub use providers::{
+    EndpointConfig, RpcAuth, RpcEndpoint, build_rpc_clients, build_rpc_clients_with_retry,
+    connect_endpoints, connect_rpc_clients,
+};
 
 // Method planners
 pub use methods::eth::get_block_by_number::BlockByNumberPlan;
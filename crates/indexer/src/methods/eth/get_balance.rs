@@ -1,4 +1,6 @@
+use crate::error::Result;
 use crate::exec::{OrderingKey, WorkItem};
+use crate::methods::Plan;
 use alloy::primitives::{Address, U256};
 use alloy::rpc::types::eth::BlockNumberOrTag;
 
@@ -8,7 +10,7 @@ pub struct GetBalancePlan {
 }
 
 impl GetBalancePlan {
-    pub fn plan(&self) -> anyhow::Result<Vec<WorkItem>> {
+    pub fn plan(&self) -> Result<Vec<WorkItem>> {
         self.queries
             .iter()
             .map(|(addr, n)| {
@@ -21,12 +23,24 @@ impl GetBalancePlan {
             .collect()
     }
 
-    pub fn decode(v: serde_json::Value) -> anyhow::Result<U256> {
+    pub fn decode(v: serde_json::Value) -> Result<U256> {
         Ok(serde_json::from_value(v)?)
     }
 }
 
-pub fn work_one(addr: Address, n: BlockNumberOrTag) -> anyhow::Result<WorkItem> {
+impl Plan for GetBalancePlan {
+    type Output = U256;
+
+    fn work_items(&self) -> Result<Vec<WorkItem>> {
+        self.plan()
+    }
+
+    fn decode(v: serde_json::Value) -> Result<Self::Output> {
+        GetBalancePlan::decode(v)
+    }
+}
+
+pub fn work_one(addr: Address, n: BlockNumberOrTag) -> Result<WorkItem> {
     Ok(WorkItem {
         method: "eth_getBalance",
         params: vec![serde_json::to_value(addr)?, serde_json::to_value(n)?],
@@ -1,6 +1,8 @@
 use crate::{
     Range,
+    error::Result,
     exec::{OrderingKey, WorkItem},
+    methods::Plan,
 };
 use alloy::rpc::types::eth::{Block, BlockNumberOrTag};
 use serde_json::Value;
@@ -8,35 +10,61 @@ use serde_json::Value;
 #[derive(Clone, Debug)]
 pub struct BlockByNumberPlan {
     pub numbers: Vec<BlockNumberOrTag>,
-    pub full: bool, // passed in from builder (defaults to true there)
+    pub range: Option<Range>, // fetched after `numbers`, generated lazily
+    pub full: bool,           // passed in from builder (defaults to true there)
 }
 
 impl BlockByNumberPlan {
-    pub fn plan(&self) -> anyhow::Result<Vec<WorkItem>> {
+    pub fn plan(&self) -> Result<Vec<WorkItem>> {
+        Ok(self.plan_iter()?.collect())
+    }
+
+    /// Lazy counterpart of `plan`: the explicit numbers, then one item per
+    /// block of `range` on demand.
+    pub fn plan_iter(&self) -> Result<impl Iterator<Item = WorkItem> + Send + 'static> {
+        let items = self
+            .numbers
+            .iter()
+            .map(|n| work_one(*n, self.full))
+            .collect::<Result<Vec<_>>>()?;
+        let range = self.range.into_iter().flat_map({
+            let full = self.full;
+            move |r| work_range(r, full)
+        });
+        Ok(items.into_iter().chain(range))
+    }
+
+    /// First block number this plan asks for, if it asks for any by number.
+    pub fn first_number(&self) -> Option<u64> {
         self.numbers
             .iter()
-            .map(|n| {
-                let key = match *n {
-                    BlockNumberOrTag::Number(num) => {
-                        OrderingKey::Range(Range { from: num, to: num })
-                    }
-                    _ => OrderingKey::None, // tags cannot be strictly ordered
-                };
-                Ok(WorkItem {
-                    method: "eth_getBlockByNumber",
-                    params: vec![serde_json::to_value(n)?, serde_json::to_value(self.full)?],
-                    key,
-                })
-            })
-            .collect()
+            .filter_map(|n| n.as_number())
+            .chain(self.range.map(|r| r.from))
+            .min()
     }
 
-    pub fn decode(v: Value) -> anyhow::Result<Option<Block>> {
+    pub fn decode(v: Value) -> Result<Option<Block>> {
         Ok(serde_json::from_value(v)?)
     }
 }
 
-pub fn work_one(n: BlockNumberOrTag, full: bool) -> anyhow::Result<WorkItem> {
+impl Plan for BlockByNumberPlan {
+    type Output = Option<Block>;
+
+    fn work_items(&self) -> Result<Vec<WorkItem>> {
+        self.plan()
+    }
+
+    fn work_iter(&self) -> Result<impl Iterator<Item = WorkItem> + Send> {
+        self.plan_iter()
+    }
+
+    fn decode(v: serde_json::Value) -> Result<Self::Output> {
+        BlockByNumberPlan::decode(v)
+    }
+}
+
+pub fn work_one(n: BlockNumberOrTag, full: bool) -> Result<WorkItem> {
     let key = match n {
         BlockNumberOrTag::Number(u) => OrderingKey::Range(Range { from: u, to: u }),
         _ => OrderingKey::None,
@@ -47,3 +75,15 @@ pub fn work_one(n: BlockNumberOrTag, full: bool) -> anyhow::Result<WorkItem> {
         key,
     })
 }
+
+/// Lazily yield one `eth_getBlockByNumber` item per block in `r`.
+pub fn work_range(r: Range, full: bool) -> impl Iterator<Item = WorkItem> + Send + 'static {
+    (r.from..=r.to).map(move |n| WorkItem {
+        method: "eth_getBlockByNumber",
+        params: vec![
+            serde_json::to_value(BlockNumberOrTag::Number(n)).expect("block number serialize"),
+            Value::Bool(full),
+        ],
+        key: OrderingKey::Range(Range { from: n, to: n }),
+    })
+}
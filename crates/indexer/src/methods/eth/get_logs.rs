@@ -1,5 +1,8 @@
 use crate::{
+    checkpoint::{CheckpointStore, resume_range},
+    error::Result,
     exec::{OrderingKey, Range, WorkItem},
+    methods::Plan,
     order::chunk_range,
 };
 use alloy::primitives::{Address, B256};
@@ -31,7 +34,19 @@ pub struct GetLogsPlan {
 }
 
 impl GetLogsPlan {
-    pub fn plan(&self) -> anyhow::Result<Vec<WorkItem>> {
+    /// Skip the part of `range` that `job` has already checkpointed.
+    pub fn resume(mut self, store: &dyn CheckpointStore, job: &str) -> Result<Self> {
+        self.range = resume_range(self.range, store, job)?;
+        Ok(self)
+    }
+
+    pub fn plan(&self) -> Result<Vec<WorkItem>> {
+        Ok(self.plan_iter().collect())
+    }
+
+    /// Lazy counterpart of `plan`: yields one `WorkItem` per chunk on demand,
+    /// so arbitrarily large ranges use constant memory.
+    pub fn plan_iter(&self) -> impl Iterator<Item = WorkItem> + Send + 'static {
         let topics_json = {
             let mut v = Vec::with_capacity(4);
             for i in 0..4 {
@@ -45,35 +60,47 @@ impl GetLogsPlan {
             serde_json::Value::Array(v)
         };
 
-        let have_addresses = !self.addresses.is_empty();
+        let addresses = (!self.addresses.is_empty())
+            .then(|| serde_json::to_value(&self.addresses).expect("addresses serialize"));
 
-        chunk_range(self.range, self.chunk_size)
-            .map(|r| {
-                let from_v = serde_json::to_value(BlockNumberOrTag::Number(r.from))?;
-                let to_v = serde_json::to_value(BlockNumberOrTag::Number(r.to))?;
-
-                let mut filter = json!({
-                    "fromBlock": from_v,
-                    "toBlock":   to_v,
-                    "topics":    topics_json.clone(),
-                });
-                if have_addresses {
-                    filter
-                        .as_object_mut()
-                        .unwrap()
-                        .insert("address".into(), serde_json::to_value(&self.addresses)?);
-                }
+        chunk_range(self.range, self.chunk_size).map(move |r| {
+            let mut filter = json!({
+                "fromBlock": BlockNumberOrTag::Number(r.from),
+                "toBlock":   BlockNumberOrTag::Number(r.to),
+                "topics":    topics_json.clone(),
+            });
+            if let Some(addresses) = &addresses {
+                filter
+                    .as_object_mut()
+                    .unwrap()
+                    .insert("address".into(), addresses.clone());
+            }
 
-                Ok(WorkItem {
-                    method: "eth_getLogs",
-                    params: vec![filter],
-                    key: OrderingKey::Range(r),
-                })
-            })
-            .collect()
+            WorkItem {
+                method: "eth_getLogs",
+                params: vec![filter],
+                key: OrderingKey::Range(r),
+            }
+        })
     }
 
-    pub fn decode(v: serde_json::Value) -> anyhow::Result<Vec<Log>> {
+    pub fn decode(v: serde_json::Value) -> Result<Vec<Log>> {
         Ok(serde_json::from_value(v)?)
     }
 }
+
+impl Plan for GetLogsPlan {
+    type Output = Vec<Log>;
+
+    fn work_items(&self) -> Result<Vec<WorkItem>> {
+        self.plan()
+    }
+
+    fn work_iter(&self) -> Result<impl Iterator<Item = WorkItem> + Send> {
+        Ok(self.plan_iter())
+    }
+
+    fn decode(v: serde_json::Value) -> Result<Self::Output> {
+        GetLogsPlan::decode(v)
+    }
+}
@@ -1,4 +1,6 @@
+use crate::error::Result;
 use crate::exec::{OrderingKey, WorkItem};
+use crate::methods::Plan;
 use alloy::consensus::Transaction as ConsensusTx; // bring trait methods into scope
 use alloy::primitives::{Address, B256, U256};
 use alloy::rpc::types::eth::Transaction;
@@ -10,7 +12,7 @@ pub struct TxByHashPlan {
 }
 
 impl TxByHashPlan {
-    pub fn plan(&self) -> anyhow::Result<Vec<WorkItem>> {
+    pub fn plan(&self) -> Result<Vec<WorkItem>> {
         self.hashes
             .iter()
             .map(|h| {
@@ -24,11 +26,23 @@ impl TxByHashPlan {
     }
 
     /// Nodes return `null` for unknown → `Ok(None)`.
-    pub fn decode(v: serde_json::Value) -> anyhow::Result<Option<Transaction>> {
+    pub fn decode(v: serde_json::Value) -> Result<Option<Transaction>> {
         Ok(serde_json::from_value(v)?)
     }
 }
 
+impl Plan for TxByHashPlan {
+    type Output = Option<Transaction>;
+
+    fn work_items(&self) -> Result<Vec<WorkItem>> {
+        self.plan()
+    }
+
+    fn decode(v: serde_json::Value) -> Result<Self::Output> {
+        TxByHashPlan::decode(v)
+    }
+}
+
 /// A small normalized view for frontends, using consensus::Transaction trait
 /// via `tx.inner` (Recovered envelope).
 #[derive(Clone, Debug)]
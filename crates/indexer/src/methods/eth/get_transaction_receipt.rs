@@ -1,4 +1,6 @@
+use crate::error::Result;
 use crate::exec::{OrderingKey, WorkItem};
+use crate::methods::Plan;
 use alloy::primitives::B256;
 use alloy::rpc::types::eth::TransactionReceipt;
 
@@ -13,7 +15,7 @@ impl TxReceiptPlan {
     /// Emit one JSON-RPC call per hash: `eth_getTransactionReceipt`.
     /// We use `OrderingKey::Index(i)`-like behavior by just preserving slice order via `None`
     /// or you can add an Index key to keep strict ordering if you want.
-    pub fn plan(&self) -> anyhow::Result<Vec<WorkItem>> {
+    pub fn plan(&self) -> Result<Vec<WorkItem>> {
         self.hashes
             .iter()
             .map(|h| {
@@ -27,7 +29,19 @@ impl TxReceiptPlan {
             .collect()
     }
 
-    pub fn decode(value: serde_json::Value) -> anyhow::Result<Option<TransactionReceipt>> {
+    pub fn decode(value: serde_json::Value) -> Result<Option<TransactionReceipt>> {
         Ok(serde_json::from_value(value)?)
     }
 }
+
+impl Plan for TxReceiptPlan {
+    type Output = Option<TransactionReceipt>;
+
+    fn work_items(&self) -> Result<Vec<WorkItem>> {
+        self.plan()
+    }
+
+    fn decode(v: serde_json::Value) -> Result<Self::Output> {
+        TxReceiptPlan::decode(v)
+    }
+}
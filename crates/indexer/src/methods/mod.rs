@@ -1,2 +1,22 @@
 pub mod eth;
 pub mod trace;
+
+use crate::error::Result;
+use crate::exec::WorkItem;
+
+/// A planner together with the type its results decode to.
+/// `EthereumIndexer::run_plan` runs the work items and decodes each result,
+/// so callers never pair a plan with the wrong `decode`.
+pub trait Plan {
+    type Output: Send + 'static;
+
+    fn work_items(&self) -> Result<Vec<WorkItem>>;
+
+    /// Work items in execution order; planners that can generate them lazily
+    /// override this so large scans stay in constant memory.
+    fn work_iter(&self) -> Result<impl Iterator<Item = WorkItem> + Send> {
+        Ok(self.work_items()?.into_iter())
+    }
+
+    fn decode(v: serde_json::Value) -> Result<Self::Output>;
+}
@@ -1,5 +1,8 @@
 use crate::{
+    checkpoint::{CheckpointStore, resume_range},
+    error::Result,
     exec::{OrderingKey, Range, WorkItem},
+    methods::Plan,
     order::chunk_range,
 };
 use alloy::primitives::Address;
@@ -19,29 +22,56 @@ pub struct TraceFilterPlan {
 }
 
 impl TraceFilterPlan {
-    pub fn plan(&self) -> anyhow::Result<Vec<WorkItem>> {
+    /// Skip the part of `range` that `job` has already checkpointed.
+    pub fn resume(mut self, store: &dyn CheckpointStore, job: &str) -> Result<Self> {
+        self.range = resume_range(self.range, store, job)?;
+        Ok(self)
+    }
+
+    pub fn plan(&self) -> Result<Vec<WorkItem>> {
+        Ok(self.plan_iter().collect())
+    }
+
+    /// Lazy counterpart of `plan`: chunks are turned into `WorkItem`s as the
+    /// executor pulls them.
+    pub fn plan_iter(&self) -> impl Iterator<Item = WorkItem> + Send + 'static {
         let mode = self.mode.unwrap_or(TraceFilterMode::Union);
-        chunk_range(self.range, self.chunk_size)
-            .map(|r| {
-                let filter = TraceFilter {
-                    from_block: Some(r.from),
-                    to_block: Some(r.to),
-                    from_address: self.from.clone(),
-                    to_address: self.to.clone(),
-                    mode,
-                    after: self.after,
-                    count: self.count,
-                };
-                Ok(WorkItem {
-                    method: "trace_filter",
-                    params: vec![serde_json::to_value(filter)?],
-                    key: OrderingKey::Range(r),
-                })
-            })
-            .collect()
+        let (from, to, after, count) = (self.from.clone(), self.to.clone(), self.after, self.count);
+        chunk_range(self.range, self.chunk_size).map(move |r| {
+            let filter = TraceFilter {
+                from_block: Some(r.from),
+                to_block: Some(r.to),
+                from_address: from.clone(),
+                to_address: to.clone(),
+                mode,
+                after,
+                count,
+            };
+            WorkItem {
+                method: "trace_filter",
+                params: vec![serde_json::to_value(filter).expect("trace filter serialize")],
+                key: OrderingKey::Range(r),
+            }
+        })
     }
 
-    pub fn decode(value: serde_json::Value) -> anyhow::Result<Vec<LocalizedTransactionTrace>> {
+    pub fn decode(value: serde_json::Value) -> Result<Vec<LocalizedTransactionTrace>> {
         Ok(serde_json::from_value(value)?)
     }
 }
+
+impl Plan for TraceFilterPlan {
+    type Output = Vec<LocalizedTransactionTrace>;
+
+    fn work_items(&self) -> Result<Vec<WorkItem>> {
+        self.plan()
+    }
+
+    fn work_iter(&self) -> Result<impl Iterator<Item = WorkItem> + Send> {
+        Ok(self.plan_iter())
+    }
+
+    fn decode(v: serde_json::Value) -> Result<Self::Output> {
+        TraceFilterPlan::decode(v)
+    }
+}
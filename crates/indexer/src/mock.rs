@@ -0,0 +1,594 @@
+//! In-process JSON-RPC backend for offline tests of code built on the
+//! indexer. A `MockRpc` answers from scripted fixtures (blocks with
+//! timestamps, logs, traces, balances, canned answers per method) and can
+//! delay or fail calls on purpose. `MockRpc::client()` is an ordinary
+//! `RpcClient`, so it goes into `ProviderPool::new` or
+//! `EngineBuilder::build_with_clients` like a real endpoint.
+
+use alloy::{
+    primitives::{Address, B256, Bytes, U256, keccak256},
+    rpc::{
+        client::RpcClient,
+        json_rpc::{ErrorPayload, RequestPacket, Response, ResponsePacket, ResponsePayload},
+    },
+    transports::{TransportError, TransportErrorKind, TransportFut},
+};
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// A failure to inject.
+#[derive(Clone, Debug)]
+pub enum MockError {
+    /// JSON-RPC error object (`code`, `message`), e.g. a revert or a range limit.
+    Rpc { code: i64, message: String },
+    /// HTTP status without a JSON-RPC body, e.g. 429 or 503.
+    Http(u16),
+    /// Connection-level failure.
+    Transport(String),
+}
+
+impl MockError {
+    pub fn rpc(code: i64, message: impl Into<String>) -> Self {
+        MockError::Rpc {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+type Handler = Arc<dyn Fn(&[Value]) -> Result<Value, MockError> + Send + Sync>;
+type AnyHandler = Arc<dyn Fn(&str, &[Value]) -> Result<Value, MockError> + Send + Sync>;
+
+struct Fault {
+    method: String,
+    /// `None` = every call.
+    remaining: Option<usize>,
+    error: MockError,
+}
+
+#[derive(Default)]
+struct State {
+    chain_id: u64,
+    head: Option<u64>,
+    blocks: BTreeMap<u64, Value>,
+    logs: Vec<Value>,
+    traces: Vec<Value>,
+    /// (address, from block) -> balance; the latest entry at or below the block applies.
+    balances: BTreeMap<(Address, u64), U256>,
+    transactions: HashMap<B256, Value>,
+    receipts: HashMap<B256, Value>,
+    handlers: HashMap<String, Handler>,
+    any_handler: Option<AnyHandler>,
+    latency: Duration,
+    method_latency: HashMap<String, Duration>,
+    faults: Vec<Fault>,
+    calls: Vec<(String, Vec<Value>)>,
+}
+
+/// Scripted JSON-RPC endpoint. Cheap to clone; clones share fixtures and
+/// the call log, so a test can keep one to assert on after handing out clients.
+#[derive(Clone)]
+pub struct MockRpc {
+    state: Arc<Mutex<State>>,
+}
+
+impl Default for MockRpc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockRpc {
+    /// Empty chain with id 1.
+    pub fn new() -> Self {
+        let state = State {
+            chain_id: 1,
+            ..Default::default()
+        };
+        Self {
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// An `RpcClient` talking to this backend.
+    pub fn client(&self) -> RpcClient {
+        RpcClient::new(self.clone(), true)
+    }
+
+    pub fn chain_id(self, id: u64) -> Self {
+        self.state.lock().unwrap().chain_id = id;
+        self
+    }
+
+    /// Answer `eth_blockNumber` (and "latest") with `n` instead of the highest block.
+    pub fn head(self, n: u64) -> Self {
+        self.state.lock().unwrap().head = Some(n);
+        self
+    }
+
+    /// Add block `number` with `timestamp` (and no transactions).
+    pub fn block(self, number: u64, timestamp: u64) -> Self {
+        self.block_json(block_json(number, timestamp))
+    }
+
+    /// Blocks `from..=to`, the first at `first_timestamp`, one every `block_time` seconds.
+    pub fn chain(mut self, from: u64, to: u64, first_timestamp: u64, block_time: u64) -> Self {
+        for n in from..=to {
+            self = self.block(n, first_timestamp + (n - from) * block_time);
+        }
+        self
+    }
+
+    /// Add a block as raw JSON; it is keyed by its `number` field.
+    pub fn block_json(self, block: Value) -> Self {
+        let n = hex_u64(&block["number"]).expect("mock block needs a hex `number`");
+        self.state.lock().unwrap().blocks.insert(n, block);
+        self
+    }
+
+    /// Add a log emitted by `address` in `block`; returned by matching `eth_getLogs` filters.
+    pub fn log(self, block: u64, address: Address, topics: Vec<B256>, data: Bytes) -> Self {
+        let log_index = self.state.lock().unwrap().logs.len() as u64;
+        self.log_json(json!({
+            "address": address,
+            "topics": topics,
+            "data": data,
+            "blockNumber": hex(block),
+            "blockHash": block_hash(block),
+            "transactionHash": tx_hash(block, log_index),
+            "transactionIndex": "0x0",
+            "logIndex": hex(log_index),
+            "removed": false,
+        }))
+    }
+
+    /// Add a log as raw JSON (needs at least `blockNumber` and `address`).
+    pub fn log_json(self, log: Value) -> Self {
+        self.state.lock().unwrap().logs.push(log);
+        self
+    }
+
+    /// Add a top-level call trace from `from` to `to` in `block`; returned by `trace_filter`.
+    pub fn call_trace(self, block: u64, from: Address, to: Address, value: U256) -> Self {
+        let position = self.state.lock().unwrap().traces.len() as u64;
+        self.trace_json(json!({
+            "action": {
+                "callType": "call",
+                "from": from,
+                "to": to,
+                "gas": "0x5208",
+                "input": "0x",
+                "value": value,
+            },
+            "result": { "gasUsed": "0x5208", "output": "0x" },
+            "subtraces": 0,
+            "traceAddress": [],
+            "type": "call",
+            "blockNumber": block,
+            "blockHash": block_hash(block),
+            "transactionHash": tx_hash(block, position),
+            "transactionPosition": position,
+        }))
+    }
+
+    /// Add a trace as raw JSON (needs at least `blockNumber` and `action`).
+    pub fn trace_json(self, trace: Value) -> Self {
+        self.state.lock().unwrap().traces.push(trace);
+        self
+    }
+
+    /// `address` holds `wei` from `block` on (until a later entry).
+    pub fn balance(self, address: Address, block: u64, wei: U256) -> Self {
+        self.state
+            .lock()
+            .unwrap()
+            .balances
+            .insert((address, block), wei);
+        self
+    }
+
+    /// Answer `eth_getTransactionByHash` for the transaction's `hash` field.
+    pub fn transaction(self, tx: Value) -> Self {
+        let hash = tx["hash"].as_str().and_then(|h| h.parse().ok());
+        let hash = hash.expect("mock transaction needs a `hash`");
+        self.state.lock().unwrap().transactions.insert(hash, tx);
+        self
+    }
+
+    /// Answer `eth_getTransactionReceipt` for the receipt's `transactionHash` field.
+    pub fn receipt(self, receipt: Value) -> Self {
+        let hash = receipt["transactionHash"]
+            .as_str()
+            .and_then(|h| h.parse().ok());
+        let hash = hash.expect("mock receipt needs a `transactionHash`");
+        self.state.lock().unwrap().receipts.insert(hash, receipt);
+        self
+    }
+
+    /// Always answer `method` with `result`, ahead of the built-in fixtures.
+    pub fn respond(self, method: &str, result: Value) -> Self {
+        self.respond_with(method, move |_| Ok(result.clone()))
+    }
+
+    /// Answer `method` by calling `f` with the request params, e.g. an
+    /// `eth_call` that depends on the block.
+    pub fn respond_with(
+        self,
+        method: &str,
+        f: impl Fn(&[Value]) -> Result<Value, MockError> + Send + Sync + 'static,
+    ) -> Self {
+        self.state
+            .lock()
+            .unwrap()
+            .handlers
+            .insert(method.to_string(), Arc::new(f));
+        self
+    }
+
+    /// Answer every call by calling `f` with the method and params; fixtures
+    /// and per-method handlers are no longer consulted.
+    pub fn respond_any(
+        self,
+        f: impl Fn(&str, &[Value]) -> Result<Value, MockError> + Send + Sync + 'static,
+    ) -> Self {
+        self.state.lock().unwrap().any_handler = Some(Arc::new(f));
+        self
+    }
+
+    /// Delay every answer by `d`.
+    pub fn latency(self, d: Duration) -> Self {
+        self.state.lock().unwrap().latency = d;
+        self
+    }
+
+    /// Delay answers to `method` by `d` (overrides `latency`).
+    pub fn method_latency(self, method: &str, d: Duration) -> Self {
+        self.state
+            .lock()
+            .unwrap()
+            .method_latency
+            .insert(method.to_string(), d);
+        self
+    }
+
+    /// Fail the next `times` calls of `method` (`"*"` = any method) with `error`.
+    pub fn fail_next(&self, method: &str, times: usize, error: MockError) -> &Self {
+        self.add_fault(method, Some(times), error)
+    }
+
+    /// Fail every call of `method` (`"*"` = any method) with `error`.
+    pub fn fail(&self, method: &str, error: MockError) -> &Self {
+        self.add_fault(method, None, error)
+    }
+
+    /// Drop all injected failures.
+    pub fn heal(&self) {
+        self.state.lock().unwrap().faults.clear();
+    }
+
+    fn add_fault(&self, method: &str, remaining: Option<usize>, error: MockError) -> &Self {
+        self.state.lock().unwrap().faults.push(Fault {
+            method: method.to_string(),
+            remaining,
+            error,
+        });
+        self
+    }
+
+    /// Every call received so far (method, params), batched calls included.
+    pub fn calls(&self) -> Vec<(String, Vec<Value>)> {
+        self.state.lock().unwrap().calls.clone()
+    }
+
+    /// How many calls of `method` were received.
+    pub fn call_count(&self, method: &str) -> usize {
+        let state = self.state.lock().unwrap();
+        state.calls.iter().filter(|(m, _)| m == method).count()
+    }
+
+    async fn handle(self, req: RequestPacket) -> Result<ResponsePacket, TransportError> {
+        let requests = req.requests();
+        let delay = {
+            let state = self.state.lock().unwrap();
+            requests
+                .iter()
+                .map(|r| {
+                    let m = r.method();
+                    state
+                        .method_latency
+                        .get(m)
+                        .copied()
+                        .unwrap_or(state.latency)
+                })
+                .max()
+                .unwrap_or_default()
+        };
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+
+        let mut answers = Vec::with_capacity(requests.len());
+        for r in requests {
+            let params: Vec<Value> = match r.params() {
+                Some(raw) => match serde_json::from_str::<Value>(raw.get()) {
+                    Ok(Value::Array(params)) => params,
+                    Ok(Value::Null) => Vec::new(),
+                    Ok(one) => vec![one],
+                    Err(e) => return Err(TransportErrorKind::custom_str(&e.to_string())),
+                },
+                None => Vec::new(),
+            };
+            answers.push((r.id().clone(), self.answer(r.method(), params)));
+        }
+
+        // answer every call before failing the packet: a failed batch uses up
+        // one answer (or fault) per call, as a recording has it
+        let mut responses = Vec::with_capacity(answers.len());
+        for (id, answer) in answers {
+            let payload = match answer {
+                Ok(v) => ResponsePayload::Success(
+                    serde_json::value::to_raw_value(&v).map_err(TransportError::ser_err)?,
+                ),
+                Err(MockError::Rpc { code, message }) => ResponsePayload::Failure(ErrorPayload {
+                    code,
+                    message: message.into(),
+                    data: None,
+                }),
+                // transport-level failures fail the whole packet, like a real endpoint
+                Err(MockError::Http(status)) => {
+                    return Err(TransportErrorKind::http_error(status, String::new()));
+                }
+                Err(MockError::Transport(msg)) => {
+                    return Err(TransportErrorKind::custom_str(&msg));
+                }
+            };
+            responses.push(Response { id, payload });
+        }
+        Ok(match req {
+            RequestPacket::Single(_) => {
+                ResponsePacket::Single(responses.pop().expect("one response per request"))
+            }
+            RequestPacket::Batch(_) => ResponsePacket::Batch(responses),
+        })
+    }
+
+    fn answer(&self, method: &str, params: Vec<Value>) -> Result<Value, MockError> {
+        let mut state = self.state.lock().unwrap();
+        state.calls.push((method.to_string(), params.clone()));
+        if let Some(fault) = state
+            .faults
+            .iter_mut()
+            .find(|f| (f.method == method || f.method == "*") && f.remaining != Some(0))
+        {
+            if let Some(n) = &mut fault.remaining {
+                *n -= 1;
+            }
+            return Err(fault.error.clone());
+        }
+        if let Some(handler) = state.any_handler.clone() {
+            drop(state);
+            return handler(method, &params);
+        }
+        if let Some(handler) = state.handlers.get(method).cloned() {
+            drop(state);
+            return handler(&params);
+        }
+        state.answer(method, &params)
+    }
+}
+
+impl State {
+    fn head(&self) -> u64 {
+        self.head
+            .or_else(|| self.blocks.keys().next_back().copied())
+            .unwrap_or(0)
+    }
+
+    /// Block number for a number or tag; every block counts as final.
+    fn block_number(&self, v: &Value) -> Result<u64, MockError> {
+        match v.as_str() {
+            None if v.is_null() => Ok(self.head()),
+            Some("latest" | "pending" | "safe" | "finalized") => Ok(self.head()),
+            Some("earliest") => Ok(0),
+            _ => hex_u64(v).ok_or_else(|| invalid(format!("bad block number {v}"))),
+        }
+    }
+
+    fn answer(&self, method: &str, params: &[Value]) -> Result<Value, MockError> {
+        let param = |i: usize| params.get(i).cloned().unwrap_or(Value::Null);
+        match method {
+            "eth_chainId" => Ok(json!(hex(self.chain_id))),
+            "eth_blockNumber" => Ok(json!(hex(self.head()))),
+            "eth_getBlockByNumber" => {
+                let n = self.block_number(&param(0))?;
+                Ok(self.blocks.get(&n).cloned().unwrap_or(Value::Null))
+            }
+            "eth_getBlockByHash" => Ok(self
+                .blocks
+                .values()
+                .find(|b| b["hash"] == param(0))
+                .cloned()
+                .unwrap_or(Value::Null)),
+            "eth_getLogs" => self.logs(&param(0)),
+            "trace_filter" => self.traces(&param(0)),
+            "eth_getBalance" => {
+                let address: Address = serde_json::from_value(param(0))
+                    .map_err(|e| invalid(format!("bad address: {e}")))?;
+                let at = self.block_number(&param(1))?;
+                let wei = self
+                    .balances
+                    .range((address, 0)..=(address, at))
+                    .next_back()
+                    .map(|(_, wei)| *wei)
+                    .unwrap_or_default();
+                Ok(json!(wei))
+            }
+            "eth_getTransactionByHash" | "eth_getTransactionReceipt" => {
+                let hash: B256 = serde_json::from_value(param(0))
+                    .map_err(|e| invalid(format!("bad hash: {e}")))?;
+                let found = if method == "eth_getTransactionByHash" {
+                    self.transactions.get(&hash)
+                } else {
+                    self.receipts.get(&hash)
+                };
+                Ok(found.cloned().unwrap_or(Value::Null))
+            }
+            _ => Err(MockError::rpc(
+                -32601,
+                format!("the method {method} does not exist/is not available"),
+            )),
+        }
+    }
+
+    fn range(&self, filter: &Value) -> Result<(u64, u64), MockError> {
+        let bound = |key: &str, default: u64| match filter.get(key) {
+            Some(v) if !v.is_null() => self.block_number(v),
+            _ => Ok(default),
+        };
+        Ok((
+            bound("fromBlock", self.head())?,
+            bound("toBlock", self.head())?,
+        ))
+    }
+
+    fn logs(&self, filter: &Value) -> Result<Value, MockError> {
+        let (from, to) = self.range(filter)?;
+        let addresses = one_or_many(&filter["address"]);
+        let topics = filter["topics"].as_array().cloned().unwrap_or_default();
+        let matching: Vec<&Value> = self
+            .logs
+            .iter()
+            .filter(|log| {
+                let n = hex_u64(&log["blockNumber"]).unwrap_or(0);
+                (from..=to).contains(&n)
+                    && (addresses.is_empty() || contains_ci(&addresses, &log["address"]))
+                    && topics.iter().enumerate().all(|(i, want)| {
+                        let want = one_or_many(want);
+                        want.is_empty() || contains_ci(&want, &log["topics"][i])
+                    })
+            })
+            .collect();
+        Ok(json!(matching))
+    }
+
+    /// `trace_filter` with `fromAddress` / `toAddress` (union or
+    /// intersection, an empty side does not count), `after` and `count`.
+    fn traces(&self, filter: &Value) -> Result<Value, MockError> {
+        let (from, to) = self.range(filter)?;
+        let senders = one_or_many(&filter["fromAddress"]);
+        let receivers = one_or_many(&filter["toAddress"]);
+        let union = filter["mode"].as_str() != Some("intersection");
+        let matching = self.traces.iter().filter(|t| {
+            let n = t["blockNumber"].as_u64().unwrap_or(0);
+            let from_hit = contains_ci(&senders, &t["action"]["from"]);
+            let to_hit = contains_ci(&receivers, &t["action"]["to"]);
+            let hit = match (senders.is_empty(), receivers.is_empty()) {
+                (true, true) => true,
+                (false, true) => from_hit,
+                (true, false) => to_hit,
+                (false, false) if union => from_hit || to_hit,
+                (false, false) => from_hit && to_hit,
+            };
+            (from..=to).contains(&n) && hit
+        });
+        let after = filter["after"].as_u64().unwrap_or(0) as usize;
+        let count = filter["count"].as_u64().map_or(usize::MAX, |c| c as usize);
+        let page: Vec<&Value> = matching.skip(after).take(count).collect();
+        Ok(json!(page))
+    }
+}
+
+impl tower::Service<RequestPacket> for MockRpc {
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: RequestPacket) -> Self::Future {
+        Box::pin(self.clone().handle(req))
+    }
+}
+
+/// A header complete enough to decode as an alloy `Block`; hashes are
+/// derived from the number so parent links line up.
+fn block_json(number: u64, timestamp: u64) -> Value {
+    let parent = if number == 0 {
+        B256::ZERO
+    } else {
+        block_hash(number - 1)
+    };
+    json!({
+        "number": hex(number),
+        "hash": block_hash(number),
+        "parentHash": parent,
+        "timestamp": hex(timestamp),
+        "sha3Uncles": B256::ZERO,
+        "miner": Address::ZERO,
+        "stateRoot": B256::ZERO,
+        "transactionsRoot": B256::ZERO,
+        "receiptsRoot": B256::ZERO,
+        "logsBloom": Bytes::from(vec![0u8; 256]),
+        "difficulty": "0x0",
+        "gasLimit": "0x1c9c380",
+        "gasUsed": "0x0",
+        "extraData": "0x",
+        "mixHash": B256::ZERO,
+        "nonce": "0x0000000000000000",
+        "baseFeePerGas": "0x7",
+        "size": "0x200",
+        "uncles": [],
+        "transactions": [],
+    })
+}
+
+fn block_hash(number: u64) -> B256 {
+    keccak256(format!("mock block {number}"))
+}
+
+fn tx_hash(block: u64, index: u64) -> B256 {
+    keccak256(format!("mock tx {block}/{index}"))
+}
+
+fn hex(n: u64) -> String {
+    format!("0x{n:x}")
+}
+
+fn hex_u64(v: &Value) -> Option<u64> {
+    match v {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => u64::from_str_radix(s.strip_prefix("0x")?, 16).ok(),
+        _ => None,
+    }
+}
+
+fn invalid(message: String) -> MockError {
+    MockError::Rpc {
+        code: -32602,
+        message,
+    }
+}
+
+/// Filter fields take a single value or a list; null means "any".
+fn one_or_many(v: &Value) -> Vec<Value> {
+    match v {
+        Value::Null => Vec::new(),
+        Value::Array(items) => items.clone(),
+        one => vec![one.clone()],
+    }
+}
+
+// addresses and hashes may differ in hex case
+fn contains_ci(set: &[Value], v: &Value) -> bool {
+    let Some(v) = v.as_str() else {
+        return false;
+    };
+    set.iter()
+        .filter_map(Value::as_str)
+        .any(|s| s.eq_ignore_ascii_case(v))
+}
@@ -1,77 +1,257 @@
-use crate::exec::{OrderingKey, Range};
-use futures::{Stream, StreamExt};
+use crate::error::{IndexerError, Result};
+use crate::exec::{OrderingKey, Range, WorkItem};
+use futures::{Stream, StreamExt, ready, task::AtomicWaker};
 use std::collections::BTreeMap;
 use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::task::{Context, Poll};
 
-pub fn order_by_range<S, T>(stream: S, start: u64) -> impl Stream<Item = anyhow::Result<(Range, T)>>
+/// Default cap on results held back while waiting for an earlier range.
+pub const DEFAULT_MAX_BUFFERED: usize = 10_000;
+
+static ACTIVE_STREAMS: AtomicUsize = AtomicUsize::new(0);
+static BUFFERED_ITEMS: AtomicUsize = AtomicUsize::new(0);
+
+/// Process-wide (live ordered streams, results they hold back), for metrics.
+pub fn ordering_gauges() -> (usize, usize) {
+    (
+        ACTIVE_STREAMS.load(Ordering::Relaxed),
+        BUFFERED_ITEMS.load(Ordering::Relaxed),
+    )
+}
+
+/// Backpressure between an ordered stream and the work items feeding its
+/// executor: while the stream holds `max_buffered` results, `gate` lets no
+/// new item through except the one it is waiting for. Requests already in
+/// flight still complete, so the buffer exceeds the cap by at most the
+/// executor's parallelism.
+#[derive(Clone)]
+pub struct Intake(Arc<IntakeState>);
+
+struct IntakeState {
+    max_buffered: usize,
+    buffered: AtomicUsize,
+    next_expected: AtomicU64,
+    waker: AtomicWaker,
+}
+
+impl Intake {
+    /// For an ordered stream starting at block `start`.
+    pub fn new(start: u64, max_buffered: usize) -> Self {
+        Self(Arc::new(IntakeState {
+            max_buffered: max_buffered.max(1),
+            buffered: AtomicUsize::new(0),
+            next_expected: AtomicU64::new(start),
+            waker: AtomicWaker::new(),
+        }))
+    }
+
+    /// `items`, paused while the ordered stream is full.
+    pub fn gate<S>(&self, items: S) -> impl Stream<Item = WorkItem> + Unpin + use<S>
+    where
+        S: Stream<Item = WorkItem>,
+    {
+        Gated {
+            items: Box::pin(items),
+            held: None,
+            intake: self.clone(),
+        }
+    }
+
+    /// Can `w` start now?
+    fn admits(&self, w: &WorkItem) -> bool {
+        let s = &self.0;
+        match w.key {
+            _ if s.buffered.load(Ordering::Acquire) < s.max_buffered => true,
+            // the range the stream waits for (or one before it) always goes
+            OrderingKey::Range(r) => r.from <= s.next_expected.load(Ordering::Acquire),
+            OrderingKey::None => true,
+        }
+    }
+
+    fn update(&self, buffered: usize, next_expected: u64) {
+        let s = &self.0;
+        s.next_expected.store(next_expected, Ordering::Release);
+        s.buffered.store(buffered, Ordering::Release);
+        if buffered < s.max_buffered {
+            s.waker.wake();
+        }
+    }
+}
+
+struct Gated<S> {
+    items: Pin<Box<S>>,
+    held: Option<WorkItem>,
+    intake: Intake,
+}
+
+impl<S: Stream<Item = WorkItem>> Stream for Gated<S> {
+    type Item = WorkItem;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        let w = match this.held.take() {
+            Some(w) => w,
+            None => match ready!(this.items.as_mut().poll_next(cx)) {
+                Some(w) => w,
+                None => return Poll::Ready(None),
+            },
+        };
+        if this.intake.admits(&w) {
+            return Poll::Ready(Some(w));
+        }
+        this.intake.0.waker.register(cx.waker());
+        // the stream may have drained between the check and registering
+        if this.intake.admits(&w) {
+            return Poll::Ready(Some(w));
+        }
+        this.held = Some(w);
+        Poll::Pending
+    }
+}
+
+/// Re-sequence executor results by `OrderingKey::Range`, starting at `start`.
+///
+/// A failed range (an error with a `range()`) is emitted as an `Err` in its
+/// place and the stream moves past it. Once the inner stream has ended, a
+/// range that never arrived is reported as `IndexerError::Missing` and
+/// skipped. A result for a range before the current position (e.g. an
+/// overlapping one) is passed on as it arrives rather than dropped.
+///
+/// Results waiting for an earlier range are buffered without limit; use
+/// `EthereumIndexer::run_ordered` / `run_plan_ordered` (or
+/// `order_by_range_with` and `Intake::gate`) to bound them.
+pub fn order_by_range<S, T>(stream: S, start: u64) -> impl Stream<Item = Result<(Range, T)>>
+where
+    S: Stream<Item = Result<(OrderingKey, T)>> + Unpin,
+{
+    ordered(stream, start, None)
+}
+
+/// `order_by_range` for a stream whose work items went through `intake.gate`.
+pub fn order_by_range_with<S, T>(
+    stream: S,
+    intake: &Intake,
+) -> impl Stream<Item = Result<(Range, T)>> + use<S, T>
 where
-    S: Stream<Item = anyhow::Result<(OrderingKey, T)>> + Unpin,
+    S: Stream<Item = Result<(OrderingKey, T)>> + Unpin,
 {
+    let start = intake.0.next_expected.load(Ordering::Acquire);
+    ordered(stream, start, Some(intake.clone()))
+}
+
+fn ordered<S, T>(stream: S, start: u64, intake: Option<Intake>) -> OrderedStream<S, T> {
+    ACTIVE_STREAMS.fetch_add(1, Ordering::Relaxed);
     OrderedStream {
         inner: stream,
         buffer: BTreeMap::new(),
         next_expected: start,
+        intake,
+        done: false,
     }
 }
 
 struct OrderedStream<S, T> {
     inner: S,
-    buffer: BTreeMap<u64, (u64, T)>, // from -> (to, value)
+    buffer: BTreeMap<u64, (u64, Result<T>)>, // from -> (to, result)
     next_expected: u64,
+    intake: Option<Intake>,
+    done: bool,
+}
+
+impl<S, T> OrderedStream<S, T> {
+    fn pop_next(&mut self) -> Option<Result<(Range, T)>> {
+        let from = self.next_expected;
+        let (to, res) = self.buffer.remove(&from)?;
+        BUFFERED_ITEMS.fetch_sub(1, Ordering::Relaxed);
+        self.next_expected = to.saturating_add(1);
+        self.sync_intake();
+        Some(res.map(|v| (Range { from, to }, v)))
+    }
+
+    /// Buffer `res`, or hand it back if its range is already behind us.
+    fn accept(&mut self, r: Range, res: Result<T>) -> Option<Result<(Range, T)>> {
+        if r.from < self.next_expected {
+            tracing::warn!(
+                "result for blocks {}..={} arrived after block {}, passing it on out of order",
+                r.from,
+                r.to,
+                self.next_expected
+            );
+            return Some(res.map(|v| (r, v)));
+        }
+        if self.buffer.insert(r.from, (r.to, res)).is_none() {
+            BUFFERED_ITEMS.fetch_add(1, Ordering::Relaxed);
+        }
+        self.sync_intake();
+        None
+    }
+
+    /// Give up on everything before `from` and report it as one failed range.
+    fn skip_to(&mut self, from: u64) -> IndexerError {
+        let range = Range {
+            from: self.next_expected,
+            to: from - 1,
+        };
+        self.next_expected = from;
+        IndexerError::Missing { range }
+    }
+
+    fn sync_intake(&self) {
+        if let Some(intake) = &self.intake {
+            intake.update(self.buffer.len(), self.next_expected);
+        }
+    }
+}
+
+impl<S, T> Drop for OrderedStream<S, T> {
+    fn drop(&mut self) {
+        ACTIVE_STREAMS.fetch_sub(1, Ordering::Relaxed);
+        BUFFERED_ITEMS.fetch_sub(self.buffer.len(), Ordering::Relaxed);
+    }
 }
 
 impl<S, T> Stream for OrderedStream<S, T>
 where
-    S: Stream<Item = anyhow::Result<(OrderingKey, T)>> + Unpin,
+    S: Stream<Item = Result<(OrderingKey, T)>> + Unpin,
 {
-    type Item = anyhow::Result<(Range, T)>;
+    type Item = Result<(Range, T)>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        // 1) If next-needed is already buffered, emit it.
-        let need = self.next_expected;
-        if let Some((to, value)) = self.buffer.remove(&need) {
-            let r = Range { from: need, to };
-            self.next_expected = to + 1;
-            return Poll::Ready(Some(Ok((r, value))));
-        }
+        let this = &mut *self;
+        loop {
+            if let Some(item) = this.pop_next() {
+                return Poll::Ready(Some(item));
+            }
+            // next range is missing: it can't arrive any more once the inner
+            // stream is done
+            if this.done {
+                return match this.buffer.keys().next().copied() {
+                    Some(from) => Poll::Ready(Some(Err(this.skip_to(from)))),
+                    None => Poll::Ready(None),
+                };
+            }
 
-        // 2) Otherwise poll the inner once.
-        match self.inner.poll_next_unpin(cx) {
-            Poll::Ready(Some(Ok((OrderingKey::Range(r), v)))) => {
-                if r.from == self.next_expected {
-                    self.next_expected = r.to + 1;
-                    Poll::Ready(Some(Ok((r, v))))
-                } else {
-                    // out-of-order -> buffer and wait for more data
-                    self.buffer.insert(r.from, (r.to, v));
-
-                    // optional: try one *more* immediate buffer emit
-                    if let Some((to, value)) = self.buffer.remove(&need) {
-                        let r = Range { from: need, to };
-                        self.next_expected = to + 1;
-                        Poll::Ready(Some(Ok((r, value))))
-                    } else {
-                        // no ready item -> Pending; inner will wake us later
-                        Poll::Pending
-                    }
+            let late = match ready!(this.inner.poll_next_unpin(cx)) {
+                Some(Ok((OrderingKey::Range(r), v))) => this.accept(r, Ok(v)),
+                Some(Ok((OrderingKey::None, _v))) => {
+                    return Poll::Ready(Some(Err(IndexerError::invalid(
+                        "Unordered item in ordered stream",
+                    ))));
                 }
-            }
-            Poll::Ready(Some(Ok((OrderingKey::None, _v)))) => Poll::Ready(Some(Err(
-                anyhow::anyhow!("Unordered item in ordered stream"),
-            ))),
-            Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(e))),
-            Poll::Ready(None) => {
-                // Inner is done; if buffer can emit next, emit it; else finish.
-                if let Some((to, value)) = self.buffer.remove(&need) {
-                    let r = Range { from: need, to };
-                    self.next_expected = to + 1;
-                    Poll::Ready(Some(Ok((r, value))))
-                } else {
-                    Poll::Ready(None)
+                Some(Err(e)) => match e.range() {
+                    Some(r) => this.accept(r, Err(e)),
+                    None => return Poll::Ready(Some(Err(e))),
+                },
+                None => {
+                    this.done = true;
+                    None
                 }
+            };
+            if let Some(item) = late {
+                return Poll::Ready(Some(item));
             }
-            Poll::Pending => Poll::Pending,
         }
     }
 }
@@ -88,3 +268,49 @@ pub fn chunk_range(r: Range, chunk_size: u64) -> impl Iterator<Item = Range> {
         Some(out)
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+
+    fn ok(from: u64, to: u64) -> Result<(OrderingKey, u64)> {
+        Ok((OrderingKey::Range(Range { from, to }), from))
+    }
+
+    async fn ordered_froms(items: Vec<Result<(OrderingKey, u64)>>, start: u64) -> Vec<Result<u64>> {
+        order_by_range(stream::iter(items), start)
+            .map(|r| r.map(|(_, v)| v))
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn emits_ranges_in_order() {
+        let out = ordered_froms(vec![ok(20, 29), ok(0, 9), ok(30, 39), ok(10, 19)], 0).await;
+        let froms: Vec<u64> = out.into_iter().map(|r| r.unwrap()).collect();
+        assert_eq!(froms, vec![0, 10, 20, 30]);
+    }
+
+    #[tokio::test]
+    async fn reports_gap_once_stream_ends() {
+        let out = ordered_froms(vec![ok(20, 29), ok(0, 9)], 0).await;
+        assert_eq!(out.len(), 3);
+        assert_eq!(*out[0].as_ref().unwrap(), 0);
+        match &out[1] {
+            Err(IndexerError::Missing { range }) => {
+                assert_eq!(*range, Range { from: 10, to: 19 })
+            }
+            other => panic!("expected Missing, got {other:?}"),
+        }
+        assert_eq!(*out[2].as_ref().unwrap(), 20);
+    }
+
+    #[tokio::test]
+    async fn passes_late_results_on() {
+        // 5..=9 overlaps a range already emitted
+        let out = ordered_froms(vec![ok(0, 9), ok(5, 9), ok(10, 19)], 0).await;
+        let froms: Vec<u64> = out.into_iter().map(|r| r.unwrap()).collect();
+        assert_eq!(froms, vec![0, 5, 10]);
+    }
+}
@@ -1,31 +1,51 @@
-use alloy::rpc::client::RpcClient;
+use crate::{
+    archive::{ArchiveTags, RECENT_STATE_BLOCKS, is_missing_state_error, is_state_query},
+    breaker::BreakerConfig,
+    cache::{ResponseCache, cache_key, canonical_json, pinned_block},
+    capabilities::Capabilities,
+    error::{IndexerError, Result},
+    head::{HeadTracker, Need, needed_block, parse_head, poll_heads},
+    ratelimit::{RateLimit, TokenBucket},
+    record::{RecordedCall, Recorder, Served},
+};
+// kept at its old path
+pub use crate::stats::RpcStats;
+use alloy::{
+    rpc::client::RpcClient,
+    transports::{RpcError, TransportError, TransportErrorKind},
+};
+use std::collections::HashMap;
 use std::sync::{
-    Arc,
-    atomic::{AtomicU64, AtomicUsize, Ordering},
+    Arc, Mutex,
+    atomic::{AtomicBool, AtomicUsize, Ordering},
 };
-use std::time::Instant;
-use tokio::sync::Semaphore;
-
-#[derive(Default)]
-pub struct RpcStats {
-    requests: AtomicU64,
-    successes: AtomicU64,
-    total_ms: AtomicU64,
+use std::time::{Duration, Instant};
+use tokio::sync::{Semaphore, watch};
+
+/// How `ProviderPool` picks the endpoint for the next request.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Selection {
+    /// Blind rotation over all endpoints.
+    #[default]
+    RoundRobin,
+    /// Endpoint with the fewest requests in flight.
+    LeastOutstanding,
+    /// Lowest expected cost: in-flight load x mean latency / success ratio.
+    Weighted,
 }
-impl RpcStats {
-    pub fn record(&self, ok: bool, dur: std::time::Duration) {
-        self.requests.fetch_add(1, Ordering::Relaxed);
-        if ok {
-            self.successes.fetch_add(1, Ordering::Relaxed);
-        }
-        self.total_ms
-            .fetch_add(dur.as_millis() as u64, Ordering::Relaxed);
+
+/// Counts a request against an endpoint until dropped (also on cancellation).
+struct Outstanding<'a>(&'a AtomicUsize);
+impl<'a> Outstanding<'a> {
+    fn enter(c: &'a AtomicUsize) -> Self {
+        c.fetch_add(1, Ordering::Relaxed);
+        Self(c)
     }
-    pub fn snapshot(&self) -> (u64, u64, f64) {
-        let r = self.requests.load(Ordering::Relaxed);
-        let s = self.successes.load(Ordering::Relaxed);
-        let t = self.total_ms.load(Ordering::Relaxed);
-        (r, s, if r > 0 { t as f64 / r as f64 } else { 0.0 })
+}
+impl Drop for Outstanding<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
     }
 }
 
@@ -34,14 +54,61 @@ pub struct ProviderPool {
     permits: Vec<Arc<Semaphore>>,
     stats: Arc<[RpcStats]>,
     rr: AtomicUsize,
+    outstanding: Vec<AtomicUsize>, // picked but not yet finished (queued + in flight)
+    selection: Selection,
+    breaker: Option<BreakerConfig>,
+    failover: usize,
+    buckets: Vec<Option<TokenBucket>>,
+    method_costs: HashMap<String, f64>, // missing => 1.0
+    batch_sizes: Vec<usize>,
+    batch_rejected: Vec<AtomicBool>,
+    cache: Option<Arc<dyn ResponseCache>>,
+    finalized: tokio::sync::Mutex<Option<(Option<u64>, Instant)>>, // head, checked at
+    chain_id: tokio::sync::OnceCell<u64>, // namespaces cache keys; probed or asked once
+    coalesce: bool,
+    in_flight: Mutex<HashMap<String, Answer>>, // cache_key -> answer of the leading call
+    quorum: HashMap<String, usize>,            // method -> endpoints asked per call
+    capabilities: Option<Vec<Capabilities>>,   // None = not probed, send anything anywhere
+    heads: HeadTracker,
+    head_poll: Option<Duration>, // None = heads are not refreshed, don't filter on them
+    archive: ArchiveTags,
+    names: Vec<String>,
+    weights: Vec<f64>,
+    timeout: Option<Duration>,
+    method_timeouts: HashMap<String, Duration>,
+    recorder: Option<Arc<Recorder>>,
+}
+
+/// Resolves to `Some` once the leading call of a coalesced request finishes.
+type Answer = watch::Receiver<Option<Result<serde_json::Value>>>;
+
+/// Removes a leading call from `in_flight`, also when it is cancelled.
+struct Leader<'a> {
+    in_flight: &'a Mutex<HashMap<String, Answer>>,
+    key: String,
+}
+impl Drop for Leader<'_> {
+    fn drop(&mut self) {
+        self.in_flight.lock().unwrap().remove(&self.key);
+    }
+}
+
+/// How long a fetched finalized head is trusted before asking again.
+const FINALIZED_TTL: Duration = Duration::from_secs(30);
+
+/// A cacheable call: its key and the highest block it depends on.
+struct Pinned {
+    key: String,
+    block: u64,
 }
 
 impl ProviderPool {
     pub fn new(clients: Vec<RpcClient>, per_rpc_parallel: usize) -> Self {
-        let permits = (0..clients.len())
+        let n = clients.len();
+        let permits = (0..n)
             .map(|_| Arc::new(Semaphore::new(per_rpc_parallel)))
             .collect();
-        let stats: Arc<[RpcStats]> = (0..clients.len())
+        let stats: Arc<[RpcStats]> = (0..n)
             .map(|_| RpcStats::default())
             .collect::<Vec<_>>()
             .into();
@@ -50,37 +117,1100 @@ impl ProviderPool {
             permits,
             stats,
             rr: AtomicUsize::new(0),
+            outstanding: (0..n).map(|_| AtomicUsize::new(0)).collect(),
+            selection: Selection::RoundRobin,
+            breaker: Some(BreakerConfig::default()),
+            failover: 2,
+            buckets: (0..n).map(|_| None).collect(),
+            method_costs: HashMap::new(),
+            batch_sizes: vec![1; n],
+            batch_rejected: (0..n).map(|_| AtomicBool::new(false)).collect(),
+            cache: None,
+            finalized: tokio::sync::Mutex::new(None),
+            chain_id: tokio::sync::OnceCell::new(),
+            coalesce: true,
+            in_flight: Mutex::new(HashMap::new()),
+            quorum: HashMap::new(),
+            capabilities: None,
+            heads: HeadTracker::new(n),
+            head_poll: None,
+            archive: ArchiveTags::new(n),
+            names: (0..n).map(|i| format!("RPC #{i}")).collect(),
+            weights: vec![1.0; n],
+            timeout: None,
+            method_timeouts: HashMap::new(),
+            recorder: None,
+        }
+    }
+    /// How endpoints (by index) are shown in logs and reports; never a raw URL.
+    pub fn with_names(mut self, names: Vec<String>) -> Self {
+        for (slot, name) in self.names.iter_mut().zip(names) {
+            *slot = name;
+        }
+        self
+    }
+
+    pub fn with_selection(mut self, selection: Selection) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    /// Relative capacity per endpoint (by index, default 1.0): an endpoint
+    /// of weight 2 is picked as if it had half the load. Applies to the
+    /// cost-based selections, not to round-robin.
+    pub fn with_weights(mut self, weights: Vec<f64>) -> Self {
+        self.weights = (0..self.clients.len())
+            .map(|i| weights.get(i).copied().unwrap_or(1.0).max(f64::EPSILON))
+            .collect();
+        self
+    }
+
+    /// How long one call may take once sent: `per_method` overrides
+    /// `default`; methods in neither have no timeout. A timed-out call
+    /// frees its concurrency slot, counts as an endpoint failure and is
+    /// failed over like a transport error.
+    pub fn with_timeouts(
+        mut self,
+        default: Option<Duration>,
+        per_method: HashMap<String, Duration>,
+    ) -> Self {
+        self.timeout = default;
+        self.method_timeouts = per_method;
+        self
+    }
+
+    /// Write every upstream call (batched ones included) to `recorder`.
+    pub fn with_recorder(mut self, recorder: Option<Arc<Recorder>>) -> Self {
+        self.recorder = recorder;
+        self
+    }
+
+    /// Per-endpoint circuit breaker settings; `None` disables it.
+    pub fn with_breaker(mut self, cfg: Option<BreakerConfig>) -> Self {
+        self.breaker = cfg;
+        self
+    }
+
+    /// How many other endpoints a failed request may be re-sent to.
+    pub fn with_failover(mut self, attempts: usize) -> Self {
+        self.failover = attempts;
+        self
+    }
+
+    /// Token-bucket budget per endpoint (by index); `None` = unlimited.
+    pub fn with_rate_limits(mut self, limits: Vec<Option<RateLimit>>) -> Self {
+        self.buckets = (0..self.clients.len())
+            .map(|i| limits.get(i).copied().flatten().map(TokenBucket::new))
+            .collect();
+        self
+    }
+
+    /// Budget units each method draws (e.g. provider compute units).
+    pub fn with_method_costs(mut self, costs: HashMap<String, f64>) -> Self {
+        self.method_costs = costs;
+        self
+    }
+
+    /// Max calls per JSON-RPC batch, per endpoint (by index); 0/1 = no batching.
+    pub fn with_batch_sizes(mut self, sizes: Vec<usize>) -> Self {
+        self.batch_sizes = (0..self.clients.len())
+            .map(|i| sizes.get(i).copied().unwrap_or(1).max(1))
+            .collect();
+        self
+    }
+
+    /// Serve and store answers of queries pinned at or below the finalized head.
+    pub fn with_cache(mut self, cache: Option<Arc<dyn ResponseCache>>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Share one upstream call between identical concurrent requests (default: on).
+    pub fn with_coalescing(mut self, yes: bool) -> Self {
+        self.coalesce = yes;
+        self
+    }
+
+    /// Methods whose answers must be confirmed by a majority of `k` endpoints.
+    pub fn with_quorum(mut self, quorum: HashMap<String, usize>) -> Self {
+        self.quorum = quorum;
+        self
+    }
+
+    /// Probe results per endpoint (by index); methods are only sent to
+    /// endpoints that support them.
+    pub fn with_capabilities(mut self, caps: Option<Vec<Capabilities>>) -> Self {
+        for (i, c) in caps.iter().flatten().enumerate() {
+            if let Some(head) = c.head {
+                self.heads.update(i, head);
+            }
+            self.archive.set(i, c.archive);
+            if let Some(id) = c.chain_id {
+                let _ = self.chain_id.set(id);
+            }
+        }
+        self.capabilities = caps;
+        self
+    }
+
+    /// Mark endpoints (by index) as archive (`true`) or pruned (`false`);
+    /// `None` leaves the probed or learned tag. Historical state queries
+    /// avoid pruned endpoints.
+    pub fn with_archive_tags(self, tags: Vec<Option<bool>>) -> Self {
+        for (i, tag) in tags.into_iter().enumerate().take(self.clients.len()) {
+            if let Some(archive) = tag {
+                self.archive.set(i, archive);
+            }
+        }
+        self
+    }
+
+    /// Poll every endpoint's head each `every` and keep requests for a block
+    /// away from endpoints that are behind it. Polls go through the same
+    /// budget, permits, stats, breaker and recorder as other calls. Polling
+    /// starts when the pool is handed to `EthereumIndexer::new` (needs a
+    /// Tokio runtime) and stops when the pool is dropped.
+    pub fn with_head_tracking(mut self, every: Option<Duration>) -> Self {
+        self.head_poll = every;
+        self
+    }
+
+    pub(crate) fn start_head_tracking(self: &Arc<Self>) {
+        let Some(every) = self.head_poll else {
+            return;
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(rt) => {
+                // unless configured, a poll should not outlive its interval
+                let timeout = self.timeout_for("eth_blockNumber").unwrap_or(every);
+                rt.spawn(poll_heads(Arc::downgrade(self), every, timeout));
+            }
+            Err(_) => tracing::warn!("no Tokio runtime, endpoint heads are not tracked"),
+        }
+    }
+
+    /// One round of head polls. Endpoints with an open circuit are left out.
+    pub(crate) async fn refresh_heads(&self, timeout: Duration) {
+        let units = self.units("eth_blockNumber");
+        let polls = (0..self.clients.len())
+            .filter(|&i| self.available(i))
+            .map(|i| async move {
+                let res =
+                    tokio::time::timeout(timeout, self.send(i, "eth_blockNumber", vec![], units));
+                (i, res.await)
+            });
+        for (i, res) in futures::future::join_all(polls).await {
+            match res {
+                Ok(Ok(v)) => match parse_head(&v) {
+                    Some(head) => self.heads.update(i, head),
+                    None => tracing::debug!("RPC #{i}: unexpected eth_blockNumber answer"),
+                },
+                Ok(Err(e)) => tracing::debug!("head poll failed: {e}"),
+                Err(_) => {
+                    tracing::debug!("RPC #{i}: head poll timed out after {timeout:?}");
+                    self.heads.forget(i);
+                }
+            }
         }
     }
 
     pub fn len(&self) -> usize {
         self.clients.len()
     }
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
     pub fn stats(&self) -> Arc<[RpcStats]> {
         self.stats.clone()
     }
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+    /// Last seen head per endpoint (`None` until first polled).
+    pub fn heads(&self) -> Vec<Option<u64>> {
+        (0..self.clients.len()).map(|i| self.heads.get(i)).collect()
+    }
+
+    fn available(&self, idx: usize) -> bool {
+        self.breaker
+            .as_ref()
+            .is_none_or(|cfg| self.stats[idx].breaker.is_available(cfg))
+    }
+
+    fn admit(&self, idx: usize) -> bool {
+        self.breaker
+            .as_ref()
+            .is_none_or(|cfg| self.stats[idx].breaker.try_admit(cfg))
+    }
+
+    /// Endpoints `method(params)` should not go to: those that can't serve
+    /// the method, pruned ones for state older than `RECENT_STATE_BLOCKS`,
+    /// and those whose head is behind the block it reads. Pruned and lagging
+    /// endpoints are only skipped while some capable endpoint is left.
+    fn skip_for(&self, method: &str, params: &[serde_json::Value]) -> Vec<usize> {
+        let mut skip = self.unsupported(method);
+        let need = needed_block(method, params);
+        if let Some(Need::Block(n)) = need
+            && is_state_query(method)
+            && self.is_historical(n)
+        {
+            let pruned = self.archive.pruned(&skip);
+            if skip.len() + pruned.len() < self.clients.len() {
+                skip.extend(pruned);
+            }
+        }
+        // heads that are not refreshed (e.g. only seeded by the probe) go
+        // stale, so they are not used to exclude endpoints
+        if let Some(need) = need.filter(|_| self.head_poll.is_some()) {
+            let lagging = self.heads.lagging(need, &skip);
+            if !lagging.is_empty() {
+                tracing::trace!("{method}: skipping RPC {lagging:?}, behind {need:?}");
+            }
+            skip.extend(lagging);
+        }
+        skip
+    }
+
+    /// Is the state at block `n` likely gone from full nodes? Without a
+    /// known head every block is treated as historical.
+    fn is_historical(&self, n: u64) -> bool {
+        self.heads
+            .best()
+            .is_none_or(|head| head.saturating_sub(n) > RECENT_STATE_BLOCKS)
+    }
+
+    /// Remember endpoints that turn out to be pruned.
+    fn note_error(&self, idx: usize, method: &str, e: &IndexerError) {
+        if is_state_query(method) && is_missing_state_error(e) && self.archive.learn_pruned(idx) {
+            tracing::info!("RPC #{idx} has no historical state, treating it as pruned");
+        }
+    }
+
+    /// Endpoints known not to serve `method`.
+    fn unsupported(&self, method: &str) -> Vec<usize> {
+        let Some(caps) = &self.capabilities else {
+            return Vec::new();
+        };
+        (0..self.clients.len())
+            .filter(|&i| caps.get(i).is_some_and(|c| !c.supports(method)))
+            .collect()
+    }
+
+    fn units(&self, method: &str) -> f64 {
+        self.method_costs.get(method).copied().unwrap_or(1.0)
+    }
+
+    /// Timeout of a call to `method`: its own, else the pool's default.
+    fn timeout_for(&self, method: &str) -> Option<Duration> {
+        self.method_timeouts.get(method).copied().or(self.timeout)
+    }
+
+    /// Await `call`, giving up after the timeout of `method` (if any).
+    async fn timed<T>(
+        &self,
+        idx: usize,
+        method: &str,
+        timeout: Option<Duration>,
+        call: impl Future<Output = Result<T, TransportError>>,
+    ) -> Result<T> {
+        let res = match timeout {
+            Some(after) => {
+                tokio::time::timeout(after, call)
+                    .await
+                    .map_err(|_| IndexerError::Timeout {
+                        method: method.to_string(),
+                        after,
+                        endpoint: Some(idx),
+                        range: None,
+                    })?
+            }
+            None => call.await,
+        };
+        res.map_err(|e| IndexerError::from_transport(e, idx))
+    }
+
+    fn record(
+        &self,
+        idx: usize,
+        method: &str,
+        params: &[serde_json::Value],
+        res: &Result<serde_json::Value>,
+        started: Instant,
+    ) {
+        if let Some(recorder) = &self.recorder {
+            let endpoint = (idx, self.names[idx].as_str());
+            recorder.write(&RecordedCall::new(
+                method,
+                params,
+                res,
+                endpoint,
+                started.elapsed(),
+            ));
+        }
+    }
+
+    /// Record an answer that was given without asking an endpoint.
+    fn record_served(
+        &self,
+        from: Served,
+        method: &str,
+        params: &[serde_json::Value],
+        res: &Result<serde_json::Value>,
+    ) {
+        if let Some(recorder) = &self.recorder {
+            recorder.write(&RecordedCall::served(method, params, res, from));
+        }
+    }
+
+    /// Endpoints to ask for `method`, if it is under a quorum policy.
+    fn quorum_size(&self, method: &str) -> Option<usize> {
+        let k = (*self.quorum.get(method)?).min(self.clients.len());
+        (k > 1).then_some(k)
+    }
 
+    fn has_budget(&self, idx: usize, units: f64) -> bool {
+        self.buckets[idx].as_ref().is_none_or(|b| b.ready(units))
+    }
+
+    fn cost(&self, idx: usize) -> f64 {
+        let load = (self.outstanding[idx].load(Ordering::Relaxed) + 1) as f64 / self.weights[idx];
+        match self.selection {
+            Selection::RoundRobin | Selection::LeastOutstanding => load,
+            Selection::Weighted => {
+                let (req, ok, avg_ms) = self.stats[idx].snapshot();
+                let success = (ok + 1) as f64 / (req + 1) as f64;
+                load * (avg_ms + 1.0) / success
+            }
+        }
+    }
+
+    /// Next endpoint, skipping `tried`. `None` once every endpoint was tried.
+    /// Endpoints with budget left for `units` are preferred over throttled ones.
+    fn pick(&self, tried: &[usize], units: f64) -> Option<usize> {
+        let n = self.clients.len();
+        // rotating start so ties spread across endpoints
+        let start = self.rr.fetch_add(1, Ordering::Relaxed) % n;
+        for _ in 0..n {
+            let candidates: Vec<usize> = (0..n)
+                .map(|i| (start + i) % n)
+                .filter(|i| !tried.contains(i) && self.available(*i))
+                .collect();
+            let throttled = |i: usize| !self.has_budget(i, units);
+            let chosen = match self.selection {
+                Selection::RoundRobin => candidates
+                    .iter()
+                    .copied()
+                    .find(|&i| !throttled(i))
+                    .or(candidates.first().copied()),
+                _ => candidates.iter().copied().min_by(|&a, &b| {
+                    throttled(a)
+                        .cmp(&throttled(b))
+                        .then(self.cost(a).total_cmp(&self.cost(b)))
+                }),
+            };
+            match chosen {
+                // lost a race for a half-open probe slot -> pick again
+                Some(idx) if !self.admit(idx) => continue,
+                Some(idx) => return Some(idx),
+                None => break,
+            }
+        }
+        // every circuit is open: don't stall the whole pool
+        let fallback = (0..n)
+            .map(|i| (start + i) % n)
+            .find(|i| !tried.contains(i))?;
+        tracing::debug!("all endpoint circuits open, sending to #{fallback}");
+        Some(fallback)
+    }
+
+    /// Send one request to the endpoint chosen by the pool's `Selection`.
+    /// On failure the request is re-sent to a different endpoint, up to the
+    /// pool's failover budget, unless the error would repeat anywhere.
+    /// Finalized answers come from / go to the cache, if one is set, and
+    /// identical requests already in flight share that call's answer.
     pub async fn rr_request(
         &self,
         method: &'static str,
         params: Vec<serde_json::Value>,
-    ) -> anyhow::Result<serde_json::Value> {
-        let idx = self.rr.fetch_add(1, Ordering::Relaxed) % self.clients.len();
-        let permit = self.permits[idx].acquire().await?;
+    ) -> Result<serde_json::Value> {
+        if !self.coalesce {
+            return self.cached_request(method, params).await;
+        }
+        let key = cache_key(method, &params);
+        let follow = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get(&key) {
+                Some(answer) => Err(answer.clone()),
+                None => {
+                    let (tx, rx) = watch::channel(None);
+                    in_flight.insert(key.clone(), rx);
+                    Ok(tx)
+                }
+            }
+        };
+        match follow {
+            Ok(tx) => {
+                let _leader = Leader {
+                    in_flight: &self.in_flight,
+                    key,
+                };
+                let res = self.cached_request(method, params).await;
+                tx.send_replace(Some(res.clone()));
+                res
+            }
+            Err(mut answer) => {
+                let shared = answer.wait_for(Option::is_some).await.map(|r| r.clone());
+                match shared {
+                    Ok(Some(res)) => {
+                        self.record_served(Served::Coalesced, method, &params, &res);
+                        res
+                    }
+                    // the leading call was cancelled: go on our own
+                    _ => self.cached_request(method, params).await,
+                }
+            }
+        }
+    }
+
+    async fn cached_request(
+        &self,
+        method: &'static str,
+        params: Vec<serde_json::Value>,
+    ) -> Result<serde_json::Value> {
+        let Some(pinned) = self.pin(method, &params).await else {
+            return self.request(method, params).await;
+        };
+        if let Some(v) = self.cached(&pinned) {
+            let res = Ok(v);
+            self.record_served(Served::Cache, method, &params, &res);
+            return res;
+        }
+        let res = self.request(method, params).await;
+        if let Ok(v) = &res {
+            self.store(pinned, v).await;
+        }
+        res
+    }
+
+    async fn request(
+        &self,
+        method: &'static str,
+        params: Vec<serde_json::Value>,
+    ) -> Result<serde_json::Value> {
+        if let Some(k) = self.quorum_size(method) {
+            return self.quorum_request(method, params, k).await;
+        }
+        let units = self.units(method);
+        // endpoints that can't serve the method count as tried up front
+        let mut tried = self.skip_for(method, &params);
+        let skipped = tried.len();
+        if skipped > 0 && skipped == self.clients.len() {
+            return Err(IndexerError::Unsupported(method.to_string()));
+        }
+        loop {
+            let idx = self.pick(&tried, units).ok_or(IndexerError::NoEndpoint)?;
+            tried.push(idx);
+            let attempts = tried.len() - skipped;
+            let last = attempts > self.failover || tried.len() == self.clients.len();
+            match self.send(idx, method, params.clone(), units).await {
+                Err(e) if !last && should_fail_over(&e) => {
+                    tracing::debug!("{method} failed, failing over: {e}");
+                }
+                res => return res,
+            }
+        }
+    }
+
+    /// Ask `k` endpoints at once and return the answer a majority agrees on.
+    /// Endpoints that fail are replaced by untried ones while any are left;
+    /// answers are compared as canonical JSON.
+    async fn quorum_request(
+        &self,
+        method: &'static str,
+        params: Vec<serde_json::Value>,
+        k: usize,
+    ) -> Result<serde_json::Value> {
+        let units = self.units(method);
+        let mut tried = self.skip_for(method, &params);
+        if tried.len() == self.clients.len() {
+            return Err(IndexerError::Unsupported(method.to_string()));
+        }
+        let k = k.min(self.clients.len() - tried.len());
+        let mut answers = Vec::with_capacity(k);
+        let mut last_err = None;
+        while answers.len() < k {
+            let mut picked = Vec::new();
+            while picked.len() < k - answers.len() {
+                let Some(idx) = self.pick(&tried, units) else {
+                    break;
+                };
+                tried.push(idx);
+                picked.push(idx);
+            }
+            if picked.is_empty() {
+                break;
+            }
+            let sends = picked
+                .iter()
+                .map(|&idx| self.send(idx, method, params.clone(), units));
+            let results = futures::future::join_all(sends).await;
+            for (idx, res) in picked.into_iter().zip(results) {
+                match res {
+                    Ok(v) => answers.push((idx, v)),
+                    Err(e) if should_fail_over(&e) => {
+                        tracing::debug!("{method} failed under quorum, replacing: {e}");
+                        last_err = Some(e);
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
+        // (canonical answer, endpoints that gave it, answer)
+        let mut groups: Vec<(String, Vec<usize>, serde_json::Value)> = Vec::new();
+        for (idx, v) in answers {
+            let key = canonical_json(&v);
+            match groups.iter_mut().find(|g| g.0 == key) {
+                Some(g) => g.1.push(idx),
+                None => groups.push((key, vec![idx], v)),
+            }
+        }
+        groups.sort_by_key(|g| std::cmp::Reverse(g.1.len()));
+        if groups.first().is_some_and(|g| g.1.len() > k / 2) {
+            let (_, agreed, v) = groups.remove(0);
+            if !groups.is_empty() {
+                let dissent: Vec<usize> = groups.into_iter().flat_map(|g| g.1).collect();
+                tracing::warn!("{method}: RPC {dissent:?} disagree with the majority {agreed:?}");
+            }
+            return Ok(v);
+        }
+        if groups.len() < 2 {
+            // too few answers to decide, not a disagreement
+            return Err(last_err.unwrap_or(IndexerError::NoEndpoint));
+        }
+        Err(IndexerError::Divergence {
+            method: method.to_string(),
+            groups: groups.into_iter().map(|g| g.1).collect(),
+            range: None,
+        })
+    }
+
+    async fn send(
+        &self,
+        idx: usize,
+        method: &'static str,
+        params: Vec<serde_json::Value>,
+        units: f64,
+    ) -> Result<serde_json::Value> {
+        let _outstanding = Outstanding::enter(&self.outstanding[idx]);
+        // wait for budget before taking a concurrency slot
+        if let Some(bucket) = &self.buckets[idx] {
+            bucket.acquire(units).await;
+        }
+        let permit = self.permits[idx]
+            .acquire()
+            .await
+            .expect("pool semaphores are never closed");
         let client = &self.clients[idx];
 
-        let t0 = Instant::now();
-        let res = client.request(method, params).await;
+        let call = self.stats[idx].begin(method);
+        let timeout = self.timeout_for(method);
+        let recorded = self.recorder.is_some().then(|| params.clone());
+        let started = Instant::now();
+        let res = self
+            .timed(idx, method, timeout, client.request(method, params))
+            .await;
+        drop(permit);
+        if let Some(params) = recorded {
+            self.record(idx, method, &params, &res, started);
+        }
+
+        if let Some(cfg) = &self.breaker {
+            match &res {
+                // a JSON-RPC error response still means the endpoint is up
+                Err(IndexerError::Rpc { .. }) | Ok(_) => self.stats[idx].breaker.on_success(),
+                Err(_) => self.stats[idx].breaker.on_failure(cfg),
+            }
+        }
+        let res = res.and_then(|v| validate(method, v));
+        call.end(&res);
+        if let Err(e) = &res {
+            self.note_error(idx, method, e);
+        }
+        res
+    }
+
+    /// Largest batch any endpoint accepts (1 = batching off).
+    pub fn max_batch_size(&self) -> usize {
+        (0..self.len())
+            .filter(|&i| self.batches(i))
+            .map(|i| self.batch_sizes[i])
+            .max()
+            .unwrap_or(1)
+    }
+
+    fn batches(&self, idx: usize) -> bool {
+        self.batch_sizes[idx] > 1 && !self.batch_rejected[idx].load(Ordering::Relaxed)
+    }
+
+    /// Send `calls` as JSON-RPC batches to batch-capable endpoints, split to
+    /// each endpoint's batch size. Results come back in call order. Items
+    /// that fail inside a batch, and whole batches an endpoint rejects, are
+    /// re-sent as single requests (with failover).
+    pub async fn batch_request(
+        &self,
+        calls: Vec<(&'static str, Vec<serde_json::Value>)>,
+    ) -> Vec<Result<serde_json::Value>> {
+        if self.cache.is_none() {
+            return self.batch_uncached(calls).await;
+        }
+        let mut pins: Vec<Option<Pinned>> =
+            futures::future::join_all(calls.iter().map(|(m, p)| self.pin(m, p))).await;
+        let mut out: Vec<Option<Result<serde_json::Value>>> = pins
+            .iter()
+            .map(|p| p.as_ref().and_then(|p| self.cached(p)).map(Ok))
+            .collect();
+        for ((method, params), hit) in calls.iter().zip(&out) {
+            if let Some(res) = hit {
+                self.record_served(Served::Cache, method, params, res);
+            }
+        }
+        let (misses, miss_calls): (Vec<usize>, Vec<_>) = calls
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| out[*i].is_none())
+            .unzip();
+        let results = self.batch_uncached(miss_calls).await;
+        for (i, res) in misses.into_iter().zip(results) {
+            if let (Ok(v), Some(p)) = (&res, pins[i].take()) {
+                self.store(p, v).await;
+            }
+            out[i] = Some(res);
+        }
+        out.into_iter()
+            .map(|r| r.expect("every call is answered"))
+            .collect()
+    }
+
+    async fn batch_uncached(
+        &self,
+        calls: Vec<(&'static str, Vec<serde_json::Value>)>,
+    ) -> Vec<Result<serde_json::Value>> {
+        let size = self.max_batch_size();
+        // quorum calls go to several endpoints each, which a batch can't do
+        if size <= 1 || calls.len() <= 1 || calls.iter().any(|(m, _)| self.quorum_size(m).is_some())
+        {
+            return self.singles(calls).await;
+        }
+        let parts = chunked(calls, size).map(|part| async move {
+            let mut no_batch: Vec<usize> = (0..self.len()).filter(|&i| !self.batches(i)).collect();
+            no_batch.extend(part.iter().flat_map(|(m, p)| self.skip_for(m, p)));
+            let units = part.iter().map(|(m, _)| self.units(m)).sum();
+            match self.pick(&no_batch, units) {
+                // endpoint may take smaller batches than the largest one
+                Some(idx) => {
+                    let subs =
+                        chunked(part, self.batch_sizes[idx]).map(|sub| self.run_batch(idx, sub));
+                    futures::future::join_all(subs)
+                        .await
+                        .into_iter()
+                        .flatten()
+                        .collect()
+                }
+                None => self.singles(part).await,
+            }
+        });
+        futures::future::join_all(parts)
+            .await
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    async fn run_batch(
+        &self,
+        idx: usize,
+        calls: Vec<(&'static str, Vec<serde_json::Value>)>,
+    ) -> Vec<Result<serde_json::Value>> {
+        let units = calls.iter().map(|(m, _)| self.units(m)).sum();
+        let results = match self.send_batch(idx, &calls, units).await {
+            Ok(Some(results)) => results,
+            rejected => {
+                if rejected
+                    .as_ref()
+                    .map_or_else(batch_unsupported, Option::is_none)
+                {
+                    tracing::warn!("RPC #{idx} rejects batch requests, using single requests");
+                    self.batch_rejected[idx].store(true, Ordering::Relaxed);
+                }
+                return self.singles(calls).await;
+            }
+        };
+        let items = calls
+            .into_iter()
+            .zip(results)
+            .map(|((method, params), res)| async move {
+                match res {
+                    Err(e) if should_fail_over(&e) => self.request(method, params).await,
+                    res => res.and_then(|v| validate(method, v)),
+                }
+            });
+        futures::future::join_all(items).await
+    }
+
+    async fn singles(
+        &self,
+        calls: Vec<(&'static str, Vec<serde_json::Value>)>,
+    ) -> Vec<Result<serde_json::Value>> {
+        futures::future::join_all(calls.into_iter().map(|(m, p)| self.request(m, p))).await
+    }
+
+    /// Cache entries are keyed by chain id too, so one cache directory can
+    /// serve several networks.
+    async fn pin(&self, method: &str, params: &[serde_json::Value]) -> Option<Pinned> {
+        self.cache.as_ref()?;
+        let block = pinned_block(method, params)?;
+        let chain_id = self.chain_id().await?;
+        Some(Pinned {
+            block,
+            key: format!("{chain_id}/{}", cache_key(method, params)),
+        })
+    }
+
+    /// The endpoints' chain id, from the `build_validated()` probe or asked
+    /// once. `None` (and nothing cached) while it can't be learned.
+    async fn chain_id(&self) -> Option<u64> {
+        let id = self
+            .chain_id
+            .get_or_try_init(|| async {
+                let v = self.request("eth_chainId", vec![]).await?;
+                v.as_str()
+                    .and_then(|s| u64::from_str_radix(s.trim_start_matches("0x"), 16).ok())
+                    .ok_or_else(|| IndexerError::decode(format!("bad eth_chainId answer {v}")))
+            })
+            .await;
+        match id {
+            Ok(id) => Some(*id),
+            Err(e) => {
+                tracing::debug!("chain id unavailable, skipping the cache: {e}");
+                None
+            }
+        }
+    }
+
+    // an entry is only ever written once final, so hits need no head check
+    fn cached(&self, p: &Pinned) -> Option<serde_json::Value> {
+        self.cache.as_ref()?.get(&p.key)
+    }
+
+    async fn store(&self, p: Pinned, v: &serde_json::Value) {
+        let Some(cache) = &self.cache else {
+            return;
+        };
+        if self
+            .finalized_head()
+            .await
+            .is_some_and(|head| p.block <= head)
+        {
+            cache.put(&p.key, v);
+        }
+    }
+
+    /// Latest finalized block, refreshed every `FINALIZED_TTL`.
+    /// `None` if the endpoints don't know the "finalized" tag.
+    async fn finalized_head(&self) -> Option<u64> {
+        let mut head = self.finalized.lock().await;
+        if let Some((n, at)) = *head
+            && at.elapsed() < FINALIZED_TTL
+        {
+            return n;
+        }
+        let params = vec!["finalized".into(), false.into()];
+        let fetched = match self.request("eth_getBlockByNumber", params).await {
+            Ok(block) => block
+                .get("number")
+                .and_then(|n| n.as_str())
+                .and_then(|n| u64::from_str_radix(n.trim_start_matches("0x"), 16).ok()),
+            Err(e) => {
+                tracing::debug!("finalized head unavailable: {e}");
+                None
+            }
+        };
+        // keep the last known head on failure; it only gets more conservative
+        let n = fetched.or(head.and_then(|(n, _)| n));
+        *head = Some((n, Instant::now()));
+        n
+    }
+
+    async fn send_batch(
+        &self,
+        idx: usize,
+        calls: &[(&'static str, Vec<serde_json::Value>)],
+        units: f64,
+    ) -> Result<Option<Vec<Result<serde_json::Value>>>> {
+        let _outstanding = Outstanding::enter(&self.outstanding[idx]);
+        if let Some(bucket) = &self.buckets[idx] {
+            bucket.acquire(units).await;
+        }
+        let permit = self.permits[idx]
+            .acquire()
+            .await
+            .expect("pool semaphores are never closed");
+
+        let timed: Vec<_> = calls
+            .iter()
+            .map(|(method, _)| self.stats[idx].begin(method))
+            .collect();
+        let mut batch = self.clients[idx].new_batch();
+        let waiters = calls
+            .iter()
+            .map(|(method, params)| batch.add_call::<_, serde_json::Value>(*method, params))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| IndexerError::from_transport(e, idx))?;
+        // the batch gets the longest timeout of its calls, none if one has none
+        let timeout = calls
+            .iter()
+            .map(|(method, _)| self.timeout_for(method))
+            .collect::<Option<Vec<_>>>()
+            .and_then(|t| t.into_iter().max());
+        let send = async {
+            batch.send().await?;
+            Ok(futures::future::join_all(waiters).await)
+        };
+        let started = Instant::now();
+        let results = match self.timed(idx, "batch", timeout, send).await {
+            Ok(results) => results,
+            Err(e) => {
+                drop(permit);
+                let failed = Err(e);
+                for (method, params) in calls {
+                    self.record(idx, method, params, &failed, started);
+                }
+                timed.into_iter().for_each(|call| call.end(&failed));
+                if let Some(cfg) = &self.breaker {
+                    self.stats[idx].breaker.on_failure(cfg);
+                }
+                return failed.map(|_| None);
+            }
+        };
         drop(permit);
 
-        self.stats[idx].record(res.is_ok(), t0.elapsed());
-        let value: serde_json::Value = res?;
+        let results: Vec<_> = results
+            .into_iter()
+            .map(|r| r.map_err(|e| IndexerError::from_transport(e, idx)))
+            .collect();
+        for ((method, params), r) in calls.iter().zip(&results) {
+            self.record(idx, method, params, r, started);
+            if let Err(e) = r {
+                self.note_error(idx, method, e);
+            }
+        }
+        for (call, r) in timed.into_iter().zip(&results) {
+            call.end(r);
+        }
+        if self.breaker.is_some() {
+            self.stats[idx].breaker.on_success();
+        }
+        // a lone error object instead of an array leaves every call unanswered
+        if results.iter().all(|r| {
+            matches!(r, Err(IndexerError::Transport { source, .. }) if matches!(
+                **source,
+                RpcError::Transport(TransportErrorKind::MissingBatchResponse(_))
+            ))
+        }) {
+            return Ok(None);
+        }
+        Ok(Some(results))
+    }
+}
+
+fn chunked<T>(items: Vec<T>, size: usize) -> impl Iterator<Item = Vec<T>> {
+    let mut items = items.into_iter();
+    std::iter::from_fn(move || {
+        let part: Vec<T> = items.by_ref().take(size.max(1)).collect();
+        (!part.is_empty()).then_some(part)
+    })
+}
+
+// Basic validation - ensure we have valid JSON structure
+fn validate(method: &str, value: serde_json::Value) -> Result<serde_json::Value> {
+    if method == "trace_filter" && value.is_string() {
+        return Err(IndexerError::decode(
+            "Invalid trace_filter response: got string instead of array/null",
+        ));
+    }
+    Ok(value)
+}
+
+/// Did the endpoint refuse the batch itself (as opposed to a network error)?
+fn batch_unsupported(e: &IndexerError) -> bool {
+    match e {
+        IndexerError::Rpc { .. } => true,
+        IndexerError::Transport { source, .. } => match &**source {
+            RpcError::Transport(TransportErrorKind::HttpError(h)) => {
+                (400..500).contains(&h.status) && h.status != 429
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Errors that would come back the same from any endpoint are not retried.
+fn should_fail_over(e: &IndexerError) -> bool {
+    if crate::bisect::is_range_limit_error(e) {
+        return false; // handled by range bisection
+    }
+    match e {
+        IndexerError::Rpc { code, .. } => !matches!(code, 3 | -32602), // reverted, invalid params
+        // a malformed answer may be one node's bug
+        IndexerError::Transport { .. }
+        | IndexerError::Timeout { .. }
+        | IndexerError::Decode { .. } => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::eth::get_block_by_number::BlockByNumberBuilder;
+    use crate::methods::eth::get_block_by_number::work_one;
+    use crate::{DiskCache, EngineBuilder, EthereumIndexer, MockError, MockRpc};
+    use alloy::rpc::types::eth::BlockNumberOrTag;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn fails_over_to_next_endpoint() {
+        let a = MockRpc::new().chain(0, 9, 1_000, 12);
+        let b = MockRpc::new().chain(0, 9, 1_000, 12);
+        a.fail_next("eth_getBlockByNumber", 2, MockError::Http(503));
+        let idx = EngineBuilder::new().build_with_clients(vec![a.client(), b.client()]);
+        let plan = BlockByNumberBuilder::new().range(0, 9).plan().unwrap();
+
+        let out: Vec<_> = idx.run_plan(&plan).unwrap().collect().await;
+        assert_eq!(out.len(), 10);
+        assert!(out.iter().all(|r| matches!(r, Ok((_, Some(_))))), "{out:?}");
+        // ten answers plus the two calls `a` failed, each retried on `b`
+        let calls = a.call_count("eth_getBlockByNumber") + b.call_count("eth_getBlockByNumber");
+        assert_eq!(calls, 12);
+    }
+
+    #[tokio::test]
+    async fn cache_is_keyed_by_chain() {
+        let dir = std::env::temp_dir().join(format!("indexer-cache-test-{}", std::process::id()));
+        let cache = Arc::new(DiskCache::open(&dir, 1 << 20).unwrap());
+        let mainnet = MockRpc::new().chain_id(1).chain(0, 9, 1_000, 12);
+        let testnet = MockRpc::new().chain_id(5).chain(0, 9, 2_000, 12);
+        let plan = BlockByNumberBuilder::new().range(0, 4).plan().unwrap();
+
+        for mock in [&mainnet, &testnet, &mainnet] {
+            let idx = EngineBuilder::new()
+                .cache(cache.clone())
+                .build_with_clients(vec![mock.client()]);
+            let out: Vec<_> = idx.run_plan(&plan).unwrap().collect().await;
+            assert!(out.iter().all(|r| r.is_ok()));
+        }
+        // five blocks plus one finalized-head lookup each: the testnet run
+        // missed the mainnet entries, the second mainnet run hit them all
+        assert_eq!(mainnet.call_count("eth_getBlockByNumber"), 6);
+        assert_eq!(testnet.call_count("eth_getBlockByNumber"), 6);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn head_polls_go_through_the_pool() {
+        let mock = MockRpc::new().chain(0, 9, 1_000, 12);
+        let idx = EngineBuilder::new()
+            .head_tracking(Duration::from_millis(20))
+            .build_with_clients(vec![mock.client()]);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(idx.heads(), vec![Some(9)]);
+        let stats = idx.stats()[0].detailed();
+        let polls = stats.methods.iter().find(|m| m.method == "eth_blockNumber");
+        assert!(polls.is_some_and(|m| m.requests >= 2), "{stats:?}");
+    }
+
+    #[tokio::test]
+    async fn probed_heads_do_not_exclude_without_polling() {
+        let caps = |head| Capabilities {
+            endpoint: String::new(),
+            down: None,
+            chain_id: Some(1),
+            head: Some(head),
+            trace: true,
+            debug: true,
+            archive: true,
+        };
+        let behind = MockRpc::new().chain(0, 9, 1_000, 12);
+        let ahead = MockRpc::new().chain(0, 9, 1_000, 12);
+        let pool = ProviderPool::new(vec![behind.client(), ahead.client()], 4)
+            .with_capabilities(Some(vec![caps(8), caps(9)]));
+        let idx = EthereumIndexer::new(pool, 4);
+
+        for _ in 0..4 {
+            let latest = work_one(BlockNumberOrTag::Latest, false).unwrap();
+            idx.run_once(latest).await.unwrap();
+        }
+        // one block behind at probe time, but still used
+        assert!(behind.call_count("eth_getBlockByNumber") > 0);
+    }
+
+    #[tokio::test]
+    async fn coalesces_identical_in_flight_calls() {
+        let run = |coalesce: bool| async move {
+            let mock = MockRpc::new()
+                .chain(0, 9, 1_000, 12)
+                .latency(Duration::from_millis(50));
+            let idx = EngineBuilder::new()
+                .coalesce_requests(coalesce)
+                .build_with_clients(vec![mock.client()]);
+            let calls = (0..5).map(|_| {
+                let item = work_one(BlockNumberOrTag::Number(3), false).unwrap();
+                idx.run_once(item)
+            });
+            let answers = futures::future::join_all(calls).await;
+            assert!(
+                answers
+                    .iter()
+                    .all(|a| a.as_ref().ok() == answers[0].as_ref().ok())
+            );
+            assert!(answers[0].is_ok());
+            mock.call_count("eth_getBlockByNumber")
+        };
+        assert_eq!(run(true).await, 1);
+        assert_eq!(run(false).await, 5);
+    }
+
+    #[tokio::test]
+    async fn quorum_reports_divergence() {
+        // same numbers, different timestamps: the block bodies disagree
+        let a = MockRpc::new().chain(0, 9, 1_000, 12);
+        let b = MockRpc::new().chain(0, 9, 1_000, 12);
+        let c = MockRpc::new().chain(0, 9, 5_000, 12);
+        let block = || work_one(BlockNumberOrTag::Number(3), false).unwrap();
 
-        // Basic validation - ensure we have valid JSON structure
-        if method == "trace_filter" && value.is_string() {
-            anyhow::bail!("Invalid trace_filter response: got string instead of array/null");
+        let split = EngineBuilder::new()
+            .quorum("eth_getBlockByNumber", 2)
+            .build_with_clients(vec![a.client(), c.client()]);
+        match split.run_once(block()).await {
+            Err(IndexerError::Divergence { method, groups, .. }) => {
+                assert_eq!(method, "eth_getBlockByNumber");
+                let mut groups = groups;
+                groups.sort();
+                assert_eq!(groups, vec![vec![0], vec![1]]);
+            }
+            other => panic!("expected Divergence, got {other:?}"),
         }
 
-        Ok(value)
+        let majority = EngineBuilder::new()
+            .quorum("eth_getBlockByNumber", 3)
+            .build_with_clients(vec![a.client(), b.client(), c.client()]);
+        let v = majority.run_once(block()).await.unwrap();
+        assert_eq!(
+            v["timestamp"],
+            serde_json::json!(format!("{:#x}", 1_000 + 3 * 12))
+        );
     }
 }
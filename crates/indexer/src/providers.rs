@@ -1,7 +1,246 @@
+use crate::error::{IndexerError, Result};
 use alloy::{
     rpc::client::RpcClient,
-    transports::{http::reqwest::Url, layers::RetryBackoffLayer},
+    transports::{
+        Authorization,
+        http::reqwest::{
+            self, Url,
+            header::{HeaderMap, HeaderName, HeaderValue},
+        },
+        ipc::IpcConnect,
+        layers::RetryBackoffLayer,
+        ws::WsConnect,
+    },
 };
+use std::{fmt, path::PathBuf, str::FromStr};
+
+/// One RPC endpoint; the transport is picked from the URL scheme.
+/// - `http://` / `https://` -> HTTP
+/// - `ws://` / `wss://` -> WebSocket
+/// - `ipc://`, `file://` or a bare filesystem path -> IPC socket
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RpcEndpoint {
+    Http(Url),
+    Ws(Url),
+    Ipc(PathBuf),
+}
+
+impl RpcEndpoint {
+    /// HTTP endpoints can be built synchronously; WS/IPC must connect first.
+    pub fn is_http(&self) -> bool {
+        matches!(self, RpcEndpoint::Http(_))
+    }
+
+    /// Printable form without secrets. Providers put API keys in the URL
+    /// path, query or user info, so those are masked; this is also what
+    /// `Display` prints.
+    pub fn redacted(&self) -> String {
+        match self {
+            RpcEndpoint::Http(url) | RpcEndpoint::Ws(url) => redact_url(url),
+            RpcEndpoint::Ipc(path) => format!("ipc://{}", path.display()),
+        }
+    }
+}
+
+fn redact_url(url: &Url) -> String {
+    let secret = url.path() != "/"
+        || url.query().is_some()
+        || !url.username().is_empty()
+        || url.password().is_some();
+    let mut out = format!("{}://{}", url.scheme(), url.host_str().unwrap_or_default());
+    if let Some(port) = url.port() {
+        out.push_str(&format!(":{port}"));
+    }
+    out.push_str(if secret { "/***" } else { "/" });
+    out
+}
+
+/// Mask every URL in `text` (e.g. an HTTP client error quoting the request URL).
+pub(crate) fn redact_urls(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = ["http://", "https://", "ws://", "wss://"]
+        .iter()
+        .filter_map(|scheme| rest.find(scheme))
+        .min()
+    {
+        let len = rest[start..]
+            .find(|c: char| c.is_whitespace() || matches!(c, ')' | '"' | '\'' | '>' | ','))
+            .unwrap_or(rest.len() - start);
+        out.push_str(&rest[..start]);
+        let raw = &rest[start..start + len];
+        match raw.parse::<Url>() {
+            Ok(url) => out.push_str(&redact_url(&url)),
+            Err(_) => out.push_str(raw),
+        }
+        rest = &rest[start + len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Credentials sent with every request to an endpoint.
+#[derive(Clone, PartialEq, Eq)]
+pub enum RpcAuth {
+    /// `Authorization: Bearer <token>`
+    Bearer(String),
+    /// `Authorization: Basic <base64(user:password)>`
+    Basic { user: String, password: String },
+}
+
+impl RpcAuth {
+    fn authorization(&self) -> Authorization {
+        match self {
+            RpcAuth::Bearer(token) => Authorization::bearer(token),
+            RpcAuth::Basic { user, password } => Authorization::basic(user, password),
+        }
+    }
+}
+
+impl fmt::Debug for RpcAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RpcAuth::Bearer(_) => write!(f, "Bearer(***)"),
+            RpcAuth::Basic { user, .. } => write!(f, "Basic({user}:***)"),
+        }
+    }
+}
+
+/// An endpoint plus the auth and headers to send with every request, and
+/// the name it shows up under in logs, stats and `/api/rpc-info`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct EndpointConfig {
+    pub endpoint: RpcEndpoint,
+    pub name: Option<String>,
+    pub auth: Option<RpcAuth>,
+    /// Extra HTTP headers (e.g. an API-key header). HTTP only.
+    pub headers: Vec<(String, String)>,
+}
+
+impl EndpointConfig {
+    pub fn new(endpoint: RpcEndpoint) -> Self {
+        Self {
+            endpoint,
+            name: None,
+            auth: None,
+            headers: Vec::new(),
+        }
+    }
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+    pub fn bearer(mut self, token: impl Into<String>) -> Self {
+        self.auth = Some(RpcAuth::Bearer(token.into()));
+        self
+    }
+    pub fn basic_auth(mut self, user: impl Into<String>, password: impl Into<String>) -> Self {
+        self.auth = Some(RpcAuth::Basic {
+            user: user.into(),
+            password: password.into(),
+        });
+        self
+    }
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// The configured name, else the redacted endpoint.
+    pub fn display_name(&self) -> String {
+        self.name
+            .clone()
+            .unwrap_or_else(|| self.endpoint.redacted())
+    }
+
+    fn header_map(&self) -> Result<HeaderMap> {
+        let mut map = HeaderMap::new();
+        let auth = self
+            .auth
+            .as_ref()
+            .map(|a| ("authorization", a.authorization().to_string()));
+        for (name, value) in self
+            .headers
+            .iter()
+            .map(|(n, v)| (n.as_str(), v.clone()))
+            .chain(auth)
+        {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| IndexerError::Config(format!("invalid header name `{name}`: {e}")))?;
+            let mut value = HeaderValue::from_str(&value)
+                .map_err(|_| IndexerError::Config(format!("invalid value for header `{name}`")))?;
+            value.set_sensitive(true);
+            map.insert(name, value);
+        }
+        Ok(map)
+    }
+}
+
+impl From<RpcEndpoint> for EndpointConfig {
+    fn from(endpoint: RpcEndpoint) -> Self {
+        Self::new(endpoint)
+    }
+}
+
+impl fmt::Debug for EndpointConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let headers: Vec<(&str, &str)> = self
+            .headers
+            .iter()
+            .map(|(n, _)| (n.as_str(), "***"))
+            .collect();
+        f.debug_struct("EndpointConfig")
+            .field("endpoint", &self.endpoint.redacted())
+            .field("name", &self.name)
+            .field("auth", &self.auth)
+            .field("headers", &headers)
+            .finish()
+    }
+}
+
+impl From<Url> for RpcEndpoint {
+    fn from(url: Url) -> Self {
+        match url.scheme() {
+            "ws" | "wss" => RpcEndpoint::Ws(url),
+            "ipc" | "file" => RpcEndpoint::Ipc(PathBuf::from(url.path())),
+            _ => RpcEndpoint::Http(url),
+        }
+    }
+}
+
+impl FromStr for RpcEndpoint {
+    type Err = IndexerError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if let Some(path) = s
+            .strip_prefix("ipc://")
+            .or_else(|| s.strip_prefix("file://"))
+        {
+            return Ok(RpcEndpoint::Ipc(PathBuf::from(path)));
+        }
+        match s.parse::<Url>() {
+            Ok(url) if matches!(url.scheme(), "http" | "https" | "ws" | "wss") => Ok(url.into()),
+            Ok(url) => Err(IndexerError::Config(format!(
+                "unsupported RPC scheme `{}` in {s}",
+                url.scheme()
+            ))),
+            // no scheme: treat as an IPC socket path
+            Err(_) if s.starts_with('/') || s.starts_with('.') || s.ends_with(".ipc") => {
+                Ok(RpcEndpoint::Ipc(PathBuf::from(s)))
+            }
+            Err(e) => Err(IndexerError::Config(format!(
+                "invalid RPC endpoint {s}: {e}"
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for RpcEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.redacted())
+    }
+}
 
 pub fn build_rpc_clients(urls: Vec<Url>) -> Vec<RpcClient> {
     urls.into_iter()
@@ -23,3 +262,100 @@ pub fn build_rpc_clients_with_retry(
         })
         .collect()
 }
+
+/// Connect clients for any mix of HTTP, WebSocket and IPC endpoints.
+/// `retry` is `(max, backoff_ms, jitter_ms)` for the retry/backoff layer.
+pub async fn connect_rpc_clients(
+    endpoints: Vec<RpcEndpoint>,
+    retry: Option<(u32, u64, u64)>,
+) -> Result<Vec<RpcClient>> {
+    let configs = endpoints.into_iter().map(EndpointConfig::from).collect();
+    connect_endpoints(configs, retry).await
+}
+
+/// Like `connect_rpc_clients`, with per-endpoint auth and headers.
+pub async fn connect_endpoints(
+    endpoints: Vec<EndpointConfig>,
+    retry: Option<(u32, u64, u64)>,
+) -> Result<Vec<RpcClient>> {
+    let mut clients = Vec::with_capacity(endpoints.len());
+    for (i, cfg) in endpoints.iter().enumerate() {
+        clients.push(connect_rpc_client(cfg, retry, i).await?);
+    }
+    Ok(clients)
+}
+
+/// HTTP client for `cfg`, built without connecting.
+pub(crate) fn http_rpc_client(
+    cfg: &EndpointConfig,
+    retry: Option<(u32, u64, u64)>,
+) -> Result<RpcClient> {
+    let RpcEndpoint::Http(url) = &cfg.endpoint else {
+        return Err(IndexerError::Config(format!(
+            "{} needs a live connection; use EngineBuilder::connect()",
+            cfg.display_name()
+        )));
+    };
+    let retry = retry.map(|(m, b, j)| RetryBackoffLayer::new(m, b, j));
+    if cfg.auth.is_none() && cfg.headers.is_empty() {
+        return Ok(match retry {
+            None => RpcClient::builder().http(url.clone()),
+            Some(layer) => RpcClient::builder().layer(layer).http(url.clone()),
+        });
+    }
+    let http = reqwest::Client::builder()
+        .default_headers(cfg.header_map()?)
+        .build()
+        .map_err(|e| IndexerError::Config(format!("{}: {e}", cfg.display_name())))?;
+    Ok(match retry {
+        None => RpcClient::builder().http_with_client(http, url.clone()),
+        Some(layer) => RpcClient::builder()
+            .layer(layer)
+            .http_with_client(http, url.clone()),
+    })
+}
+
+/// Connection failures are reported as transport errors of endpoint `idx`.
+async fn connect_rpc_client(
+    cfg: &EndpointConfig,
+    retry: Option<(u32, u64, u64)>,
+    idx: usize,
+) -> Result<RpcClient> {
+    if !cfg.headers.is_empty() && !cfg.endpoint.is_http() {
+        return Err(IndexerError::Config(format!(
+            "{}: custom headers are only supported for HTTP endpoints",
+            cfg.display_name()
+        )));
+    }
+    if cfg.auth.is_some() && matches!(cfg.endpoint, RpcEndpoint::Ipc(_)) {
+        return Err(IndexerError::Config(format!(
+            "{}: IPC endpoints take no auth",
+            cfg.display_name()
+        )));
+    }
+    let connected = |e| IndexerError::from_transport(e, idx);
+    let auth = cfg.auth.as_ref().map(RpcAuth::authorization);
+    let retry_layer = retry.map(|(m, b, j)| RetryBackoffLayer::new(m, b, j));
+    let client = match (&cfg.endpoint, retry_layer) {
+        (RpcEndpoint::Http(_), _) => http_rpc_client(cfg, retry)?,
+        (RpcEndpoint::Ws(url), None) => RpcClient::builder()
+            .ws(WsConnect::new(url.as_str()).with_auth_opt(auth))
+            .await
+            .map_err(connected)?,
+        (RpcEndpoint::Ws(url), Some(layer)) => RpcClient::builder()
+            .layer(layer)
+            .ws(WsConnect::new(url.as_str()).with_auth_opt(auth))
+            .await
+            .map_err(connected)?,
+        (RpcEndpoint::Ipc(path), None) => RpcClient::builder()
+            .ipc(IpcConnect::new(path.clone()))
+            .await
+            .map_err(connected)?,
+        (RpcEndpoint::Ipc(path), Some(layer)) => RpcClient::builder()
+            .layer(layer)
+            .ipc(IpcConnect::new(path.clone()))
+            .await
+            .map_err(connected)?,
+    };
+    Ok(client)
+}
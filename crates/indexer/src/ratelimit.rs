@@ -0,0 +1,137 @@
+//! Token-bucket request budgets per endpoint.
+//! Each method draws a configurable cost (requests or provider compute units)
+//! from its endpoint's bucket before it is sent.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    /// Sustained budget, in cost units per second.
+    pub units_per_sec: f64,
+    /// Bucket size: how many units may be spent in a burst.
+    pub burst: f64,
+}
+
+impl RateLimit {
+    /// `units` per second with a one-second burst.
+    pub fn per_second(units: f64) -> Self {
+        Self {
+            units_per_sec: units,
+            burst: units,
+        }
+    }
+}
+
+pub(crate) struct TokenBucket {
+    limit: RateLimit,
+    state: Mutex<(f64, Instant)>, // (tokens, last refill)
+}
+
+impl TokenBucket {
+    pub(crate) fn new(limit: RateLimit) -> Self {
+        let limit = RateLimit {
+            units_per_sec: limit.units_per_sec.max(f64::EPSILON),
+            burst: limit.burst.max(1.0),
+        };
+        Self {
+            limit,
+            state: Mutex::new((limit.burst, Instant::now())),
+        }
+    }
+
+    fn refill(&self, st: &mut (f64, Instant)) {
+        let now = Instant::now();
+        let earned = now.duration_since(st.1).as_secs_f64() * self.limit.units_per_sec;
+        st.0 = (st.0 + earned).min(self.limit.burst);
+        st.1 = now;
+    }
+
+    /// Can `cost` be spent right now without waiting?
+    pub(crate) fn ready(&self, cost: f64) -> bool {
+        let mut st = self.state.lock().unwrap();
+        self.refill(&mut st);
+        st.0 >= self.need(cost)
+    }
+
+    // a cost above the burst could never be covered; wait for a full bucket
+    fn need(&self, cost: f64) -> f64 {
+        cost.min(self.limit.burst)
+    }
+
+    /// Spend `cost`, waiting until the bucket can cover it.
+    /// Tokens are only taken once they are there, so dropping the future
+    /// while it waits leaves the bucket untouched.
+    pub(crate) async fn acquire(&self, cost: f64) {
+        loop {
+            let wait = {
+                let mut st = self.state.lock().unwrap();
+                self.refill(&mut st);
+                let need = self.need(cost);
+                if st.0 >= need {
+                    st.0 -= cost;
+                    return;
+                }
+                (need - st.0) / self.limit.units_per_sec
+            };
+            tokio::time::sleep(Duration::from_secs_f64(wait)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::methods::eth::get_block_by_number::work_one;
+    use crate::{EngineBuilder, MockRpc};
+    use alloy::rpc::types::eth::BlockNumberOrTag;
+
+    #[tokio::test]
+    async fn waits_once_burst_is_spent() {
+        let bucket = TokenBucket::new(RateLimit {
+            units_per_sec: 20.0,
+            burst: 2.0,
+        });
+        let started = Instant::now();
+        bucket.acquire(1.0).await;
+        bucket.acquire(1.0).await;
+        assert!(started.elapsed() < Duration::from_millis(20));
+        assert!(!bucket.ready(1.0));
+        bucket.acquire(1.0).await;
+        assert!(started.elapsed() >= Duration::from_millis(40));
+    }
+
+    #[tokio::test]
+    async fn cancelled_acquire_takes_nothing() {
+        let bucket = TokenBucket::new(RateLimit::per_second(10.0));
+        bucket.acquire(10.0).await;
+        let waiting = tokio::time::timeout(Duration::from_millis(20), bucket.acquire(10.0));
+        assert!(waiting.await.is_err());
+        tokio::time::sleep(Duration::from_millis(1_000)).await;
+        assert!(bucket.ready(10.0));
+    }
+
+    #[tokio::test]
+    async fn method_costs_draw_from_the_endpoint_budget() {
+        let run = |cost: f64| async move {
+            let mock = MockRpc::new().chain(0, 9, 1_000, 12);
+            let idx = EngineBuilder::new()
+                .rate_limit(RateLimit {
+                    units_per_sec: 50.0,
+                    burst: 10.0,
+                })
+                .method_cost("eth_getBlockByNumber", cost)
+                .build_with_clients(vec![mock.client()]);
+            let started = Instant::now();
+            for n in 0..3 {
+                let item = work_one(BlockNumberOrTag::Number(n), false).unwrap();
+                idx.run_once(item).await.unwrap();
+            }
+            started.elapsed()
+        };
+        // default cost of 1 fits the burst
+        assert!(run(1.0).await < Duration::from_millis(100));
+        // 10 units each: the 2nd and 3rd call wait 200ms apiece
+        assert!(run(10.0).await >= Duration::from_millis(350));
+    }
+}
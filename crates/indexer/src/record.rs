@@ -0,0 +1,269 @@
+//! Record and replay RPC traffic. A `Recorder` set on the pool writes every
+//! upstream call as one NDJSON line (method, params, answer, endpoint,
+//! latency), plus the answers it gave without one (cache hits, coalesced
+//! calls); `replay()` serves such a file back offline, so a scan that went
+//! wrong can be re-run against exactly the data it saw.
+
+#[cfg(any(test, feature = "mock"))]
+use crate::{
+    cache::cache_key,
+    mock::{MockError, MockRpc},
+};
+use crate::{error::IndexerError, providers::redact_urls};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+#[cfg(any(test, feature = "mock"))]
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+#[cfg(any(test, feature = "mock"))]
+use std::io::{BufRead, BufReader};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// One line of a recording.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedCall {
+    pub method: String,
+    pub params: Vec<Value>,
+    /// The answer, `null` included; absent if the call failed.
+    #[serde(
+        default,
+        deserialize_with = "present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RecordedError>,
+    /// Display name of the endpoint (never a raw URL) and its index in the pool.
+    pub endpoint: String,
+    pub endpoint_index: usize,
+    pub latency_ms: f64,
+    /// Set if no endpoint was asked; `endpoint` then names the source.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub served_from: Option<Served>,
+}
+
+/// Where an answer that did not go upstream came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Served {
+    /// The response cache.
+    Cache,
+    /// An identical call in flight at the same time (its own line has the
+    /// endpoint).
+    Coalesced,
+}
+
+impl Served {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Served::Cache => "cache",
+            Served::Coalesced => "coalesced",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordedError {
+    /// JSON-RPC error object.
+    Rpc { code: i64, message: String },
+    /// No JSON-RPC answer: timeout, connection, HTTP status.
+    Transport { message: String },
+}
+
+// `"result": null` is an answer, unlike a missing `result`
+fn present<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Value>, D::Error> {
+    Value::deserialize(d).map(Some)
+}
+
+impl RecordedCall {
+    pub(crate) fn new(
+        method: &str,
+        params: &[Value],
+        res: &Result<Value, IndexerError>,
+        endpoint: (usize, &str),
+        latency: Duration,
+    ) -> Self {
+        let (result, error) = match res {
+            Ok(v) => (Some(v.clone()), None),
+            Err(IndexerError::Rpc { code, message, .. }) => (
+                None,
+                Some(RecordedError::Rpc {
+                    code: *code,
+                    message: message.clone(),
+                }),
+            ),
+            Err(IndexerError::Transport { source, .. }) => (
+                None,
+                Some(RecordedError::Transport {
+                    message: redact_urls(&source.to_string()),
+                }),
+            ),
+            Err(IndexerError::Timeout { after, .. }) => (
+                None,
+                Some(RecordedError::Transport {
+                    message: format!("timed out after {after:?}"),
+                }),
+            ),
+            Err(e) => (
+                None,
+                Some(RecordedError::Transport {
+                    message: e.to_string(),
+                }),
+            ),
+        };
+        RecordedCall {
+            method: method.to_string(),
+            params: params.to_vec(),
+            result,
+            error,
+            endpoint: endpoint.1.to_string(),
+            endpoint_index: endpoint.0,
+            latency_ms: latency.as_secs_f64() * 1000.0,
+            served_from: None,
+        }
+    }
+
+    /// An answer given without asking an endpoint.
+    pub(crate) fn served(
+        method: &str,
+        params: &[Value],
+        res: &Result<Value, IndexerError>,
+        from: Served,
+    ) -> Self {
+        RecordedCall {
+            served_from: Some(from),
+            ..RecordedCall::new(method, params, res, (0, from.as_str()), Duration::ZERO)
+        }
+    }
+}
+
+/// NDJSON sink for `RecordedCall`s, shared by all endpoints of a pool.
+pub struct Recorder {
+    out: Mutex<BufWriter<File>>,
+}
+
+impl Recorder {
+    /// Start a new recording at `path` (an existing file is replaced).
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, IndexerError> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .map_err(|e| IndexerError::Io(format!("creating {}: {e}", path.display())))?;
+        Ok(Self {
+            out: Mutex::new(BufWriter::new(file)),
+        })
+    }
+
+    /// Append one call; lines are flushed right away so a crashed run
+    /// leaves a usable recording. Write errors are logged, not returned.
+    pub(crate) fn write(&self, call: &RecordedCall) {
+        let mut out = self.out.lock().unwrap();
+        let res = serde_json::to_writer(&mut *out, call)
+            .map_err(std::io::Error::from)
+            .and_then(|()| out.write_all(b"\n"))
+            .and_then(|()| out.flush());
+        if let Err(e) = res {
+            tracing::warn!("recording {}: {e}", call.method);
+        }
+    }
+}
+
+/// A backend answering from the recording at `path`, and the number of
+/// endpoints the recording used. Identical calls get their recorded answers
+/// in order, the last one repeating, whichever client asks; calls missing
+/// from the recording fail with a JSON-RPC error saying so.
+#[cfg(any(test, feature = "mock"))]
+pub fn replay(path: impl AsRef<Path>) -> Result<(MockRpc, usize), IndexerError> {
+    let path = path.as_ref();
+    let failed = |e: &dyn std::fmt::Display| IndexerError::Io(format!("{}: {e}", path.display()));
+    let file = File::open(path).map_err(|e| failed(&e))?;
+    let mut answers: HashMap<String, VecDeque<Result<Value, MockError>>> = HashMap::new();
+    let mut endpoints = 1;
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| failed(&e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let call: RecordedCall = serde_json::from_str(&line)
+            .map_err(|e| failed(&format_args!("line {}: {e}", i + 1)))?;
+        match call.served_from {
+            // the leading call's line already holds this answer
+            Some(Served::Coalesced) => continue,
+            Some(Served::Cache) => {}
+            None => endpoints = endpoints.max(call.endpoint_index + 1),
+        }
+        let answer = match (call.result, call.error) {
+            (Some(v), _) => Ok(v),
+            (None, Some(RecordedError::Rpc { code, message })) => {
+                Err(MockError::rpc(code, message))
+            }
+            (None, Some(RecordedError::Transport { message })) => {
+                Err(MockError::Transport(message))
+            }
+            (None, None) => Ok(Value::Null),
+        };
+        answers
+            .entry(cache_key(&call.method, &call.params))
+            .or_default()
+            .push_back(answer);
+    }
+
+    let answers = Mutex::new(answers);
+    let backend = MockRpc::new().respond_any(move |method, params| {
+        let mut answers = answers.lock().unwrap();
+        match answers.get_mut(&cache_key(method, params)) {
+            Some(queue) if queue.len() > 1 => queue.pop_front().expect("non-empty queue"),
+            Some(queue) => queue.front().cloned().expect("non-empty queue"),
+            None => Err(MockError::rpc(
+                -32000,
+                format!("not in the recording: {method} {}", Value::from(params)),
+            )),
+        }
+    });
+    Ok((backend, endpoints))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockByNumberBuilder, DiskCache, EngineBuilder};
+    use futures::StreamExt;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn cache_hits_are_recorded_and_replayed() {
+        let dir = std::env::temp_dir().join(format!("indexer-record-test-{}", std::process::id()));
+        let cache = Arc::new(DiskCache::open(dir.join("cache"), 1 << 20).unwrap());
+        let mock = MockRpc::new().chain(0, 9, 1_000, 12);
+        let plan = BlockByNumberBuilder::new().range(0, 4).plan().unwrap();
+        let warm = EngineBuilder::new()
+            .cache(cache.clone())
+            .build_with_clients(vec![mock.client()]);
+        let _: Vec<_> = warm.run_plan(&plan).unwrap().collect().await;
+
+        let path = dir.join("calls.ndjson");
+        let recorded = EngineBuilder::new()
+            .cache(cache)
+            .record(Arc::new(Recorder::create(&path).unwrap()))
+            .build_with_clients(vec![mock.client()]);
+        let _: Vec<_> = recorded.run_plan(&plan).unwrap().collect().await;
+        let lines = std::fs::read_to_string(&path).unwrap();
+        let calls: Vec<RecordedCall> = lines
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        let hits = calls
+            .iter()
+            .filter(|c| c.served_from == Some(Served::Cache))
+            .count();
+        assert_eq!(hits, 5);
+
+        let replayed = EngineBuilder::new().replay(&path).unwrap();
+        let out: Vec<_> = replayed.run_plan(&plan).unwrap().collect().await;
+        assert!(out.iter().all(|r| matches!(r, Ok((_, Some(_))))), "{out:?}");
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
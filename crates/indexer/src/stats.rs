@@ -0,0 +1,450 @@
+//! Per-endpoint request statistics: totals and, per method, latency
+//! histograms, errors by class and in-flight counts.
+
+use crate::archive::is_missing_state_error;
+use crate::breaker::{CircuitBreaker, CircuitState};
+use crate::error::IndexerError;
+use alloy::transports::{RpcError, TransportErrorKind};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+};
+use std::time::{Duration, Instant};
+
+const BUCKETS: usize = 64;
+// bucket i counts latencies up to GROWTH^i ms (~25% resolution, up to ~20 min)
+const GROWTH: f64 = 1.25;
+// exported buckets: every 4th bound, 1ms .. ~11min in ~2.4x steps
+const EXPORT_STEP: usize = 4;
+
+struct Histogram {
+    counts: [AtomicU64; BUCKETS],
+    total: AtomicU64,
+    sum_us: AtomicU64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            counts: std::array::from_fn(|_| AtomicU64::new(0)),
+            total: AtomicU64::new(0),
+            sum_us: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Histogram {
+    fn record(&self, dur: Duration) {
+        let ms = dur.as_secs_f64() * 1000.0;
+        let i = if ms <= 1.0 {
+            0
+        } else {
+            ((ms.ln() / GROWTH.ln()).ceil() as usize).min(BUCKETS - 1)
+        };
+        self.counts[i].fetch_add(1, Ordering::Relaxed);
+        self.total.fetch_add(1, Ordering::Relaxed);
+        self.sum_us
+            .fetch_add(dur.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn mean_ms(&self) -> f64 {
+        let n = self.total.load(Ordering::Relaxed);
+        if n == 0 {
+            return 0.0;
+        }
+        self.sum_us.load(Ordering::Relaxed) as f64 / 1000.0 / n as f64
+    }
+
+    /// Upper bound (ms) of the bucket holding quantile `q`.
+    fn quantile(&self, q: f64) -> f64 {
+        let n = self.total.load(Ordering::Relaxed);
+        if n == 0 {
+            return 0.0;
+        }
+        let rank = ((q * n as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, c) in self.counts.iter().enumerate() {
+            seen += c.load(Ordering::Relaxed);
+            if seen >= rank {
+                return GROWTH.powi(i as i32);
+            }
+        }
+        GROWTH.powi(BUCKETS as i32 - 1)
+    }
+
+    fn cumulative(&self) -> LatencyHistogram {
+        let mut seen = 0;
+        let mut buckets = Vec::with_capacity(BUCKETS / EXPORT_STEP);
+        for (i, c) in self.counts.iter().enumerate().take(BUCKETS - 1) {
+            seen += c.load(Ordering::Relaxed);
+            if i % EXPORT_STEP == 0 {
+                buckets.push((GROWTH.powi(i as i32) / 1000.0, seen));
+            }
+        }
+        LatencyHistogram {
+            buckets,
+            sum_secs: self.sum_us.load(Ordering::Relaxed) as f64 / 1e6,
+            count: self.total.load(Ordering::Relaxed),
+        }
+    }
+
+    fn snapshot(&self) -> LatencyStats {
+        LatencyStats {
+            mean_ms: self.mean_ms(),
+            p50_ms: self.quantile(0.50),
+            p95_ms: self.quantile(0.95),
+            p99_ms: self.quantile(0.99),
+        }
+    }
+}
+
+/// What kind of failure a request ended with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorClass {
+    Timeout,
+    /// Non-2xx HTTP status.
+    Http(u16),
+    /// JSON-RPC error response code.
+    Rpc(i64),
+    /// Response did not decode / validate.
+    Decode,
+    /// The endpoint no longer has the state of the requested block (pruned node).
+    MissingState,
+    /// Anything else (connection refused, closed socket, ...).
+    Other,
+}
+
+impl ErrorClass {
+    pub fn of(e: &IndexerError) -> Self {
+        if is_missing_state_error(e) {
+            return ErrorClass::MissingState;
+        }
+        match e {
+            IndexerError::Rpc { code, .. } => ErrorClass::Rpc(*code),
+            IndexerError::Decode { .. } => ErrorClass::Decode,
+            IndexerError::Timeout { .. } => ErrorClass::Timeout,
+            IndexerError::Transport { source, .. } => match &**source {
+                RpcError::Transport(TransportErrorKind::HttpError(h)) => ErrorClass::Http(h.status),
+                RpcError::DeserError { .. } | RpcError::SerError(_) => ErrorClass::Decode,
+                e if is_timeout(e) => ErrorClass::Timeout,
+                _ => ErrorClass::Other,
+            },
+            _ => ErrorClass::Other,
+        }
+    }
+}
+
+// the HTTP client reports timeouts somewhere down the source chain
+fn is_timeout(e: &(dyn std::error::Error + 'static)) -> bool {
+    let mut cur = Some(e);
+    while let Some(e) = cur {
+        let s = e.to_string().to_ascii_lowercase();
+        if s.contains("timed out") || s.contains("timeout") {
+            return true;
+        }
+        cur = e.source();
+    }
+    false
+}
+
+#[derive(Default)]
+struct ErrorCounts {
+    timeout: AtomicU64,
+    decode: AtomicU64,
+    missing_state: AtomicU64,
+    other: AtomicU64,
+    http: Mutex<BTreeMap<u16, u64>>,
+    rpc: Mutex<BTreeMap<i64, u64>>,
+}
+
+impl ErrorCounts {
+    fn add(&self, class: ErrorClass) {
+        match class {
+            ErrorClass::Timeout => {
+                self.timeout.fetch_add(1, Ordering::Relaxed);
+            }
+            ErrorClass::Decode => {
+                self.decode.fetch_add(1, Ordering::Relaxed);
+            }
+            ErrorClass::MissingState => {
+                self.missing_state.fetch_add(1, Ordering::Relaxed);
+            }
+            ErrorClass::Other => {
+                self.other.fetch_add(1, Ordering::Relaxed);
+            }
+            ErrorClass::Http(status) => *self.http.lock().unwrap().entry(status).or_default() += 1,
+            ErrorClass::Rpc(code) => *self.rpc.lock().unwrap().entry(code).or_default() += 1,
+        }
+    }
+
+    fn snapshot(&self) -> ErrorStats {
+        ErrorStats {
+            timeout: self.timeout.load(Ordering::Relaxed),
+            http: self.http.lock().unwrap().clone(),
+            rpc: self.rpc.lock().unwrap().clone(),
+            decode: self.decode.load(Ordering::Relaxed),
+            missing_state: self.missing_state.load(Ordering::Relaxed),
+            other: self.other.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    requests: AtomicU64,
+    successes: AtomicU64,
+    in_flight: AtomicUsize,
+    latency: Histogram,
+    errors: ErrorCounts,
+}
+
+impl Counters {
+    fn record(&self, outcome: Result<(), ErrorClass>, dur: Duration) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        match outcome {
+            Ok(()) => {
+                self.successes.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(class) => self.errors.add(class),
+        }
+        self.latency.record(dur);
+    }
+}
+
+#[derive(Default)]
+pub struct RpcStats {
+    all: Counters,
+    methods: Mutex<HashMap<&'static str, Arc<Counters>>>,
+    pub(crate) breaker: CircuitBreaker,
+}
+
+impl RpcStats {
+    /// Start timing a request; it counts as in flight until the guard drops.
+    pub(crate) fn begin(&self, method: &'static str) -> Call<'_> {
+        let per_method = self.counters(method);
+        self.all.in_flight.fetch_add(1, Ordering::Relaxed);
+        per_method.in_flight.fetch_add(1, Ordering::Relaxed);
+        Call {
+            stats: self,
+            method: per_method,
+            t0: Instant::now(),
+        }
+    }
+
+    /// Count a request timed elsewhere. It is filed under the method
+    /// `"unknown"`, and failures count as `ErrorClass::Other`.
+    pub fn record(&self, ok: bool, dur: Duration) {
+        let outcome = if ok { Ok(()) } else { Err(ErrorClass::Other) };
+        self.all.record(outcome, dur);
+        self.counters("unknown").record(outcome, dur);
+    }
+
+    fn counters(&self, method: &'static str) -> Arc<Counters> {
+        self.methods
+            .lock()
+            .unwrap()
+            .entry(method)
+            .or_default()
+            .clone()
+    }
+
+    /// (requests, successes, mean latency ms) over all methods.
+    pub fn snapshot(&self) -> (u64, u64, f64) {
+        (
+            self.all.requests.load(Ordering::Relaxed),
+            self.all.successes.load(Ordering::Relaxed),
+            self.all.latency.mean_ms(),
+        )
+    }
+
+    pub fn circuit(&self) -> CircuitState {
+        self.breaker.state()
+    }
+
+    /// Full breakdown: totals, error classes and per-method latency.
+    pub fn detailed(&self) -> EndpointStats {
+        let mut methods: Vec<MethodStats> = self
+            .methods
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(m, c)| MethodStats {
+                method: (*m).to_string(),
+                requests: c.requests.load(Ordering::Relaxed),
+                successes: c.successes.load(Ordering::Relaxed),
+                in_flight: c.in_flight.load(Ordering::Relaxed),
+                latency: c.latency.snapshot(),
+                histogram: c.latency.cumulative(),
+                errors: c.errors.snapshot(),
+            })
+            .collect();
+        methods.sort_by(|a, b| a.method.cmp(&b.method));
+        EndpointStats {
+            requests: self.all.requests.load(Ordering::Relaxed),
+            successes: self.all.successes.load(Ordering::Relaxed),
+            in_flight: self.all.in_flight.load(Ordering::Relaxed),
+            latency: self.all.latency.snapshot(),
+            errors: self.all.errors.snapshot(),
+            circuit: self.circuit(),
+            methods,
+        }
+    }
+}
+
+/// A request being timed (see `RpcStats::begin`).
+pub(crate) struct Call<'a> {
+    stats: &'a RpcStats,
+    method: Arc<Counters>,
+    t0: Instant,
+}
+
+impl Call<'_> {
+    pub(crate) fn end<T>(self, res: &Result<T, IndexerError>) {
+        let outcome = res.as_ref().map(|_| ()).map_err(ErrorClass::of);
+        let dur = self.t0.elapsed();
+        self.stats.all.record(outcome, dur);
+        self.method.record(outcome, dur);
+    }
+}
+
+impl Drop for Call<'_> {
+    fn drop(&mut self) {
+        self.stats.all.in_flight.fetch_sub(1, Ordering::Relaxed);
+        self.method.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct LatencyStats {
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+}
+
+/// Cumulative latency counts, e.g. for a Prometheus histogram.
+#[derive(Clone, Debug, Default)]
+pub struct LatencyHistogram {
+    /// (upper bound in seconds, requests that took at most that), ascending.
+    pub buckets: Vec<(f64, u64)>,
+    pub sum_secs: f64,
+    pub count: u64,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ErrorStats {
+    pub timeout: u64,
+    /// HTTP status -> count
+    pub http: BTreeMap<u16, u64>,
+    /// JSON-RPC error code -> count
+    pub rpc: BTreeMap<i64, u64>,
+    pub decode: u64,
+    pub missing_state: u64,
+    pub other: u64,
+}
+
+impl ErrorStats {
+    pub fn total(&self) -> u64 {
+        self.timeout
+            + self.http.values().sum::<u64>()
+            + self.rpc.values().sum::<u64>()
+            + self.decode
+            + self.missing_state
+            + self.other
+    }
+}
+
+impl fmt::Display for ErrorStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if self.timeout > 0 {
+            parts.push(format!("timeout x{}", self.timeout));
+        }
+        parts.extend(self.http.iter().map(|(s, n)| format!("http {s} x{n}")));
+        parts.extend(self.rpc.iter().map(|(c, n)| format!("rpc {c} x{n}")));
+        if self.decode > 0 {
+            parts.push(format!("decode x{}", self.decode));
+        }
+        if self.missing_state > 0 {
+            parts.push(format!("missing state x{}", self.missing_state));
+        }
+        if self.other > 0 {
+            parts.push(format!("other x{}", self.other));
+        }
+        if parts.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", parts.join(", "))
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct MethodStats {
+    pub method: String,
+    pub requests: u64,
+    pub successes: u64,
+    pub in_flight: usize,
+    pub latency: LatencyStats,
+    #[serde(skip)]
+    pub histogram: LatencyHistogram,
+    pub errors: ErrorStats,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct EndpointStats {
+    pub requests: u64,
+    pub successes: u64,
+    pub in_flight: usize,
+    pub latency: LatencyStats,
+    pub errors: ErrorStats,
+    pub circuit: CircuitState,
+    /// Sorted by method name.
+    pub methods: Vec<MethodStats>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_counts_under_unknown() {
+        let stats = RpcStats::default();
+        stats.record(true, Duration::from_millis(10));
+        stats.record(false, Duration::from_millis(30));
+        let (requests, successes, mean_ms) = stats.snapshot();
+        assert_eq!((requests, successes), (2, 1));
+        assert!((mean_ms - 20.0).abs() < 1.0);
+        let d = stats.detailed();
+        assert_eq!(d.methods.len(), 1);
+        assert_eq!(d.methods[0].method, "unknown");
+        assert_eq!(d.methods[0].errors.other, 1);
+    }
+
+    #[test]
+    fn exported_buckets_are_cumulative() {
+        let h = Histogram::default();
+        for ms in [0.5, 3.0, 3.0, 40.0, 2_000.0, 3_600_000.0] {
+            h.record(Duration::from_secs_f64(ms / 1000.0));
+        }
+        let c = h.cumulative();
+        assert_eq!(c.count, 6);
+        assert!((c.sum_secs - 3_602.046_5).abs() < 1e-3, "{}", c.sum_secs);
+        assert!(
+            c.buckets
+                .windows(2)
+                .all(|w| w[0].0 < w[1].0 && w[0].1 <= w[1].1)
+        );
+        let at = |secs: f64| c.buckets.iter().rfind(|(le, _)| *le <= secs).unwrap().1;
+        assert_eq!(c.buckets[0], (0.001, 1));
+        assert_eq!(at(0.0035), 1);
+        assert_eq!(at(0.01), 3);
+        assert_eq!(at(0.1), 4);
+        assert_eq!(at(10.0), 5);
+        // the hour-long call only shows up in +Inf (= count)
+        assert_eq!(c.buckets.last().unwrap().1, 5);
+    }
+}